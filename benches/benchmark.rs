@@ -1,19 +1,95 @@
+use std::fs::File;
+use std::path::PathBuf;
 use std::time::Duration;
 
-use criterion::{criterion_group, criterion_main, Criterion};
-use rust_billion_row_challenge::process_file;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_billion_row_challenge::{generate, process_file, Processor};
 
-fn benchmark(c: &mut Criterion) {
-    let address = std::env::var("MEASUREMENTS_FILE").expect("No file specified");
+// Sizes of the files generated when no MEASUREMENTS_FILE is given
+const FIXTURE_ROWS: [u64; 3] = [1_000_000, 10_000_000, 100_000_000];
+const FIXTURE_SEED: u64 = 42;
+
+// A file of `rows` generated measurements in the temp dir, written by the first run and reused by
+// later ones, as the same seed always gives the same file
+fn fixture(rows: u64) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("brc_bench_{rows}_{FIXTURE_SEED}.txt"));
+    if !path.exists() {
+        // Moved into place once complete, so an interrupted run leaves no short file behind
+        let partial = path.with_extension("partial");
+        generate(rows, 413, FIXTURE_SEED, File::create(&partial).unwrap()).unwrap();
+        std::fs::rename(&partial, &path).unwrap();
+    }
+    path
+}
 
+fn benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("File Processing");
     group.sample_size(10);
     group.warm_up_time(Duration::from_secs(5));
     group.measurement_time(Duration::from_secs(100));
-    group.bench_function("process_file", |b| b.iter(|| process_file(&address)));
+    if let Ok(address) = std::env::var("MEASUREMENTS_FILE") {
+        group.bench_function("process_file", |b| b.iter(|| process_file(&address).unwrap()));
+    } else {
+        for rows in FIXTURE_ROWS {
+            let path = fixture(rows);
+            let address = path.to_str().unwrap();
+            group.throughput(Throughput::Elements(rows));
+            group.bench_with_input(BenchmarkId::new("process_file", rows), address, |b, address| b.iter(|| process_file(address).unwrap()));
+        }
+    }
+
+    group.finish();
+}
+
+// Generated in memory, so the hot-station cache can be compared with and without
+// `--features hot-cache` on inputs with and without runs of the same station
+fn locality(c: &mut Criterion) {
+    const LINES: usize = 2_000_000;
+    const STATIONS: usize = 400;
+    let line = |station: usize, seed: usize| format!("Station {station:03};{}.{}\n", seed % 99, seed % 10);
+    let clustered = (0..LINES).map(|i| line(i / 1000 % STATIONS, i)).collect::<String>();
+    let mut seed = 42usize;
+    let shuffled = (0..LINES).map(|i| {
+        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+        line((seed >> 33) % STATIONS, i)
+    }).collect::<String>();
+
+    let processor = Processor::builder().build().unwrap();
+    let mut group = c.benchmark_group("Station Locality");
+    group.sample_size(20);
+    group.bench_function("clustered", |b| b.iter(|| processor.process_reader(clustered.as_bytes()).unwrap()));
+    group.bench_function("shuffled", |b| b.iter(|| processor.process_reader(shuffled.as_bytes()).unwrap()));
+    group.finish();
+}
+
+// Each hasher set through the builder, on names of the challenge's lengths. fxhash and aHash join
+// under `--features fxhash,ahash`.
+fn hashers(c: &mut Criterion) {
+    const LINES: usize = 2_000_000;
+    let mut seed = 7usize;
+    let input = (0..LINES).map(|i| {
+        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+        format!("Weather station {};{}.{}\n", (seed >> 33) % 10_000, i % 99, i % 10)
+    }).collect::<String>();
 
+    let mut group = c.benchmark_group("Hashers");
+    group.sample_size(20);
+    let processor = Processor::builder().build().unwrap();
+    group.bench_function("default", |b| b.iter(|| processor.process_reader(input.as_bytes()).unwrap()));
+    let processor = Processor::builder().hasher(std::hash::RandomState::new()).build().unwrap();
+    group.bench_function("siphash", |b| b.iter(|| processor.process_reader(input.as_bytes()).unwrap()));
+    #[cfg(feature = "fxhash")]
+    {
+        let processor = Processor::builder().hasher(fxhash::FxBuildHasher::default()).build().unwrap();
+        group.bench_function("fxhash", |b| b.iter(|| processor.process_reader(input.as_bytes()).unwrap()));
+    }
+    #[cfg(feature = "ahash")]
+    {
+        let processor = Processor::builder().hasher(ahash::RandomState::new()).build().unwrap();
+        group.bench_function("ahash", |b| b.iter(|| processor.process_reader(input.as_bytes()).unwrap()));
+    }
     group.finish();
 }
 
-criterion_group!(benches, benchmark);
-criterion_main!(benches);
\ No newline at end of file
+criterion_group!(benches, benchmark, locality, hashers);
+criterion_main!(benches);
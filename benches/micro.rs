@@ -0,0 +1,53 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rust_billion_row_challenge::parse::{parse_i32, split_line, LineFormat};
+use rust_billion_row_challenge::Processor;
+
+const LINES: usize = 100_000;
+
+// Synthetic lines shaped like the challenge's: names of 3 to 26 bytes among a few hundred
+// stations, and values from -99.9 to 99.9 with one or two integer digits
+fn lines() -> String {
+    let mut seed = 42usize;
+    (0..LINES).map(|_| {
+        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+        let station = (seed >> 33) % 400;
+        let value = ((seed >> 17) % 1999) as i32 - 999;
+        let name = "Station".chars().cycle().take(3 + station % 24).collect::<String>();
+        let sign = if value < 0 { "-" } else { "" };
+        format!("{name} {station};{sign}{}.{}\n", value.abs() / 10, value.abs() % 10)
+    }).collect()
+}
+
+// The splitter and the parser on their own, over every line of a batch
+fn parser(c: &mut Criterion) {
+    let input = lines();
+    let values = input.lines().filter_map(|line| line.rsplit_once(';')).map(|(_, value)| value).collect::<Vec<_>>();
+    let lines = input.lines().collect::<Vec<_>>();
+    let format = LineFormat::default();
+
+    let mut group = c.benchmark_group("Hot Loop");
+    group.throughput(Throughput::Elements(LINES as u64));
+    group.bench_function("parse_i32", |b| b.iter(|| {
+        values.iter().map(|value| parse_i32(black_box(value)).unwrap_or(0) as i64).sum::<i64>()
+    }));
+    group.bench_function("split_line", |b| b.iter(|| {
+        lines.iter().filter_map(|line| split_line(black_box(line), format)).map(|(station, value)| station.len() + value.len()).sum::<usize>()
+    }));
+    group.finish();
+}
+
+// One batch on the calling thread, so only the parsing and the map lookups are timed
+fn batch(c: &mut Criterion) {
+    let input = lines();
+    let processor = Processor::builder().build().unwrap();
+
+    let mut group = c.benchmark_group("Batch");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("process_batch", |b| b.iter(|| processor.process_slice(black_box(input.as_bytes())).unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, parser, batch);
+criterion_main!(benches);
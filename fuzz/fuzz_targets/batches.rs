@@ -0,0 +1,30 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_billion_row_challenge::{LinePolicy, Processor, Utf8Policy};
+
+// The first four bytes size the batches and reads, the rest is the input. Streamed in many small
+// batches, whose remainders carry partial lines over, the input must aggregate exactly as it does
+// parsed whole as one batch.
+fuzz_target!(|data: &[u8]| {
+    let Some((&[batch_lines, capacity, threads, options], input)) = data.split_first_chunk::<4>() else {
+        return;
+    };
+    let utf8 = if options & 1 == 0 { Utf8Policy::Validate } else { Utf8Policy::Lossy };
+    let builder = || Processor::builder().line_policy(LinePolicy::Collect).utf8_policy(utf8);
+    let streamed = builder()
+        .batch_lines(batch_lines as usize % 16 + 1)
+        .buffer_capacity(capacity as usize + 1)
+        .avg_line_len((options >> 1) as usize % 32 + 1)
+        .threads(threads as usize % 4 + 1)
+        .build().unwrap()
+        .process_reader(input);
+    let whole = builder().build().unwrap().process_slice(input);
+    match (streamed, whole) {
+        (Ok(streamed), Ok(whole)) => {
+            assert_eq!(streamed.to_string(), whole.to_string());
+            assert_eq!(streamed.invalid_lines(), whole.invalid_lines());
+        }
+        (streamed, whole) => assert_eq!(streamed.is_ok(), whole.is_ok(), "{streamed:?} {whole:?}"),
+    }
+});
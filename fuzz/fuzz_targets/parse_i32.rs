@@ -0,0 +1,32 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_billion_row_challenge::parse::{parse_decimal, parse_i32, parse_wide_i32};
+
+// `-?d?d.d` in tenths, spelled out a character at a time
+fn by_hand(value: &str) -> Option<i32> {
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value),
+    };
+    let (integer, fraction) = digits.split_once('.')?;
+    let all_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    if !(1..=2).contains(&integer.len()) || fraction.len() != 1 || !all_digits(integer) || !all_digits(fraction) {
+        return None;
+    }
+    let tenths = integer.parse::<i32>().ok()? * 10 + fraction.parse::<i32>().ok()?;
+    Some(if negative { -tenths } else { tenths })
+}
+
+// The branch-light parser must agree with the plain one on anything, reading no further than the
+// value however short it is. The wider parsers only need to not panic.
+fuzz_target!(|data: &[u8]| {
+    let Ok(value) = std::str::from_utf8(data) else {
+        return;
+    };
+    assert_eq!(parse_i32(value), by_hand(value), "{value:?}");
+    for digits in 1..=8 {
+        let _ = parse_wide_i32(value, digits);
+        let _ = parse_decimal(value, digits, 3);
+    }
+});
@@ -0,0 +1,28 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_billion_row_challenge::parse::{split_line, FieldOrder, LineFormat};
+
+// The first byte picks the delimiter and field order, the rest is the line
+fuzz_target!(|data: &[u8]| {
+    let Some((&first, line)) = data.split_first() else {
+        return;
+    };
+    let order = if first & 0x80 == 0 { FieldOrder::StationFirst } else { FieldOrder::ValueFirst };
+    let (Some(format), Ok(line)) = (LineFormat::new((first & 0x7F) as char, order), std::str::from_utf8(line)) else {
+        return;
+    };
+    let delimiter = (first & 0x7F) as char;
+    match split_line(line, format) {
+        Some((station, value)) => {
+            assert_eq!(station.len() + value.len() + 1, line.len());
+            assert!(!value.contains(delimiter));
+            let (first_field, second_field) = match order {
+                FieldOrder::StationFirst => (station, value),
+                FieldOrder::ValueFirst => (value, station),
+            };
+            assert_eq!(format!("{first_field}{delimiter}{second_field}"), line);
+        }
+        None => assert!(!line.contains(delimiter)),
+    }
+});
@@ -0,0 +1,248 @@
+use super::{Columns, Data};
+
+/// Accumulates the values of one station. The parsing loop calls `new` for a station's first
+/// value and `update` for the rest, and `merge` combines the accumulators of the same station
+/// from different parts of the input. Values are in tenths of a degree.
+///
+/// `Data` is the default, giving the challenge's min/mean/max. Plug in another with
+/// `ProcessorBuilder::aggregator`.
+pub trait Aggregator: Send {
+    type Output;
+    /// Whether merging in any order gives the same result. The streaming backend then lets each
+    /// worker fold every batch it parses into one map, rather than allocating a map per batch.
+    const ORDER_INDEPENDENT: bool = false;
+
+    fn new(value: i32) -> Self;
+    fn update(&mut self, value: i32);
+    /// Folds in an accumulator of the same station. Batches are merged in input order, with
+    /// `other` coming after `self`.
+    fn merge(&mut self, other: Self);
+    fn finalize(&self) -> Self::Output;
+}
+
+impl Aggregator for Data {
+    type Output = Data;
+    const ORDER_INDEPENDENT: bool = true;
+
+    fn new(value: i32) -> Self {
+        Data { sum: value as i64, count: 1, min: value, max: value }
+    }
+    fn update(&mut self, value: i32) {
+        self.sum += value as i64;
+        self.count += 1;
+        if value < self.min {
+            self.min = value;
+        } else if value > self.max {
+            self.max = value;
+        }
+    }
+    fn merge(&mut self, other: Data) {
+        Data::merge(self, other)
+    }
+    fn finalize(&self) -> Data {
+        *self
+    }
+}
+
+/// `Data` plus the sum of squared values, adding the population standard deviation to the output
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Moments {
+    data: Data,
+    // In tenths squared, which overflows i64 after a few billion large values
+    sum_squares: i128,
+}
+impl Moments {
+    pub fn data(&self) -> &Data {
+        &self.data
+    }
+    // Exact in tenths squared until the final division
+    fn variance_tenths(&self) -> f64 {
+        let (count, sum) = (self.data.count as i128, self.data.sum as i128);
+        (count * self.sum_squares - sum * sum) as f64 / (count * count) as f64
+    }
+    pub fn variance(&self) -> f64 {
+        self.variance_tenths() / 100.0
+    }
+    pub fn std_dev(&self) -> f64 {
+        self.variance_tenths().sqrt() / 10.0
+    }
+}
+impl Aggregator for Moments {
+    type Output = Moments;
+    const ORDER_INDEPENDENT: bool = true;
+
+    fn new(value: i32) -> Self {
+        Moments { data: Data::new(value), sum_squares: value as i128 * value as i128 }
+    }
+    fn update(&mut self, value: i32) {
+        self.data.update(value);
+        self.sum_squares += value as i128 * value as i128;
+    }
+    fn merge(&mut self, other: Moments) {
+        self.data.merge(other.data);
+        self.sum_squares += other.sum_squares;
+    }
+    fn finalize(&self) -> Moments {
+        *self
+    }
+}
+impl Columns for Moments {
+    const EXTRA: &'static [&'static str] = &["stddev"];
+
+    fn data(&self) -> &Data {
+        &self.data
+    }
+    fn extra(&self) -> Vec<i64> {
+        // Rounded half up like the mean
+        vec![(self.variance_tenths().sqrt() + 0.5).floor() as i64]
+    }
+}
+
+// Values of the challenge's own range, -99.9 to 99.9, in tenths
+const BUCKET_OFFSET: i32 = 999;
+const BUCKETS: usize = 2 * BUCKET_OFFSET as usize + 1;
+
+/// `Data` plus an exact histogram of the values, adding the median and tail percentiles to the
+/// output. Values of the challenge's range are counted in one bucket per tenth, wider ones are
+/// kept as they are.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Percentiles {
+    data: Data,
+    buckets: Box<[u32]>,
+    outliers: Vec<i32>,
+}
+impl Percentiles {
+    pub fn data(&self) -> &Data {
+        &self.data
+    }
+    /// The nearest-rank percentile: the smallest value that at least `p` percent of the values
+    /// are less than or equal to
+    pub fn percentile(&self, p: f64) -> f64 {
+        self.percentile_tenths(p) as f64 / 10.0
+    }
+
+    fn percentile_tenths(&self, p: f64) -> i32 {
+        let rank = ((p / 100.0 * self.data.count as f64).ceil() as u64).clamp(1, self.data.count as u64);
+        let mut outliers = self.outliers.clone();
+        outliers.sort_unstable();
+        let split = outliers.partition_point(|&value| value < -BUCKET_OFFSET);
+        let (below, above) = outliers.split_at(split);
+        if rank <= below.len() as u64 {
+            return below[rank as usize - 1];
+        }
+        let mut seen = below.len() as u64;
+        for (index, &count) in self.buckets.iter().enumerate() {
+            seen += count as u64;
+            if seen >= rank {
+                return index as i32 - BUCKET_OFFSET;
+            }
+        }
+        above[(rank - seen) as usize - 1]
+    }
+    fn add(&mut self, value: i32) {
+        match usize::try_from(value + BUCKET_OFFSET) {
+            Ok(index) if index < BUCKETS => self.buckets[index] += 1,
+            _ => self.outliers.push(value),
+        }
+    }
+}
+impl Aggregator for Percentiles {
+    type Output = Percentiles;
+    // Outliers are kept in input order, but sorted before use
+    const ORDER_INDEPENDENT: bool = true;
+
+    fn new(value: i32) -> Self {
+        let mut percentiles = Percentiles { data: Data::new(value), buckets: vec![0; BUCKETS].into_boxed_slice(), outliers: Vec::new() };
+        percentiles.add(value);
+        percentiles
+    }
+    fn update(&mut self, value: i32) {
+        self.data.update(value);
+        self.add(value);
+    }
+    fn merge(&mut self, mut other: Percentiles) {
+        self.data.merge(other.data);
+        self.buckets.iter_mut().zip(other.buckets.iter()).for_each(|(count, other)| *count += other);
+        self.outliers.append(&mut other.outliers);
+    }
+    fn finalize(&self) -> Percentiles {
+        self.clone()
+    }
+}
+impl Columns for Percentiles {
+    const EXTRA: &'static [&'static str] = &["p50", "p95", "p99"];
+
+    fn data(&self) -> &Data {
+        &self.data
+    }
+    fn extra(&self) -> Vec<i64> {
+        [50.0, 95.0, 99.0].into_iter().map(|p| self.percentile_tenths(p) as i64).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Processor;
+
+    // Keeps every value, which shows whether merges preserve input order
+    struct Sequence(Vec<i32>);
+    impl Aggregator for Sequence {
+        type Output = usize;
+
+        fn new(value: i32) -> Self {
+            Sequence(vec![value])
+        }
+        fn update(&mut self, value: i32) {
+            self.0.push(value);
+        }
+        fn merge(&mut self, mut other: Self) {
+            self.0.append(&mut other.0);
+        }
+        fn finalize(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    #[test]
+    fn test_moments() {
+        let results = Processor::builder().aggregator::<Moments>().batch_lines(2).avg_line_len(1).build().unwrap()
+            .process_reader(&b"a;2.0\na;4.0\na;4.0\na;4.0\na;5.0\na;5.0\na;7.0\na;9.0\nb;-1.5\n"[..]).unwrap();
+        let a = results.get("a").unwrap();
+        assert_eq!((a.data().count(), a.data().mean(), a.std_dev(), a.variance()), (8, 5.0, 2.0, 4.0));
+        assert_eq!(results.get("b").unwrap().std_dev(), 0.0);
+        let mut output = Vec::new();
+        crate::output::write_results(&results, Default::default(), &mut output).unwrap();
+        assert_eq!(output, b"{a=2.0/5.0/9.0/2.0, b=-1.5/-1.5/-1.5/0.0}\n");
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let input = (1..=100).rev().map(|value| format!("a;{:.1}\n", value as f64 / 10.0)).collect::<String>() + "b;-1234.5\nb;0.0\nb;1234.5\n";
+        let processor = Processor::builder().aggregator::<Percentiles>().max_integer_digits(4).threads(3).batch_lines(7).avg_line_len(1).build().unwrap();
+        let results = processor.process_reader(input.as_bytes()).unwrap();
+        let a = results.get("a").unwrap();
+        assert_eq!([0.0, 1.0, 50.0, 95.0, 99.0, 100.0].map(|p| a.percentile(p)), [0.1, 0.1, 5.0, 9.5, 9.9, 10.0]);
+        let b = results.get("b").unwrap();
+        assert_eq!([0.0, 34.0, 50.0, 67.0, 100.0].map(|p| b.percentile(p)), [-1234.5, 0.0, 0.0, 1234.5, 1234.5]);
+        let mut output = Vec::new();
+        crate::output::write_results(&results, Default::default(), &mut output).unwrap();
+        assert_eq!(output, b"{a=0.1/5.1/10.0/5.0/9.5/9.9, b=-1234.5/0.0/1234.5/0.0/1234.5/1234.5}\n");
+    }
+
+    #[test]
+    fn test_custom_aggregator() {
+        let values = (-250..250).collect::<Vec<i32>>();
+        let input = values.iter().map(|value| format!("{};{:.1}\n", ["a", "b"][value.unsigned_abs() as usize % 2], *value as f64 / 10.0)).collect::<String>();
+        let processor = Processor::builder().aggregator::<Sequence>().threads(4).batch_lines(7).avg_line_len(1).build().unwrap();
+        let results = processor.process_reader(input.as_bytes()).unwrap();
+        let expected = |parity| values.iter().copied().filter(|value| value.unsigned_abs() % 2 == parity).collect::<Vec<_>>();
+        assert_eq!(results.get("a").unwrap().0, expected(0));
+        assert_eq!(results.get("b").unwrap().0, expected(1));
+        assert_eq!(results.finalized().collect::<Vec<_>>(), [("a", 250), ("b", 250)]);
+    }
+
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, UInt32Array};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use super::{Columns, Results};
+
+impl<A: Columns> Results<A> {
+    /// One row per station in station order, with the columns of `write_csv`: `station`, then
+    /// `min`, `mean`, `max` and any extra values as floats rounded as printed, then `count`
+    pub fn to_arrow(&self) -> Result<RecordBatch, ArrowError> {
+        let mut fields = vec![Field::new("station", DataType::Utf8, false)];
+        fields.extend(["min", "mean", "max"].iter().chain(A::EXTRA).map(|name| Field::new(*name, DataType::Float64, false)));
+        fields.push(Field::new("count", DataType::UInt32, false));
+
+        let stations = StringArray::from_iter_values(self.iter().map(|(station, _)| station));
+        let extra = self.iter().map(|(_, columns)| columns.extra()).collect::<Vec<_>>();
+        let tenths = |values: Vec<i64>| Arc::new(Float64Array::from_iter_values(values.into_iter().map(|value| value as f64 / 10.0))) as ArrayRef;
+        let mut columns = vec![Arc::new(stations) as ArrayRef];
+        columns.push(tenths(self.iter().map(|(_, columns)| columns.data().min as i64).collect()));
+        columns.push(tenths(self.iter().map(|(_, columns)| columns.data().mean_tenths()).collect()));
+        columns.push(tenths(self.iter().map(|(_, columns)| columns.data().max as i64).collect()));
+        columns.extend((0..A::EXTRA.len()).map(|index| tenths(extra.iter().map(|values| values[index]).collect())));
+        columns.push(Arc::new(UInt32Array::from_iter_values(self.iter().map(|(_, columns)| columns.data().count()))));
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, UInt32Type};
+    use crate::{Moments, Processor};
+
+    #[test]
+    fn test_to_arrow() {
+        let input = &b"Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n"[..];
+        let batch = Processor::builder().build().unwrap().process_reader(input).unwrap().to_arrow().unwrap();
+        let names = batch.schema().fields().iter().map(|field| field.name().clone()).collect::<Vec<_>>();
+        assert_eq!(names, ["station", "min", "mean", "max", "count"]);
+        let stations = batch.column(0).as_string::<i32>();
+        assert_eq!((stations.value(0), stations.value(1)), ("Bulawayo", "Hamburg"));
+        assert_eq!(batch.column(1).as_primitive::<Float64Type>().values(), &[8.9, -3.4]);
+        assert_eq!(batch.column(2).as_primitive::<Float64Type>().values(), &[8.9, 4.3]);
+        assert_eq!(batch.column(4).as_primitive::<UInt32Type>().values(), &[1, 2]);
+
+        let moments = Processor::builder().aggregator::<Moments>().build().unwrap().process_reader(input).unwrap().to_arrow().unwrap();
+        assert_eq!(moments.schema().field(4).name(), "stddev");
+        assert_eq!(moments.column(4).as_primitive::<Float64Type>().values(), &[0.0, 7.7]);
+    }
+
+}
@@ -0,0 +1,37 @@
+use std::hash::BuildHasher;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use super::{address_of, Aggregator, ProcessError, Processor, Results};
+
+impl<S: BuildHasher + Clone + Send + Sync + 'static, A: Aggregator + 'static> Processor<S, A> {
+    /// Aggregates the file like `process` without blocking the async executor. The run goes to
+    /// tokio's blocking threads, and the parsing itself still to the processor's own pool, so the
+    /// processor is shared through an `Arc` to outlive the caller's borrow.
+    pub async fn process_file_async(self: Arc<Self>, path: impl AsRef<Path>) -> Result<Results<A>, ProcessError> {
+        // Missing and unreadable files fail here, before a blocking thread is taken
+        tokio::fs::metadata(&path).await?;
+        let path = path.as_ref().to_path_buf();
+        tokio::task::spawn_blocking(move || self.process(address_of(&path)?))
+            .await
+            .map_err(io::Error::other)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_process_file_async() {
+        let path = std::env::temp_dir().join(format!("process_async_{}.txt", std::process::id()));
+        tokio::fs::write(&path, "Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n").await.unwrap();
+        let processor = Arc::new(Processor::builder().threads(2).build().unwrap());
+        let results = processor.clone().process_file_async(&path).await.unwrap();
+        assert_eq!(results.get("Hamburg").unwrap().to_string(), "-3.4/4.3/12.0");
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert!(matches!(processor.process_file_async(&path).await, Err(ProcessError::Io(_))));
+    }
+
+}
@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::process::{Command, ExitCode};
+use clap::Parser;
+use rust_billion_row_challenge::{parse_results, Processor, Summary};
+
+/// Runs this crate and an external 1BRC implementation over the same input and reports the
+/// first station where their results differ
+#[derive(Parser)]
+struct Args {
+    /// Measurements file given to both implementations
+    file: String,
+    /// External command to compare against. `{}` in its arguments is replaced by the input
+    /// path, otherwise the path is appended.
+    #[arg(last = true, required = true)]
+    command: Vec<String>,
+}
+
+// Prints values the way the challenge does, so that `12`, `12.0` and `-0.0`/`0.0` compare equal
+fn normalize(summary: &Summary) -> String {
+    let format = |value: f64| match format!("{value:.1}") {
+        zero if zero == "-0.0" => "0.0".to_string(),
+        value => value,
+    };
+    format!("{}/{}/{}", format(summary.min), format(summary.mean), format(summary.max))
+}
+
+fn external(args: &Args) -> io::Result<BTreeMap<String, Summary>> {
+    let (program, rest) = args.command.split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No command given"))?;
+    let mut command = Command::new(program);
+    if rest.iter().any(|arg| arg.contains("{}")) {
+        command.args(rest.iter().map(|arg| arg.replace("{}", &args.file)));
+    } else {
+        command.args(rest).arg(&args.file);
+    }
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("{program} exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    parse_results(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn run(args: Args) -> io::Result<ExitCode> {
+    let results = Processor::builder().build()?.process(&args.file)?;
+    let ours = results.iter()
+        .map(|(station, data)| (station.to_string(), data.to_string()))
+        .collect::<BTreeMap<_, _>>();
+    let theirs = external(&args)?.iter()
+        .map(|(station, summary)| (station.clone(), normalize(summary)))
+        .collect::<BTreeMap<_, _>>();
+
+    let stations = ours.keys().chain(theirs.keys()).collect::<std::collections::BTreeSet<_>>();
+    for station in stations {
+        match (ours.get(station), theirs.get(station)) {
+            (Some(a), Some(b)) if a == b => continue,
+            (a, b) => {
+                let missing = "missing".to_string();
+                println!("First divergence at {station:?}: ours {}, theirs {}", a.unwrap_or(&missing), b.unwrap_or(&missing));
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+    println!("Identical results for {} stations", ours.len());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
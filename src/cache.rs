@@ -0,0 +1,248 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use rayon::prelude::*;
+
+const PRIME_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+const PRIME_READ_SIZE: usize = 1024 * 1024;
+// Alignment of buffers, offsets and lengths that O_DIRECT asks for on common file systems
+const DIRECT_ALIGNMENT: usize = 4096;
+const DIRECT_READ_SIZE: usize = 4 * 1024 * 1024;
+
+/// Pulls the whole file into the page cache with a parallel read pass so that a
+/// following timed run measures parsing rather than disk throughput.
+pub fn prime_cache(address: &str) -> io::Result<()> {
+    let file = File::open(address)?;
+    let length = file.metadata()?.len();
+    advise_will_need(&file, 0, length);
+
+    let chunks = length.div_ceil(PRIME_CHUNK_SIZE);
+    (0..chunks).into_par_iter().try_for_each(|chunk| {
+        let start = chunk * PRIME_CHUNK_SIZE;
+        let mut file = File::open(address)?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut reader = file.take(PRIME_CHUNK_SIZE);
+        let mut buffer = vec![0; PRIME_READ_SIZE];
+        while reader.read(&mut buffer)? != 0 {}
+        Ok(())
+    })
+}
+
+/// Evicts the file from the page cache so the next run starts cold.
+///
+/// When running as root the system-wide `drop_caches` knob is used, otherwise only the
+/// pages belonging to this file are dropped through `POSIX_FADV_DONTNEED`. With the
+/// `no-unsafe` feature only the first of these is available.
+#[cfg(all(target_os = "linux", not(feature = "no-unsafe")))]
+pub fn drop_caches(address: &str) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = File::open(address)?;
+    // Flush dirty pages first, only clean pages can be evicted
+    // SAFETY: takes no arguments and cannot fail
+    unsafe { libc::sync() };
+    if std::fs::write("/proc/sys/vm/drop_caches", "1").is_ok() {
+        return Ok(());
+    }
+    // SAFETY: the descriptor belongs to `file`, which outlives the call
+    match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) } {
+        0 => Ok(()),
+        error => Err(io::Error::from_raw_os_error(error)),
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "no-unsafe"))]
+pub fn drop_caches(address: &str) -> io::Result<()> {
+    // Without sync(2) dirty pages of a freshly written file may survive
+    File::open(address)?.sync_all()?;
+    std::fs::write("/proc/sys/vm/drop_caches", "1")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn drop_caches(_address: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "dropping caches is only supported on Linux"))
+}
+
+#[cfg(all(target_os = "linux", not(feature = "no-unsafe")))]
+fn advise_will_need(file: &File, offset: u64, length: u64) {
+    use std::os::unix::io::AsRawFd;
+
+    // Purely a hint, the reads that follow do the actual work
+    // SAFETY: the descriptor belongs to `file`, which outlives the call
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), offset as libc::off_t, length as libc::off_t, libc::POSIX_FADV_WILLNEED) };
+}
+
+#[cfg(any(not(target_os = "linux"), feature = "no-unsafe"))]
+fn advise_will_need(_file: &File, _offset: u64, _length: u64) {}
+
+// Opens a file that is about to be read from start to end, telling the OS so that it reads
+// further ahead than for random access
+#[cfg(all(target_os = "linux", not(feature = "no-unsafe")))]
+pub(crate) fn open_sequential(address: &str) -> io::Result<File> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = File::open(address)?;
+    // SAFETY: the descriptor belongs to `file`, which outlives the call
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_SEQUENTIAL) };
+    Ok(file)
+}
+
+#[cfg(windows)]
+pub(crate) fn open_sequential(address: &str) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    // FILE_FLAG_SEQUENTIAL_SCAN
+    std::fs::OpenOptions::new().read(true).custom_flags(0x0800_0000).open(address)
+}
+
+#[cfg(not(any(all(target_os = "linux", not(feature = "no-unsafe")), windows)))]
+pub(crate) fn open_sequential(address: &str) -> io::Result<File> {
+    File::open(address)
+}
+
+// Reads a file from `position` on, asking the OS to prefetch the `window` bytes ahead of the
+// reader and renewing the hint whenever half of it has been read. Without a window reads pass
+// straight through.
+pub(crate) struct ReadAhead {
+    file: File,
+    position: u64,
+    // End of the range hinted so far
+    advised: u64,
+    window: Option<u64>,
+}
+impl ReadAhead {
+    pub fn new(file: File, position: u64, window: Option<u64>) -> Self {
+        ReadAhead { file, position, advised: position, window }
+    }
+}
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(window) = self.window {
+            if self.advised < self.position + window / 2 {
+                let start = self.advised.max(self.position);
+                advise_will_need(&self.file, start, self.position + window - start);
+                self.advised = self.position + window;
+            }
+        }
+        let bytes_read = self.file.read(buf)?;
+        self.position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+// Reads a file without filling the page cache: through O_DIRECT where the file system supports
+// it, otherwise with ordinary reads whose pages are dropped as soon as they were copied out
+pub(crate) struct DirectReader {
+    file: File,
+    direct: bool,
+    // Holds one aligned region of `DIRECT_READ_SIZE` bytes starting at `start`
+    buffer: Vec<u8>,
+    start: usize,
+    filled: usize,
+    position: usize,
+    // File offset of the region in the buffer
+    offset: u64,
+    // Set after a short direct read, which only happens at the end of the file
+    finished: bool,
+}
+impl DirectReader {
+    pub fn open(address: &str) -> io::Result<Self> {
+        let (file, direct) = match open_direct(address) {
+            Ok(file) => (file, true),
+            // File systems such as tmpfs refuse O_DIRECT
+            Err(error) if error.raw_os_error() == Some(libc::EINVAL) => (File::open(address)?, false),
+            Err(error) => return Err(error),
+        };
+        let buffer = vec![0; DIRECT_READ_SIZE + DIRECT_ALIGNMENT];
+        let start = buffer.as_ptr().align_offset(DIRECT_ALIGNMENT);
+        Ok(DirectReader { file, direct, buffer, start, filled: 0, position: 0, offset: 0, finished: false })
+    }
+}
+impl Read for DirectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.filled {
+            // Reading on from an unaligned offset would fail
+            if self.finished {
+                return Ok(0);
+            }
+            self.offset += self.filled as u64;
+            let region = &mut self.buffer[self.start..self.start + DIRECT_READ_SIZE];
+            // Every read but the last fills the region, keeping the file offset aligned
+            self.filled = loop {
+                match self.file.read(region) {
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                    result => break result?,
+                }
+            };
+            self.position = 0;
+            self.finished = self.direct && self.filled < DIRECT_READ_SIZE;
+            if !self.direct {
+                advise_dont_need(&self.file, self.offset, self.filled as u64);
+            }
+        }
+        let length = buf.len().min(self.filled - self.position);
+        buf[..length].copy_from_slice(&self.buffer[self.start + self.position..self.start + self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_direct(address: &str) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new().read(true).custom_flags(libc::O_DIRECT).open(address)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_direct(_address: &str) -> io::Result<File> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Direct I/O is only supported on Linux"))
+}
+
+#[cfg(all(target_os = "linux", not(feature = "no-unsafe")))]
+fn advise_dont_need(file: &File, offset: u64, length: u64) {
+    use std::os::unix::io::AsRawFd;
+
+    // Purely a hint, pages that stay cached only cost memory
+    // SAFETY: the descriptor belongs to `file`, which outlives the call
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), offset as libc::off_t, length as libc::off_t, libc::POSIX_FADV_DONTNEED) };
+}
+
+#[cfg(any(not(target_os = "linux"), feature = "no-unsafe"))]
+fn advise_dont_need(_file: &File, _offset: u64, _length: u64) {}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_direct_reader() {
+        let input = (0..400_000).map(|i| format!("Station {};{}.{}\n", i % 41, i % 100 - 50, i % 10)).collect::<String>();
+        let path = std::env::temp_dir().join(format!("direct_reader_{}.txt", std::process::id()));
+        std::fs::write(&path, &input).unwrap();
+        let mut read = Vec::new();
+        DirectReader::open(path.to_str().unwrap()).unwrap().read_to_end(&mut read).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(read == input.as_bytes());
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        let path = std::env::temp_dir().join(format!("read_ahead_{}.txt", std::process::id()));
+        let input = (0..20_000).map(|i| format!("Station {};{}.{}\n", i % 41, i % 100 - 50, i % 10)).collect::<String>();
+        std::fs::write(&path, &input).unwrap();
+        let mut read = Vec::new();
+        let mut file = open_sequential(path.to_str().unwrap()).unwrap();
+        file.seek(SeekFrom::Start(10)).unwrap();
+        let mut reader = ReadAhead::new(file, 10, Some(4096));
+        while reader.by_ref().take(1000).read_to_end(&mut read).unwrap() > 0 {}
+        assert_eq!(reader.advised, input.len() as u64 + 4096);
+        let address = path.to_str().unwrap();
+        let hinted = crate::Processor::builder().readahead(64 * 1024).batch_lines(100).build().unwrap().process(address).unwrap();
+        let expected = crate::Processor::builder().build().unwrap().process(address).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(read == input.as_bytes()[10..]);
+        assert_eq!(hinted.to_string(), expected.to_string());
+    }
+
+}
@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::io::{self, BufRead, BufReader, Read};
+use hashbrown::HashSet;
+use super::parse::{parse_i32, parse_wide_i32};
+use super::{LINE_DELIMITER, MAX_INTEGER_DIGITS, MAX_STATION_LENGTH, MAX_UNIQUE_STATIONS};
+
+// Violating lines listed by number, the rest are only counted
+const MAX_EXAMPLES: usize = 20;
+
+/// A way a line breaks the official format. Only the first is reported for each line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Violation {
+    InvalidUtf8,
+    /// No `;`, or more than one, which includes blank lines
+    Delimiter,
+    /// An empty station name or one longer than 100 bytes
+    NameLength,
+    /// A value other than `-?d?d.d`, such as `1.23` or `12.0\r`
+    ValueFormat,
+    /// A well-formed value outside -99.9..=99.9
+    ValueRange,
+}
+impl Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Violation::InvalidUtf8 => "invalid UTF-8",
+            Violation::Delimiter => "not exactly one `;`",
+            Violation::NameLength => "station name empty or over 100 bytes",
+            Violation::ValueFormat => "value not of the form -?d?d.d",
+            Violation::ValueRange => "value outside -99.9..=99.9",
+        })
+    }
+}
+
+/// Outcome of checking every line of an input against the official format
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub lines: u64,
+    pub unique_stations: usize,
+    /// Lines breaking each rule
+    pub violations: BTreeMap<Violation, u64>,
+    /// The first violating lines, numbered from 1
+    pub examples: Vec<(u64, Violation)>,
+}
+impl Check {
+    /// Whether the input follows the format, including its limit of 10,000 distinct stations
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty() && self.unique_stations <= MAX_UNIQUE_STATIONS
+    }
+}
+impl Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (line_no, violation) in &self.examples {
+            writeln!(f, "Line {line_no}: {violation}")?;
+        }
+        for (violation, count) in &self.violations {
+            writeln!(f, "{count} lines: {violation}")?;
+        }
+        if self.unique_stations > MAX_UNIQUE_STATIONS {
+            writeln!(f, "{} distinct stations, over the limit of {MAX_UNIQUE_STATIONS}", self.unique_stations)?;
+        }
+        let invalid = self.violations.values().sum::<u64>();
+        writeln!(f, "{invalid} of {} lines violate the format", self.lines)
+    }
+}
+
+/// Streams the input once, checking every line against the official challenge format rather
+/// than the looser one the processor accepts. Quicker to run before a long job than to find a
+/// malformed line halfway through it.
+pub fn check<R: Read>(reader: R) -> io::Result<Check> {
+    let mut reader = BufReader::new(reader);
+    let mut outcome = Check::default();
+    let mut stations = HashSet::<String>::new();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        outcome.lines += 1;
+        let contents = line.strip_suffix(b"\n").unwrap_or(&line);
+        match check_line(contents) {
+            Ok(station) => {
+                if !stations.contains(station) {
+                    stations.insert(station.to_string());
+                }
+            }
+            Err(violation) => {
+                *outcome.violations.entry(violation).or_default() += 1;
+                if outcome.examples.len() < MAX_EXAMPLES {
+                    outcome.examples.push((outcome.lines, violation));
+                }
+            }
+        }
+    }
+    outcome.unique_stations = stations.len();
+    Ok(outcome)
+}
+
+// The station of a valid line
+fn check_line(line: &[u8]) -> Result<&str, Violation> {
+    let line = std::str::from_utf8(line).map_err(|_| Violation::InvalidUtf8)?;
+    let (station, value) = line.split_once(LINE_DELIMITER).ok_or(Violation::Delimiter)?;
+    if value.contains(LINE_DELIMITER) {
+        return Err(Violation::Delimiter);
+    }
+    if station.is_empty() || station.len() > MAX_STATION_LENGTH {
+        return Err(Violation::NameLength);
+    }
+    match (parse_i32(value), parse_wide_i32(value, MAX_INTEGER_DIGITS)) {
+        (Some(_), _) => Ok(station),
+        (None, Some(_)) => Err(Violation::ValueRange),
+        (None, None) => Err(Violation::ValueFormat),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let mut input = b"Hamburg;12.0\nBulawayo;8.9\nbad\n;1.0\n".to_vec();
+        input.extend("x".repeat(101).bytes());
+        input.extend(b";1.0\nOslo;1.23\nOslo;100.0\na;b;1.0\n\nZ\xc3\xbcrich;-5.5\r\n\xff;1.0\nOslo;-99.9");
+        let outcome = check(&input[..]).unwrap();
+        assert_eq!((outcome.lines, outcome.unique_stations), (12, 3));
+        assert_eq!(outcome.examples.iter().map(|&(line_no, _)| line_no).collect::<Vec<_>>(), [3, 4, 5, 6, 7, 8, 9, 10, 11]);
+        let counts = outcome.violations.iter().map(|(&violation, &count)| (violation, count)).collect::<Vec<_>>();
+        assert_eq!(counts, [(Violation::InvalidUtf8, 1), (Violation::Delimiter, 3), (Violation::NameLength, 2), (Violation::ValueFormat, 2), (Violation::ValueRange, 1)]);
+        assert!(!outcome.is_ok());
+        assert!(outcome.to_string().ends_with("9 of 12 lines violate the format\n"));
+        assert!(check(&b"Hamburg;12.0\nBulawayo;8.9"[..]).unwrap().is_ok());
+    }
+
+}
@@ -0,0 +1,158 @@
+use std::fs::{self, File};
+use std::hash::BuildHasher;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Instant;
+use xxhash_rust::xxh3::xxh3_64;
+use super::partition::{last_line_end, next_line_start, read_range};
+use super::{open_sequential, Compression, Data, ProcessError, ProcessStats, Processor, Results};
+
+// Start of every checkpoint file, with the version of the layout that follows
+const MAGIC: &[u8; 8] = b"BRCCKPT1";
+// Bytes parsed between two checkpoints of a run
+const CHECKPOINT_INTERVAL: u64 = 1 << 30;
+// Bytes before the offset whose hash tells whether the file is still the one checkpointed
+const CHECK_SIZE: u64 = 4096;
+
+/// How far an earlier run over a file got, and what it had aggregated up to there
+#[derive(Debug)]
+pub struct Checkpoint {
+    /// Bytes at the start of the file covered by `results`, ending at the end of a line
+    pub offset: u64,
+    // xxHash3 of the bytes just before `offset`
+    hash: u64,
+    pub results: Results<Data>,
+}
+impl Checkpoint {
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.offset.to_le_bytes())?;
+        writer.write_all(&self.hash.to_le_bytes())?;
+        self.results.write_state(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; 24];
+        reader.read_exact(&mut header)?;
+        let (magic, numbers) = header.split_at(8);
+        if magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a checkpoint"));
+        }
+        let (offset, hash) = numbers.split_at(8);
+        Ok(Checkpoint {
+            offset: u64::from_le_bytes(offset.try_into().map_err(io::Error::other)?),
+            hash: u64::from_le_bytes(hash.try_into().map_err(io::Error::other)?),
+            results: Results::read_state(reader)?,
+        })
+    }
+
+    // Replaces the saved checkpoint all at once, so a run dying while saving keeps the last one
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let partial = path.with_extension("partial");
+        self.write_to(File::create(&partial)?)?;
+        fs::rename(partial, path)
+    }
+}
+
+impl<S: BuildHasher + Clone + Send + Sync> Processor<S, Data> {
+    /// Aggregates the file, resuming from the checkpoint at `checkpoint_path` if there is one and
+    /// saving a new one after every gigabyte. A run that dies part way, or a later run over a file
+    /// that has grown since, then only parses the bytes after the checkpoint. An unterminated last
+    /// line is aggregated but left out of the checkpoint, to be read again once it is complete.
+    pub fn process_resumable(&self, address: &str, checkpoint_path: &Path) -> Result<Results, ProcessError> {
+        self.process_checkpointed(address, checkpoint_path, CHECKPOINT_INTERVAL)
+    }
+
+    fn process_checkpointed(&self, address: &str, checkpoint_path: &Path, interval: u64) -> Result<Results, ProcessError> {
+        if self.config.manifest_path.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "A manifest covers a whole input").into());
+        }
+        if self.config.max_lines.is_some() || self.config.sample.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Checkpoints cover every line, without line limits or sampling").into());
+        }
+        let start = Instant::now();
+        let mut file = open_sequential(address)?;
+        if self.config.compression.of_file(&mut file)? != Compression::None {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "Compressed files cannot be checkpointed").into());
+        }
+        let length = file.metadata()?.len();
+        let mut checkpoint = match File::open(checkpoint_path) {
+            Ok(saved) => Checkpoint::read_from(saved)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Checkpoint { offset: 0, hash: hash_before(address, 0)?, results: Results::default() },
+            Err(error) => return Err(error.into()),
+        };
+        if checkpoint.offset > length || hash_before(address, checkpoint.offset)? != checkpoint.hash {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "The file no longer matches the checkpoint").into());
+        }
+
+        let complete = last_line_end(address, length)?;
+        while checkpoint.offset < complete {
+            let end = next_line_start(checkpoint.offset + interval, complete, |start, end| read_range(address, start, end))?;
+            let before = checkpoint.results.stats().lines;
+            let segment = self.process_range(file.try_clone()?, checkpoint.offset..end, || Ok(before), Instant::now())?;
+            if segment.stats().cancelled {
+                // Only part of the segment was read, so the saved checkpoint stays at its start
+                checkpoint.results.merge(segment);
+                break;
+            }
+            checkpoint.results.merge(segment);
+            checkpoint.offset = end;
+            checkpoint.hash = hash_before(address, end)?;
+            checkpoint.save(checkpoint_path)?;
+        }
+        let mut results = checkpoint.results;
+        if complete < length {
+            let before = results.stats().lines;
+            results.merge(self.process_range(file, complete..length, || Ok(before), Instant::now())?);
+        }
+        let stats = ProcessStats { total_time: start.elapsed(), ..*results.stats() };
+        Ok(results.with_stats(stats))
+    }
+}
+
+fn hash_before(address: &str, offset: u64) -> io::Result<u64> {
+    Ok(xxh3_64(&read_range(address, offset.saturating_sub(CHECK_SIZE), offset)?))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let directory = std::env::temp_dir();
+        let path = directory.join(format!("checkpointed_{}.txt", std::process::id()));
+        let checkpoint_path = directory.join(format!("checkpointed_{}.checkpoint", std::process::id()));
+        let mut input = String::new();
+        for i in 0..2000 {
+            input += &format!("Station {};{}.{}\n", i % 31, i % 100 - 50, i % 10);
+        }
+        let processor = Processor::builder().build().unwrap();
+        // The first half ends with a whole record that has yet to get its newline
+        let cut = input.len() / 2 + input[input.len() / 2..].find('\n').unwrap();
+        let (first, rest) = input.split_at(cut);
+        fs::write(&path, first).unwrap();
+        let address = path.to_str().unwrap();
+        let partial = processor.process_checkpointed(address, &checkpoint_path, 1000).unwrap();
+        let saved = Checkpoint::read_from(File::open(&checkpoint_path).unwrap()).unwrap();
+
+        fs::write(&path, &input).unwrap();
+        let resumed = processor.process_checkpointed(address, &checkpoint_path, 1000).unwrap();
+        let whole = processor.process(address).unwrap();
+        fs::write(&path, rest).unwrap();
+        let replaced = processor.process_checkpointed(address, &checkpoint_path, 1000);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&checkpoint_path).unwrap();
+
+        assert_eq!(partial.stats().lines, saved.results.stats().lines + 1);
+        assert_eq!(partial.stats().lines, first.lines().count() as u64);
+        assert_eq!(&input.as_bytes()[saved.offset as usize - 1..saved.offset as usize + 8], b"\nStation ");
+        assert_eq!(resumed.to_string(), whole.to_string());
+        assert_eq!(resumed.stats().lines, 2000);
+        assert!(replaced.is_err());
+    }
+
+}
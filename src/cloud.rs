@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::sync::Arc;
+use object_store::path::Path;
+use object_store::{parse_url_opts, ObjectStore};
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+use url::Url;
+
+// Bytes per ranged GET, and how many are in flight ahead of the parser
+const CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+const PREFETCH: usize = 8;
+
+/// Streams an object from S3, Google Cloud Storage or Azure in order, keeping several ranged GETs
+/// in flight ahead of the reader so that the parsing workers stay fed. Credentials, regions and
+/// endpoints come from the usual environment variables, such as `AWS_ACCESS_KEY_ID`.
+pub struct ObjectReader {
+    runtime: Runtime,
+    store: Arc<dyn ObjectStore>,
+    path: Path,
+    length: u64,
+    chunk_size: u64,
+    // Start of the next range to request
+    next_fetch: u64,
+    pending: VecDeque<JoinHandle<object_store::Result<Vec<u8>>>>,
+    current: Vec<u8>,
+    position: usize,
+}
+impl ObjectReader {
+    /// Opens an `s3://`, `gs://`, `az://` or `file://` URL, looking up the object's length
+    pub fn open(url: &str) -> io::Result<Self> {
+        let parsed = Url::parse(url).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+        let (store, path) = parse_url_opts(&parsed, options).map_err(io::Error::other)?;
+        let store = Arc::<dyn ObjectStore>::from(store);
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+        let length = runtime.block_on(store.head(&path)).map_err(not_found)?.size as u64;
+        Ok(ObjectReader { runtime, store, path, length, chunk_size: CHUNK_SIZE, next_fetch: 0, pending: VecDeque::new(), current: Vec::new(), position: 0 })
+    }
+
+    /// Size of the whole object in bytes
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    fn prefetch(&mut self) {
+        while self.pending.len() < PREFETCH && self.next_fetch < self.length {
+            let range = self.next_fetch as usize..(self.next_fetch + self.chunk_size).min(self.length) as usize;
+            self.next_fetch = range.end as u64;
+            let (store, path) = (Arc::clone(&self.store), self.path.clone());
+            self.pending.push_back(self.runtime.spawn(async move {
+                store.get_range(&path, range).await.map(|bytes| bytes.to_vec())
+            }));
+        }
+    }
+}
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.current.len() {
+            self.prefetch();
+            let Some(next) = self.pending.pop_front() else {
+                return Ok(0);
+            };
+            self.current = self.runtime.block_on(next).map_err(io::Error::other)?.map_err(io::Error::other)?;
+            self.position = 0;
+        }
+        let length = buf.len().min(self.current.len() - self.position);
+        buf[..length].copy_from_slice(&self.current[self.position..self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+fn not_found(error: object_store::Error) -> io::Error {
+    match error {
+        object_store::Error::NotFound { .. } => io::Error::new(io::ErrorKind::NotFound, error),
+        error => io::Error::other(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs::File;
+    use std::io::Write;
+    use super::*;
+
+    #[test]
+    fn test_object_reader() {
+        let path = std::env::temp_dir().join(format!("object_reader_{}.txt", std::process::id()));
+        let mut input = String::new();
+        for i in 0..3000 {
+            input += &format!("Station {};{}.{}\n", i % 29, i % 100 - 50, i % 10);
+        }
+        File::create(&path).unwrap().write_all(input.as_bytes()).unwrap();
+        let url = format!("file://{}", path.display());
+        let mut read = Vec::new();
+        let mut reader = ObjectReader::open(&url).unwrap();
+        assert_eq!(reader.len(), input.len() as u64);
+        // Many more ranges than are prefetched at once
+        reader.chunk_size = 1000;
+        reader.read_to_end(&mut read).unwrap();
+        let results = crate::Processor::builder().build().unwrap().process(&url).unwrap();
+        let expected = crate::Processor::builder().build().unwrap().process(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read, input.as_bytes());
+        assert_eq!(results.to_string(), expected.to_string());
+        assert!(ObjectReader::open(&url).is_err_and(|error| error.kind() == io::ErrorKind::NotFound));
+    }
+
+}
@@ -0,0 +1,148 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// How the input is compressed. Neither magic number can start valid UTF-8 text, so detection
+/// never mistakes a plain measurements file for a compressed one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Recognise gzip and zstd by their magic bytes and read anything else as is
+    #[default]
+    Auto,
+    None,
+    /// Needs the `flate2` feature
+    Gzip,
+    /// Needs the `zstd` feature
+    Zstd,
+}
+impl Compression {
+    fn detect(header: &[u8]) -> Compression {
+        if header.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if header.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    // Resolves `Auto` from the first bytes of the file, leaving the file at its start
+    pub(crate) fn of_file(self, file: &mut File) -> io::Result<Compression> {
+        if self != Compression::Auto {
+            return Ok(self);
+        }
+        let mut header = Vec::with_capacity(ZSTD_MAGIC.len());
+        file.take(ZSTD_MAGIC.len() as u64).read_to_end(&mut header)?;
+        file.rewind()?;
+        Ok(Compression::detect(&header))
+    }
+}
+
+// Decompresses a reader that cannot be rewound, peeking at its first bytes to resolve `Auto`
+pub(crate) fn decompress<'a, R: Read + Send + 'a>(reader: R, compression: Compression) -> io::Result<Box<dyn Read + Send + 'a>> {
+    let mut reader = BufReader::new(reader);
+    let compression = match compression {
+        Compression::Auto => Compression::detect(reader.fill_buf()?),
+        compression => compression,
+    };
+    decoder(reader, compression)
+}
+
+pub(crate) fn decoder<'a, R: BufRead + Send + 'a>(reader: R, compression: Compression) -> io::Result<Box<dyn Read + Send + 'a>> {
+    match compression {
+        Compression::Auto | Compression::None => Ok(Box::new(reader)),
+        // Concatenated members are common in rotated logs, so keep reading past the first
+        #[cfg(feature = "flate2")]
+        Compression::Gzip => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        #[cfg(not(feature = "flate2"))]
+        Compression::Gzip => Err(missing_feature("gzip", "flate2")),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(zstd::Decoder::with_buffer(reader)?)),
+        #[cfg(not(feature = "zstd"))]
+        Compression::Zstd => Err(missing_feature("zstd", "zstd")),
+    }
+}
+
+#[cfg(not(all(feature = "flate2", feature = "zstd")))]
+fn missing_feature(format: &str, feature: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, format!("Reading {format} input needs the {feature} feature"))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{verify_manifest, Manifest, Processor};
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Compression::detect(&[0x1f, 0x8b, 0x08, 0x00]), Compression::Gzip);
+        assert_eq!(Compression::detect(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]), Compression::Zstd);
+        assert_eq!(Compression::detect(b"Hamburg;12.0\n"), Compression::None);
+        assert_eq!(Compression::detect(b""), Compression::None);
+    }
+
+    #[test]
+    fn test_compressed_files() {
+        let input = b"Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n";
+        // Only extended when a compression feature is enabled
+        #[allow(unused_mut)]
+        let mut encoded = vec![(Compression::None, input.to_vec())];
+        #[cfg(feature = "flate2")]
+        {
+            use std::io::Write;
+
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(input).unwrap();
+            encoded.push((Compression::Gzip, encoder.finish().unwrap()));
+        }
+        #[cfg(feature = "zstd")]
+        encoded.push((Compression::Zstd, zstd::encode_all(&input[..], 0).unwrap()));
+
+        let path = std::env::temp_dir().join(format!("compressed_{}", std::process::id()));
+        let address = path.to_str().unwrap();
+        for (compression, bytes) in encoded {
+            std::fs::write(&path, bytes).unwrap();
+            for setting in [Compression::Auto, compression] {
+                let results = Processor::builder().compression(setting).build().unwrap().process(address).unwrap();
+                assert_eq!(results.get("Hamburg").map(|data| data.count()), Some(2), "{compression:?}");
+            }
+        }
+        std::fs::write(&path, [0x1f, 0x8b, 0x08]).unwrap();
+        assert!(Processor::builder().build().unwrap().process(address).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_manifest_of_compressed_files() {
+        let input = b"Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n";
+        let path = std::env::temp_dir().join(format!("compressed_manifest_{}", std::process::id()));
+        let manifest_path = std::env::temp_dir().join(format!("compressed_manifest_{}.txt", std::process::id()));
+        let address = path.to_str().unwrap();
+        let processor = Processor::builder().manifest_path(&manifest_path).build().unwrap();
+        std::fs::write(&path, input).unwrap();
+        processor.process(address).unwrap();
+        let manifest = Manifest::read_from(std::fs::File::open(&manifest_path).unwrap()).unwrap();
+        assert_eq!(verify_manifest(address, &manifest).unwrap(), []);
+
+        // Compressed files are detected before any bytes are hashed
+        #[cfg(feature = "flate2")]
+        {
+            use std::io::Write;
+
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(input).unwrap();
+            std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+            let error = processor.process(address).err();
+            assert!(matches!(error, Some(crate::ProcessError::Io(error)) if error.kind() == std::io::ErrorKind::InvalidInput));
+        }
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            assert!(Processor::builder().compression(compression).manifest_path(&manifest_path).build().is_err());
+        }
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&manifest_path).unwrap();
+    }
+
+}
@@ -0,0 +1,185 @@
+use std::hash::BuildHasher;
+use std::io;
+use std::time::{Duration, Instant};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Gauge, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use super::output::ordered;
+use super::{Columns, Ingest, Limit, Results, SortBy, Tenths};
+
+// Shortest time between two throughput readings, so a redraw on a key press does not give a rate
+// measured over a few milliseconds
+const MIN_RATE_WINDOW: Duration = Duration::from_millis(500);
+
+/// A full-screen view of results as they grow, in the manner of `top`: the leading stations by a
+/// column, how fast lines are coming in and how much of the input has been read. `s` switches the
+/// column and `q`, Esc or Ctrl+C closes the view.
+pub struct Dashboard {
+    title: String,
+    sort_by: SortBy,
+    limit: Option<Limit>,
+    // Lines and time of the last throughput reading, and the lines per second since the one before
+    last: Option<(u64, Instant)>,
+    lines_per_second: f64,
+}
+impl Dashboard {
+    /// Lists the stations ordered and limited as in the output of the same settings. Without a
+    /// limit, as many stations as fit are listed, highest first, or by name in name order.
+    pub fn new(title: impl Into<String>, sort_by: SortBy, limit: Option<Limit>) -> Self {
+        Dashboard { title: title.into(), sort_by, limit, last: None, lines_per_second: 0.0 }
+    }
+
+    /// Takes over the terminal and draws a snapshot of `ingest` every `interval` until `stop`
+    /// returns true or the view is closed, then restores the terminal. `input_length` gives the
+    /// current length of the input being read, when there is one, for the progress gauge.
+    pub fn run<S: BuildHasher + Clone + Send + Sync, A: Columns + Clone>(&mut self, ingest: &Ingest<S, A>, interval: Duration,
+        input_length: impl Fn() -> Option<u64>, stop: impl Fn() -> bool) -> io::Result<()> {
+        let mut terminal = ratatui::try_init()?;
+        let shown = self.show(&mut terminal, ingest, interval, input_length, stop);
+        ratatui::try_restore()?;
+        shown
+    }
+
+    fn show<S: BuildHasher + Clone + Send + Sync, A: Columns + Clone>(&mut self, terminal: &mut DefaultTerminal, ingest: &Ingest<S, A>,
+        interval: Duration, input_length: impl Fn() -> Option<u64>, stop: impl Fn() -> bool) -> io::Result<()> {
+        while !stop() {
+            let results = ingest.snapshot();
+            let length = input_length();
+            terminal.draw(|frame| self.draw(frame, &results, length))?;
+            // Keys are answered at once, redrawing after a change of column
+            let deadline = Instant::now() + interval;
+            while let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                if !event::poll(wait)? {
+                    break;
+                }
+                let Event::Key(key) = event::read()? else { continue };
+                match key.code {
+                    _ if key.kind != KeyEventKind::Press => {}
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    // Raw mode turns Ctrl+C into a key rather than an interrupt
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                    KeyCode::Char('s') => {
+                        self.sort_by = next_column(self.sort_by);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Draws one snapshot: a status line, the progress through the input, the stations and the keys
+    fn draw<A: Columns>(&mut self, frame: &mut Frame, results: &Results<A>, input_length: Option<u64>) {
+        let stats = results.stats();
+        let now = Instant::now();
+        match self.last {
+            Some((lines, at)) if now - at < MIN_RATE_WINDOW || stats.lines < lines => {}
+            Some((lines, at)) => {
+                self.lines_per_second = (stats.lines - lines) as f64 / (now - at).as_secs_f64();
+                self.last = Some((stats.lines, now));
+            }
+            None => self.last = Some((stats.lines, now)),
+        }
+        let [status, progress, table, keys] = Layout::vertical([Constraint::Length(1), Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)])
+            .areas(frame.area());
+
+        let status_line = format!("{}  {} lines  {} stations  {:.0} lines/s  {} invalid  up {:.0}s", self.title, stats.lines, results.len(),
+            self.lines_per_second, results.invalid_lines().len(), stats.total_time.as_secs_f64());
+        frame.render_widget(Paragraph::new(status_line), status);
+        let read = format!("{:.1} MB", stats.bytes_read as f64 / 1e6);
+        match input_length {
+            Some(length) if length > 0 => {
+                let ratio = (stats.bytes_read as f64 / length as f64).min(1.0);
+                frame.render_widget(Gauge::default().ratio(ratio).label(format!("{read} of {:.1} MB", length as f64 / 1e6)), progress);
+            }
+            _ => frame.render_widget(Paragraph::new(format!("{read} read")), progress),
+        }
+
+        // One row of the table goes to the header
+        let fitting = usize::from(table.height.saturating_sub(1));
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None if self.sort_by == SortBy::Station => Limit::Bottom(fitting),
+            None => Limit::Top(fitting),
+        };
+        let header = ["station", "min", "mean", "max"].into_iter().chain(A::EXTRA.iter().copied()).chain(["count"]).map(|name| {
+            match name == column_name(self.sort_by) {
+                true => format!("{name} *"),
+                false => name.to_string(),
+            }
+        });
+        let rows = ordered(results, self.sort_by, Some(limit)).into_iter().map(|(station, columns)| {
+            let data = columns.data();
+            let values = [Tenths(data.min as i64), Tenths(data.mean_tenths()), Tenths(data.max as i64)].into_iter()
+                .chain(columns.extra().into_iter().map(Tenths))
+                .map(|value| value.to_string());
+            Row::new([station.to_string()].into_iter().chain(values).chain([data.count().to_string()]))
+        });
+        let widths = [Constraint::Fill(3)].into_iter().chain([Constraint::Fill(1)].repeat(A::EXTRA.len() + 4));
+        let table_widget = Table::new(rows, widths).header(Row::new(header).style(Style::default().add_modifier(Modifier::BOLD)));
+        frame.render_widget(table_widget, table);
+        frame.render_widget(Paragraph::new(format!("s: order by {} next  q: quit", column_name(next_column(self.sort_by)))), keys);
+    }
+}
+
+fn next_column(column: SortBy) -> SortBy {
+    match column {
+        SortBy::Station => SortBy::Min,
+        SortBy::Min => SortBy::Mean,
+        SortBy::Mean => SortBy::Max,
+        SortBy::Max => SortBy::Count,
+        SortBy::Count => SortBy::Station,
+    }
+}
+
+fn column_name(column: SortBy) -> &'static str {
+    match column {
+        SortBy::Station => "station",
+        SortBy::Min => "min",
+        SortBy::Mean => "mean",
+        SortBy::Max => "max",
+        SortBy::Count => "count",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use super::*;
+
+    #[test]
+    fn test_draw() {
+        let results = crate::Processor::builder().build().unwrap()
+            .process_reader(&b"Hamburg;12.0\nBulawayo;8.9\nPalembang;38.8\nHamburg;-3.4\n"[..]).unwrap();
+        let mut dashboard = Dashboard::new("measurements.txt", SortBy::Mean, None);
+        let mut terminal = Terminal::new(TestBackend::new(80, 6)).unwrap();
+        let screen = |terminal: &Terminal<TestBackend>| {
+            let buffer = terminal.backend().buffer();
+            buffer.content.chunks(usize::from(buffer.area.width))
+                .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>().trim_end().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        terminal.draw(|frame| dashboard.draw(frame, &results, Some(100))).unwrap();
+        let lines = screen(&terminal);
+        assert!(lines[0].starts_with("measurements.txt  4 lines  3 stations"), "{lines:?}");
+        assert!(lines[1].contains("0.0 MB of 0.0 MB"), "{lines:?}");
+        assert!(lines[2].starts_with("station") && lines[2].contains("mean *"), "{lines:?}");
+        // Only the two highest means fit
+        assert!(lines[3].starts_with("Palembang") && lines[4].starts_with("Bulawayo"), "{lines:?}");
+        assert_eq!(lines[5], "s: order by max next  q: quit");
+
+        dashboard.sort_by = SortBy::Station;
+        terminal.draw(|frame| dashboard.draw(frame, &results, None)).unwrap();
+        let lines = screen(&terminal);
+        assert!(lines[1].ends_with("MB read"), "{lines:?}");
+        assert!(lines[3].starts_with("Bulawayo") && lines[4].starts_with("Hamburg"), "{lines:?}");
+        assert!(lines[4].contains("-3.4") && lines[4].contains("4.3") && lines[4].ends_with('2'), "{lines:?}");
+    }
+
+}
@@ -0,0 +1,158 @@
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+// Upper bounds of the queue-wait histogram buckets, anything slower lands in a final bucket
+const QUEUE_WAIT_BUCKETS_MS: [u64; 6] = [1, 4, 16, 64, 256, 1024];
+
+/// What a single batch cost and where it ran
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BatchRecord {
+    pub worker: usize,
+    pub bytes: usize,
+    pub lines: u64,
+    pub queue_wait: Duration,
+}
+impl BatchRecord {
+    // Records a parsed batch against the worker thread running it
+    pub fn new(bytes: usize, lines: u64, queue_wait: Duration) -> Self {
+        BatchRecord {
+            worker: rayon::current_thread_index().unwrap_or(0),
+            bytes,
+            lines,
+            queue_wait,
+        }
+    }
+}
+
+/// Where the time of a run went. Read, parse and merge times are summed over the threads doing
+/// the work, so on a parallel run they can add up to more than `total_time`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProcessStats {
+    /// Input bytes as read, before any decompression
+    pub bytes_read: u64,
+    pub lines: u64,
+    pub batches: u64,
+    pub read_time: Duration,
+    pub parse_time: Duration,
+    pub merge_time: Duration,
+    pub total_time: Duration,
+    /// Whether only part of the input was aggregated, under a line limit or a sample
+    pub sampled: bool,
+    /// Whether the run was stopped by a `CancellationToken`. `bytes_read` then counts the bytes
+    /// aggregated, after any decompression.
+    pub cancelled: bool,
+}
+impl ProcessStats {
+    // Adds up the work of two parts of the same run
+    pub(crate) fn combine(self, other: ProcessStats) -> ProcessStats {
+        ProcessStats {
+            bytes_read: self.bytes_read + other.bytes_read,
+            lines: self.lines + other.lines,
+            batches: self.batches + other.batches,
+            read_time: self.read_time + other.read_time,
+            parse_time: self.parse_time + other.parse_time,
+            merge_time: self.merge_time + other.merge_time,
+            total_time: self.total_time.max(other.total_time),
+            sampled: self.sampled || other.sampled,
+            cancelled: self.cancelled || other.cancelled,
+        }
+    }
+
+    /// Input bytes per second of wall-clock time, in decimal gigabytes
+    pub fn gigabytes_per_second(&self) -> f64 {
+        self.bytes_read as f64 / 1e9 / self.total_time.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// One JSON object with times in seconds
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"bytes_read\":{},\"lines\":{},\"batches\":{},\"read_seconds\":{},\"parse_seconds\":{},\"merge_seconds\":{},\"total_seconds\":{},\"gigabytes_per_second\":{},\"sampled\":{},\"cancelled\":{}}}",
+            self.bytes_read, self.lines, self.batches, self.read_time.as_secs_f64(), self.parse_time.as_secs_f64(),
+            self.merge_time.as_secs_f64(), self.total_time.as_secs_f64(), self.gigabytes_per_second(), self.sampled, self.cancelled,
+        )
+    }
+}
+impl Display for ProcessStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sampled = if self.sampled { " (sampled)" } else { "" };
+        let cancelled = if self.cancelled { " (cancelled)" } else { "" };
+        writeln!(f, "{:<12}{} bytes, {} lines{sampled}{cancelled}, {} batches", "Input:", self.bytes_read, self.lines, self.batches)?;
+        writeln!(f, "{:<12}{:.3}s", "Read:", self.read_time.as_secs_f64())?;
+        writeln!(f, "{:<12}{:.3}s", "Parse:", self.parse_time.as_secs_f64())?;
+        writeln!(f, "{:<12}{:.3}s", "Merge:", self.merge_time.as_secs_f64())?;
+        writeln!(f, "{:<12}{:.3}s, {:.2} GB/s", "Total:", self.total_time.as_secs_f64(), self.gigabytes_per_second())
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WorkerLoad {
+    pub worker: usize,
+    pub batches: usize,
+    pub bytes: u64,
+    pub lines: u64,
+}
+
+/// How evenly work was spread across the pool during a run
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Diagnostics {
+    pub batches: usize,
+    pub workers: Vec<WorkerLoad>,
+    /// Batches per queue-wait bucket, the time between being read and a worker picking them up
+    pub queue_wait_histogram: [usize; QUEUE_WAIT_BUCKETS_MS.len() + 1],
+}
+impl Diagnostics {
+    pub(crate) fn from_records(records: impl IntoIterator<Item = BatchRecord>) -> Self {
+        let mut diagnostics = Diagnostics::default();
+        for record in records {
+            diagnostics.batches += 1;
+            if diagnostics.workers.len() <= record.worker {
+                diagnostics.workers.resize_with(record.worker + 1, WorkerLoad::default);
+            }
+            let load = &mut diagnostics.workers[record.worker];
+            load.worker = record.worker;
+            load.batches += 1;
+            load.bytes += record.bytes as u64;
+            load.lines += record.lines;
+
+            let wait_ms = record.queue_wait.as_millis() as u64;
+            let bucket = QUEUE_WAIT_BUCKETS_MS.iter().position(|&bound| wait_ms < bound).unwrap_or(QUEUE_WAIT_BUCKETS_MS.len());
+            diagnostics.queue_wait_histogram[bucket] += 1;
+        }
+        diagnostics.workers.retain(|load| load.batches > 0);
+        diagnostics
+    }
+}
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Batches: {}", self.batches)?;
+        writeln!(f, "{:>6} {:>8} {:>14} {:>12}", "worker", "batches", "bytes", "lines")?;
+        for load in &self.workers {
+            writeln!(f, "{:>6} {:>8} {:>14} {:>12}", load.worker, load.batches, load.bytes, load.lines)?;
+        }
+        writeln!(f, "Queue wait:")?;
+        let mut lower = 0;
+        for (count, upper) in self.queue_wait_histogram.iter().zip(QUEUE_WAIT_BUCKETS_MS) {
+            writeln!(f, "{:>12} {}", format!("{lower}-{upper}ms"), count)?;
+            lower = upper;
+        }
+        writeln!(f, "{:>12} {}", format!(">={lower}ms"), self.queue_wait_histogram[QUEUE_WAIT_BUCKETS_MS.len()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_from_records() {
+        let record = |worker, wait_ms| BatchRecord { worker, bytes: 100, lines: 10, queue_wait: Duration::from_millis(wait_ms) };
+        let diagnostics = Diagnostics::from_records([record(0, 0), record(2, 3), record(2, 5000)]);
+        assert_eq!(diagnostics.batches, 3);
+        assert_eq!(diagnostics.workers.len(), 2);
+        assert_eq!(diagnostics.workers[1], WorkerLoad { worker: 2, batches: 2, bytes: 200, lines: 20 });
+        assert_eq!(diagnostics.queue_wait_histogram, [1, 1, 0, 0, 0, 0, 1]);
+    }
+
+}
@@ -0,0 +1,85 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io;
+
+/// Why processing an input failed
+#[derive(Debug)]
+pub enum ProcessError {
+    Io(io::Error),
+    /// A line that is not `station;value` with a value in the configured range, numbered from 1
+    MalformedLine { line_no: u64, contents: String },
+    /// Bytes that are not UTF-8 on this line, numbered from 1, starting `offset` bytes into it
+    InvalidUtf8 { line_no: u64, offset: usize },
+}
+impl Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProcessError::Io(error) => write!(f, "{error}"),
+            ProcessError::MalformedLine { line_no, contents } => write!(f, "Invalid line {line_no}: {contents:?}"),
+            ProcessError::InvalidUtf8 { line_no, offset } => write!(f, "Invalid UTF-8 on line {line_no} at byte {offset}"),
+        }
+    }
+}
+impl ProcessError {
+    // Renumbers the line for an input that followed `lines_before` lines of earlier inputs
+    pub(crate) fn after_lines(self, lines_before: u64) -> ProcessError {
+        match self {
+            ProcessError::MalformedLine { line_no, contents } => ProcessError::MalformedLine { line_no: lines_before + line_no, contents },
+            ProcessError::InvalidUtf8 { line_no, offset } => ProcessError::InvalidUtf8 { line_no: lines_before + line_no, offset },
+            error => error,
+        }
+    }
+}
+impl Error for ProcessError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ProcessError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+impl From<io::Error> for ProcessError {
+    fn from(error: io::Error) -> Self {
+        ProcessError::Io(error)
+    }
+}
+impl From<ProcessError> for io::Error {
+    fn from(error: ProcessError) -> Self {
+        match error {
+            ProcessError::Io(error) => error,
+            error => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
+
+// Failure inside one batch, with the line counted from the start of the batch
+#[derive(Debug)]
+pub(crate) enum BatchError {
+    Io(io::Error),
+    MalformedLine { line: u64, contents: String },
+    InvalidUtf8 { line: u64, offset: usize },
+}
+impl BatchError {
+    // Numbers the line from the start of the batch, for an error in a part of it after `lines`
+    pub fn later(self, lines: u64) -> BatchError {
+        match self {
+            BatchError::MalformedLine { line, contents } => BatchError::MalformedLine { line: lines + line, contents },
+            BatchError::InvalidUtf8 { line, offset } => BatchError::InvalidUtf8 { line: lines + line, offset },
+            error => error,
+        }
+    }
+
+    // Numbers the line from the start of the input, given how many lines came before the batch
+    pub fn after_lines(self, lines_before: u64) -> ProcessError {
+        match self {
+            BatchError::Io(error) => ProcessError::Io(error),
+            BatchError::MalformedLine { line, contents } => ProcessError::MalformedLine { line_no: lines_before + line + 1, contents },
+            BatchError::InvalidUtf8 { line, offset } => ProcessError::InvalidUtf8 { line_no: lines_before + line + 1, offset },
+        }
+    }
+}
+impl From<io::Error> for BatchError {
+    fn from(error: io::Error) -> Self {
+        BatchError::Io(error)
+    }
+}
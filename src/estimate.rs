@@ -0,0 +1,131 @@
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::{Duration, Instant};
+use super::{Aggregator, LineLengths, ProcessError, Processor};
+
+/// Predicted cost of a full run, extrapolated from samples of the input
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub file_bytes: u64,
+    pub sampled_bytes: u64,
+    pub read_bytes_per_second: f64,
+    pub parse_bytes_per_second: f64,
+    pub runtime: Duration,
+    pub peak_memory_bytes: u64,
+}
+impl Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const MB: f64 = 1_000_000.0;
+        writeln!(f, "{:<18}{:.1} MB", "File size:", self.file_bytes as f64 / MB)?;
+        writeln!(f, "{:<18}{:.1} MB", "Sampled:", self.sampled_bytes as f64 / MB)?;
+        writeln!(f, "{:<18}{:.1} MB/s", "Read throughput:", self.read_bytes_per_second / MB)?;
+        writeln!(f, "{:<18}{:.1} MB/s", "Parse throughput:", self.parse_bytes_per_second / MB)?;
+        writeln!(f, "{:<18}{:.1}s", "Predicted time:", self.runtime.as_secs_f64())?;
+        writeln!(f, "{:<18}{:.1} MB", "Predicted peak:", self.peak_memory_bytes as f64 / MB)
+    }
+}
+
+impl<S: BuildHasher + Clone + Send + Sync, A: Aggregator> Processor<S, A> {
+    /// Reads `sample_size` bytes from the start, middle and end of the file, aggregates them with
+    /// this processor's configuration and extrapolates runtime and peak memory for the whole file.
+    pub fn estimate(&self, address: &str, sample_size: u64) -> Result<Estimate, ProcessError> {
+        let mut file = File::open(address)?;
+        let file_bytes = file.metadata()?.len();
+
+        let read_start = Instant::now();
+        let mut samples = Vec::new();
+        if file_bytes <= sample_size * 3 {
+            file.read_to_end(&mut samples)?;
+        } else {
+            for offset in [0, (file_bytes - sample_size) / 2, file_bytes - sample_size] {
+                read_sample(&mut file, offset, sample_size, &mut samples)?;
+            }
+        }
+        let read_time = read_start.elapsed();
+
+        let parse_start = Instant::now();
+        self.aggregate(samples.as_slice(), None)?;
+        let parse_time = parse_start.elapsed();
+
+        let sampled_bytes = samples.len() as u64;
+        let read_bytes_per_second = sampled_bytes as f64 / read_time.as_secs_f64().max(f64::EPSILON);
+        let parse_bytes_per_second = sampled_bytes as f64 / parse_time.as_secs_f64().max(f64::EPSILON);
+        let runtime = Duration::from_secs_f64(file_bytes as f64 / read_bytes_per_second.min(parse_bytes_per_second));
+
+        // When reading outpaces parsing, the backlog of unparsed batches grows until the reader
+        // blocks: at most the batches in flight on the pool plus the read buffers filled ahead
+        let line_lengths = self.config.line_lengths(LineLengths::detect(&samples));
+        let batch_bytes = self.config.read_size(line_lengths) as u64;
+        let reader_bytes = (self.config.batch_lines * (line_lengths.max + 1)) as u64;
+        let total_batches = file_bytes.div_ceil(batch_bytes).max(1);
+        let threads = self.pool.num_threads();
+        let max_backlog = (self.config.max_in_flight_batches.unwrap_or(2 * threads) + self.config.read_buffers) as u64;
+        let backlog = if read_bytes_per_second > parse_bytes_per_second {
+            (total_batches as f64 * (1.0 - parse_bytes_per_second / read_bytes_per_second)) as u64
+        } else {
+            threads as u64
+        };
+        let peak_memory_bytes = reader_bytes + backlog.clamp(1, max_backlog.min(total_batches)) * batch_bytes;
+
+        Ok(Estimate { file_bytes, sampled_bytes, read_bytes_per_second, parse_bytes_per_second, runtime, peak_memory_bytes })
+    }
+}
+
+// Appends the complete lines found in `sample_size` bytes after `offset`
+fn read_sample(file: &mut File, offset: u64, sample_size: u64, samples: &mut Vec<u8>) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut sample = Vec::new();
+    file.take(sample_size).read_to_end(&mut sample)?;
+    let start = match offset {
+        0 => 0,
+        _ => sample.iter().position(|&b| b == b'\n').map_or(sample.len(), |newline| newline + 1),
+    };
+    let end = sample.iter().rposition(|&b| b == b'\n').map_or(start, |newline| newline + 1);
+    samples.extend_from_slice(&sample[start..end.max(start)]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+    use super::*;
+
+    #[test]
+    fn test_samples_and_model() {
+        let input = (0..10_000).map(|line| format!("station{line};{}.{}\n", line % 90 - 45, line % 10)).collect::<String>();
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(input.as_bytes()).unwrap();
+        let address = file.path().to_str().unwrap();
+        let length = input.len() as u64;
+        let processor = Processor::builder().threads(2).max_in_flight_batches(2).read_buffers(1).batch_lines(100).build().unwrap();
+
+        // A file within three samples is read whole
+        let whole = processor.estimate(address, length / 3 + 1).unwrap();
+        assert_eq!((whole.file_bytes, whole.sampled_bytes), (length, length));
+        let sampled = processor.estimate(address, 1_000).unwrap();
+        assert_eq!(sampled.file_bytes, length);
+        assert!((2_000..=3_000).contains(&sampled.sampled_bytes), "{}", sampled.sampled_bytes);
+        // The middle and last samples start and end on line boundaries
+        for offset in [(length - 1_000) / 2, length - 1_000] {
+            let mut sample = Vec::new();
+            read_sample(&mut File::open(address).unwrap(), offset, 1_000, &mut sample).unwrap();
+            let start = input.find(std::str::from_utf8(&sample).unwrap()).unwrap();
+            assert!(start > offset as usize && input.as_bytes()[start - 1] == b'\n' && sample.ends_with(b"\n"));
+        }
+
+        // Runtime is the file over the slower of reading and parsing, and peak memory is the
+        // reader's buffer plus a backlog of one to three batches: two in flight and one read ahead
+        let slower = whole.read_bytes_per_second.min(whole.parse_bytes_per_second);
+        assert!((whole.runtime.as_secs_f64() - length as f64 / slower).abs() < 1e-6);
+        let line_lengths = processor.config.line_lengths(LineLengths::detect(input.as_bytes()));
+        let batch_bytes = processor.config.read_size(line_lengths) as u64;
+        let reader_bytes = (100 * (line_lengths.max + 1)) as u64;
+        assert!((reader_bytes + batch_bytes..=reader_bytes + 3 * batch_bytes).contains(&whole.peak_memory_bytes), "{whole:?}");
+        assert_eq!(whole.to_string().lines().count(), 6);
+    }
+
+}
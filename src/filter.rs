@@ -0,0 +1,62 @@
+use std::collections::HashSet;
+use std::io::{self, BufRead};
+
+/// Which stations are aggregated. Lines of other stations are still checked for malformed
+/// values, but skip the hashing and map insertion that dominate the cost of a line.
+#[derive(Debug, Clone)]
+pub enum StationFilter {
+    /// Exactly these names
+    Names(HashSet<String>),
+    /// Names starting with this
+    Prefix(String),
+    /// Names matching this expression anywhere, unless anchored. Needs the `regex` feature.
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+impl StationFilter {
+    /// Reads one name per line, ignoring blank lines
+    pub fn names_from_reader<R: BufRead>(reader: R) -> io::Result<StationFilter> {
+        let mut names = HashSet::new();
+        for line in reader.lines() {
+            let line = line?;
+            let name = line.strip_suffix('\r').unwrap_or(&line);
+            if !name.is_empty() {
+                names.insert(name.to_string());
+            }
+        }
+        Ok(StationFilter::Names(names))
+    }
+
+    pub fn matches(&self, station: &str) -> bool {
+        match self {
+            StationFilter::Names(names) => names.contains(station),
+            StationFilter::Prefix(prefix) => station.starts_with(prefix.as_str()),
+            #[cfg(feature = "regex")]
+            StationFilter::Regex(regex) => regex.is_match(station),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Processor;
+
+    #[test]
+    fn test_station_filters() {
+        let input = &b"Hamburg;12.0\nHalifax;3.0\nBulawayo;8.9\nHamburg;-3.4\n"[..];
+        let stations = |filter| {
+            let results = Processor::builder().station_filter(filter).batch_lines(1).avg_line_len(1).build().unwrap().process_reader(input).unwrap();
+            results.iter().map(|(station, data)| (station.to_string(), data.count())).collect::<Vec<_>>()
+        };
+        let names = StationFilter::names_from_reader(&b"Bulawayo\r\n\nHamburg\nOslo\n"[..]).unwrap();
+        assert_eq!(stations(names), [("Bulawayo".to_string(), 1), ("Hamburg".to_string(), 2)]);
+        assert_eq!(stations(StationFilter::Prefix("Ha".to_string())), [("Halifax".to_string(), 1), ("Hamburg".to_string(), 2)]);
+        #[cfg(feature = "regex")]
+        assert_eq!(stations(StationFilter::Regex(regex::Regex::new("^B|fax$").unwrap())), [("Bulawayo".to_string(), 1), ("Halifax".to_string(), 1)]);
+        let filtered = Processor::builder().station_filter(StationFilter::Prefix("Ha".to_string())).build().unwrap();
+        assert!(filtered.process_reader(&b"Hamburg;1.0\nBulawayo;bad\n"[..]).is_err());
+    }
+
+}
@@ -0,0 +1,283 @@
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use super::partition::align_shards;
+
+// Bytes per range request of a `ParallelHttpReader`, and how many are in flight ahead of the reader
+const CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+const PREFETCH: usize = 8;
+
+/// Splits the object at `url` into `workers` byte ranges that each start at the beginning of a
+/// line, so every worker can fetch and process its range independently with HTTP range requests.
+/// S3 and other object stores work through plain or presigned HTTPS URLs.
+pub fn http_shards(url: &str, workers: usize) -> io::Result<Vec<Range<u64>>> {
+    let length = content_length(url)?;
+    align_shards(length, workers, |start, end| fetch_range(url, start, end))
+}
+
+fn content_length(url: &str) -> io::Result<u64> {
+    let response = ureq::head(url).call().map_err(io::Error::other)?;
+    if response.header("Accept-Ranges") == Some("none") {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "Server does not support range requests"));
+    }
+    response.header("Content-Length")
+        .and_then(|length| length.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length"))
+}
+
+fn fetch_range(url: &str, start: u64, end: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity((end - start) as usize);
+    HttpReader::new(url, start..end).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// How often and how patiently a network read is retried before the run is abandoned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { max_retries: 8, initial_backoff: Duration::from_millis(250), max_backoff: Duration::from_secs(30) }
+    }
+}
+impl RetryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff.saturating_mul(1 << attempt.min(16)).min(self.max_backoff)
+    }
+}
+
+enum Failure {
+    Transient(io::Error),
+    Fatal(io::Error),
+}
+
+/// Streams a byte range of the object at `url`. Dropped connections, timeouts and 5xx or 429
+/// responses are retried with exponential backoff, and each retry resumes with a new range request
+/// from the last byte delivered, so a failure late in a large object does not restart it.
+pub struct HttpReader {
+    url: String,
+    position: u64,
+    end: u64,
+    body: Option<Box<dyn Read + Send + Sync>>,
+    retry: RetryPolicy,
+}
+impl HttpReader {
+    pub fn new(url: &str, range: Range<u64>) -> Self {
+        HttpReader { url: url.to_string(), position: range.start, end: range.end, body: None, retry: RetryPolicy::default() }
+    }
+
+    /// Reads the whole object, looking up its length first
+    pub fn open(url: &str) -> io::Result<Self> {
+        Ok(HttpReader::new(url, 0..content_length(url)?))
+    }
+
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Offset of the next byte to be read
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    fn connect(&self) -> Result<Box<dyn Read + Send + Sync>, Failure> {
+        let response = ureq::get(&self.url)
+            .set("Range", &format!("bytes={}-{}", self.position, self.end - 1))
+            .call()
+            .map_err(|error| match error {
+                ureq::Error::Status(status, _) if status == 429 || status >= 500 => Failure::Transient(io::Error::other(error)),
+                ureq::Error::Status(..) => Failure::Fatal(io::Error::other(error)),
+                ureq::Error::Transport(_) => Failure::Transient(io::Error::other(error)),
+            })?;
+        if response.status() != 206 {
+            return Err(Failure::Fatal(io::Error::new(io::ErrorKind::Unsupported, "Server ignored the range request")));
+        }
+        Ok(Box::new(response.into_reader().take(self.end - self.position)))
+    }
+}
+impl Read for HttpReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.end.saturating_sub(self.position);
+        if buf.is_empty() || remaining == 0 {
+            return Ok(0);
+        }
+        let length = remaining.min(buf.len() as u64) as usize;
+        let buf = &mut buf[..length];
+        let mut attempt = 0;
+        loop {
+            let result = match self.body.as_mut() {
+                Some(body) => match body.read(buf) {
+                    Ok(0) => Err(Failure::Transient(io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed before the end of the range"))),
+                    Ok(bytes_read) => Ok(bytes_read),
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(error) => Err(Failure::Transient(error)),
+                },
+                None => self.connect().map(|body| {
+                    self.body = Some(body);
+                    0
+                }),
+            };
+            match result {
+                Ok(0) => continue,
+                Ok(bytes_read) => {
+                    self.position += bytes_read as u64;
+                    return Ok(bytes_read);
+                }
+                Err(Failure::Transient(_)) if attempt < self.retry.max_retries => {
+                    self.body = None;
+                    thread::sleep(self.retry.backoff(attempt));
+                    attempt += 1;
+                }
+                Err(Failure::Transient(error) | Failure::Fatal(error)) => return Err(error),
+            }
+        }
+    }
+}
+
+/// Streams a byte range of the object at `url` in order through several range requests in flight
+/// at once, each retried like an `HttpReader`, so a single slow connection does not starve the
+/// parsing workers
+pub struct ParallelHttpReader {
+    url: String,
+    end: u64,
+    chunk_size: u64,
+    retry: RetryPolicy,
+    // Start of the next range to request
+    next_fetch: u64,
+    pending: VecDeque<JoinHandle<io::Result<Vec<u8>>>>,
+    current: Vec<u8>,
+    position: usize,
+}
+impl ParallelHttpReader {
+    pub fn new(url: &str, range: Range<u64>) -> Self {
+        ParallelHttpReader {
+            url: url.to_string(),
+            end: range.end,
+            chunk_size: CHUNK_SIZE,
+            retry: RetryPolicy::default(),
+            next_fetch: range.start,
+            pending: VecDeque::new(),
+            current: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Reads the whole object, looking up its length first
+    pub fn open(url: &str) -> io::Result<Self> {
+        Ok(ParallelHttpReader::new(url, 0..content_length(url)?))
+    }
+
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    fn prefetch(&mut self) {
+        while self.pending.len() < PREFETCH && self.next_fetch < self.end {
+            let range = self.next_fetch..(self.next_fetch + self.chunk_size).min(self.end);
+            self.next_fetch = range.end;
+            let mut reader = HttpReader::new(&self.url, range).retry_policy(self.retry);
+            self.pending.push_back(thread::spawn(move || {
+                let mut bytes = Vec::new();
+                reader.read_to_end(&mut bytes)?;
+                Ok(bytes)
+            }));
+        }
+    }
+}
+impl Read for ParallelHttpReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.current.len() {
+            self.prefetch();
+            let Some(next) = self.pending.pop_front() else {
+                return Ok(0);
+            };
+            self.current = next.join().map_err(|_| io::Error::other("A download thread failed"))??;
+            self.position = 0;
+        }
+        let length = buf.len().min(self.current.len() - self.position);
+        buf[..length].copy_from_slice(&self.current[self.position..self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use super::*;
+
+    // Serves range requests for `data`, cutting the first `drops` responses off halfway
+    // through the body and answering the next `unavailable` with a 503
+    fn flaky_server(data: &'static [u8], drops: usize, unavailable: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for (request, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                let mut range = None;
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(bytes) = line.strip_prefix("range: bytes=").or(line.strip_prefix("Range: bytes=")) {
+                        let (start, end) = bytes.split_once('-').unwrap();
+                        range = Some(start.parse::<usize>().unwrap()..end.parse::<usize>().unwrap() + 1);
+                    }
+                }
+                if (drops..drops + unavailable).contains(&request) {
+                    write!(stream, "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").unwrap();
+                    continue;
+                }
+                // Only the HEAD request looking up the length comes without a range
+                let Some(range) = range else {
+                    write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", data.len()).unwrap();
+                    continue;
+                };
+                let body = &data[range.clone()];
+                write!(stream, "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nConnection: close\r\n\r\n",
+                    body.len(), range.start, range.end - 1, data.len()).unwrap();
+                let sent = if request < drops { body.len() / 2 } else { body.len() };
+                stream.write_all(&body[..sent]).unwrap();
+            }
+        });
+        format!("http://{address}/measurements.txt")
+    }
+
+    #[test]
+    fn test_reader_resumes_after_failures() {
+        let data: &'static [u8] = b"Hamburg;12.0\nBulawayo;8.9\nPalembang;38.8\nSt. John's;15.2\n";
+        let url = flaky_server(data, 3, 2);
+        let retry = RetryPolicy { max_retries: 8, initial_backoff: Duration::from_millis(1), max_backoff: Duration::from_millis(10) };
+        let mut reader = HttpReader::new(&url, 4..data.len() as u64).retry_policy(retry);
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        assert_eq!(output, &data[4..]);
+        assert_eq!(reader.position(), data.len() as u64);
+
+        let url = flaky_server(data, 5, 1);
+        let mut reader = ParallelHttpReader::new(&url, 4..data.len() as u64).retry_policy(retry);
+        reader.chunk_size = 5;
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        assert_eq!(output, &data[4..]);
+
+        let url = flaky_server(data, 0, 0);
+        let results = crate::Processor::builder().build().unwrap().process(&url).unwrap();
+        assert_eq!(results.to_string(), "{Bulawayo=8.9/8.9/8.9, Hamburg=12.0/12.0/12.0, Palembang=38.8/38.8/38.8, St. John's=15.2/15.2/15.2}");
+
+        let url = flaky_server(data, 0, 3);
+        let retry = RetryPolicy { max_retries: 2, ..retry };
+        assert!(HttpReader::new(&url, 0..data.len() as u64).retry_policy(retry).read_to_end(&mut Vec::new()).is_err());
+    }
+
+}
@@ -0,0 +1,173 @@
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+use memchr::memrchr;
+use super::source::Tail;
+use super::{merge_batches, write_results, Columns, Data, ParsedBatch, ProcessError, ProcessStats, Processor, Results, StationHasher};
+
+// Most bytes taken from a client per read. Whatever has arrived is parsed without waiting for more.
+pub(crate) const READ_SIZE: usize = 64 * 1024;
+
+/// Aggregates `station;value` lines arriving over time from any number of clients, such as
+/// connections to a socket, so that results can be read while input keeps coming
+pub struct Ingest<S = StationHasher, A = Data> {
+    processor: Processor<S, A>,
+    state: Mutex<ParsedBatch<S, A>>,
+    start: Instant,
+}
+impl<S: BuildHasher + Clone + Send + Sync, A: Columns + Clone> Ingest<S, A> {
+    /// Parses lines as configured on the processor, which also sets the output of
+    /// `write_snapshot`. Its backend and thread pool are not used.
+    pub fn new(processor: Processor<S, A>) -> Self {
+        let state = Mutex::new(processor.lines_only());
+        Ingest { processor, state, start: Instant::now() }
+    }
+
+    /// Reads lines from one client until it closes the stream, folding in each read as soon as
+    /// it arrives. Returns the client's number of lines, or the first error with lines numbered
+    /// from the start of this client. Lines folded in before an error are kept.
+    pub fn feed<R: Read>(&self, mut reader: R) -> Result<u64, ProcessError> {
+        let mut lines = 0;
+        let mut batch = Vec::new();
+        let mut chunk = vec![0; READ_SIZE];
+        loop {
+            let bytes_read = match reader.read(&mut chunk) {
+                Ok(bytes_read) => bytes_read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error.into()),
+            };
+            batch.extend_from_slice(&chunk[..bytes_read]);
+            // Parse up to the last newline and keep the partial line for the next read
+            let end = match memrchr(b'\n', &batch) {
+                _ if bytes_read == 0 => batch.len(),
+                Some(newline) => newline + 1,
+                None => continue,
+            };
+            if end > 0 {
+                lines += self.fold(&batch[..end], lines)?;
+                batch.drain(..end);
+            }
+            if bytes_read == 0 {
+                return Ok(lines);
+            }
+        }
+    }
+
+    // Parses whole lines that came after `lines_before` lines of the same client and adds them to
+    // the shared results, returning their number
+    pub(crate) fn fold(&self, batch: &[u8], lines_before: u64) -> Result<u64, ProcessError> {
+        let mut map = self.processor.empty_map();
+        let mut parsed = self.processor.parse_into(batch, &mut map).map_err(|error| error.after_lines(lines_before))?;
+        parsed.map = map;
+        let lines = parsed.lines;
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let current = std::mem::replace(&mut *state, self.processor.lines_only());
+        *state = merge_batches(current, parsed);
+        Ok(lines)
+    }
+
+    /// Feeds the file at `address` from its start, then keeps reading whatever is appended to it,
+    /// looking for more every `interval`, until reading fails. A file that shrinks, as when it is
+    /// rotated or truncated, ends following with an error.
+    pub fn follow(&self, address: &str, interval: Duration) -> Result<(), ProcessError> {
+        self.feed(Tail::new(File::open(address)?, interval))?;
+        Ok(())
+    }
+
+    /// Takes each connection as it is accepted and feeds it on its own thread, until accepting
+    /// fails. A client that sends a malformed line under `LinePolicy::Strict` or fails to read is
+    /// dropped and reported on stderr, without affecting the others.
+    pub fn listen<C, I>(self: Arc<Self>, connections: I) -> io::Result<()>
+    where
+        C: Read + Send + 'static,
+        I: IntoIterator<Item = io::Result<C>>,
+        S: 'static,
+        A: 'static,
+    {
+        for connection in connections {
+            let connection = connection?;
+            let ingest = Arc::clone(&self);
+            thread::spawn(move || {
+                if let Err(error) = ingest.feed(connection) {
+                    eprintln!("Dropped a client: {error}");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// The results of every line folded in so far
+    pub fn snapshot(&self) -> Results<A> {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let invalid_lines = state.invalid_lines.iter().map(|(line, contents)| (line + 1, contents.clone())).collect();
+        let stats = ProcessStats { lines: state.lines, total_time: self.start.elapsed(), ..state.stats };
+        Results::from_map(state.map.clone()).with_invalid_lines(invalid_lines).with_stats(stats)
+    }
+
+    /// Writes a snapshot in the processor's output format
+    pub fn write_snapshot<W: Write>(&self, writer: W) -> io::Result<()> {
+        write_results(&self.snapshot(), self.processor.config.output, writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::net::{TcpListener, TcpStream};
+    use super::*;
+
+    #[test]
+    fn test_ingest() {
+        let ingest = Arc::new(Ingest::new(Processor::builder().build().unwrap()));
+        assert_eq!(ingest.feed(&b"Hamburg;12.0\nAbha;-3.4"[..]).unwrap(), 2);
+        assert!(matches!(ingest.feed(&b"Abha;1.0\nbad\n"[..]), Err(ProcessError::MalformedLine { line_no: 2, .. })));
+        assert_eq!(ingest.snapshot().to_string(), "{Abha=-3.4/-3.4/-3.4, Hamburg=12.0/12.0/12.0}");
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut clients = (0..4).map(|_| TcpStream::connect(address).unwrap()).collect::<Vec<_>>();
+        let accepted = (0..4).map(|_| listener.accept().map(|(stream, _)| stream)).collect::<Vec<_>>();
+        let handles = accepted.into_iter().map(|connection| {
+            let ingest = Arc::clone(&ingest);
+            thread::spawn(move || ingest.feed(connection.unwrap()).unwrap())
+        }).collect::<Vec<_>>();
+        for (index, client) in clients.iter_mut().enumerate() {
+            client.write_all(format!("Zürich;{index}.5\nHamburg;-{index}.0\n").as_bytes()).unwrap();
+        }
+        drop(clients);
+        assert_eq!(handles.into_iter().map(|handle| handle.join().unwrap()).sum::<u64>(), 8);
+        let snapshot = ingest.snapshot();
+        assert_eq!(snapshot.get("Zürich").unwrap().to_string(), "0.5/2.0/3.5");
+        assert_eq!(snapshot.get("Hamburg").unwrap().to_string(), "-3.0/1.2/12.0");
+        assert_eq!(snapshot.stats().lines, 10);
+    }
+
+    #[test]
+    fn test_follow() {
+        let path = std::env::temp_dir().join(format!("follow_{}.txt", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(b"Hamburg;12.0\nAbha;").unwrap();
+        let ingest = Arc::new(Ingest::new(Processor::builder().build().unwrap()));
+        let follower = {
+            let (ingest, address) = (Arc::clone(&ingest), path.to_str().unwrap().to_string());
+            thread::spawn(move || ingest.follow(&address, Duration::from_millis(1)))
+        };
+        let wait_for = |lines: u64| {
+            while ingest.snapshot().stats().lines < lines {
+                thread::sleep(Duration::from_millis(1));
+            }
+        };
+        wait_for(1);
+        file.write_all(b"-3.4\nHamburg;2.0\n").unwrap();
+        wait_for(3);
+        assert_eq!(ingest.snapshot().to_string(), "{Abha=-3.4/-3.4/-3.4, Hamburg=2.0/7.0/12.0}");
+        File::create(&path).unwrap();
+        let result = follower.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+}
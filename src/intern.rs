@@ -0,0 +1,246 @@
+use std::hash::BuildHasher;
+use std::io::{BufReader, Read};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+use std::thread;
+use std::time::Instant;
+use crossbeam::queue::SegQueue;
+use hashbrown::HashMap;
+use rayon::prelude::*;
+use super::{in_order, merge_batches, source, Aggregator, BufferPool, Counted, Data, InFlight, ProcessError, ProcessStats, Processor, Progress, Results, Stations};
+
+/// Gives each station name a dense ID, in the order the names are first seen, keeping a single
+/// copy of every name. Shared between the threads of a run.
+#[derive(Debug, Default)]
+pub struct Interner {
+    names: RwLock<Names>,
+}
+#[derive(Debug, Default)]
+struct Names {
+    ids: HashMap<Arc<str>, u32>,
+    names: Vec<Arc<str>>,
+}
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The name's ID, assigning the next one if the name is new
+    pub fn intern(&self, name: &str) -> u32 {
+        if let Some(id) = self.id(name) {
+            return id;
+        }
+        let mut names = self.names.write().unwrap_or_else(PoisonError::into_inner);
+        // Another thread may have added it since the read lock was let go
+        if let Some(&id) = names.ids.get(name) {
+            return id;
+        }
+        let id = names.names.len() as u32;
+        let name = Arc::<str>::from(name);
+        names.ids.insert(name.clone(), id);
+        names.names.push(name);
+        id
+    }
+
+    /// The ID of a name interned earlier
+    pub fn id(&self, name: &str) -> Option<u32> {
+        self.read().ids.get(name).copied()
+    }
+
+    /// The name that was given `id`
+    pub fn name(&self, id: u32) -> Option<Arc<str>> {
+        self.read().names.get(id as usize).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Names> {
+        self.names.read().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// What `Processor::process_interned` aggregated, each station's data at the index of its ID
+#[derive(Debug)]
+pub struct Interned<A = Data> {
+    interner: Interner,
+    data: Vec<Option<A>>,
+    invalid_lines: Vec<(u64, String)>,
+    stats: ProcessStats,
+}
+impl<A: Aggregator> Interned<A> {
+    pub fn interner(&self) -> &Interner {
+        &self.interner
+    }
+
+    /// The data of the station with this ID
+    pub fn get(&self, id: u32) -> Option<&A> {
+        self.data.get(id as usize)?.as_ref()
+    }
+
+    /// Every station's data, indexed by ID
+    pub fn data(&self) -> &[Option<A>] {
+        &self.data
+    }
+
+    pub fn stats(&self) -> &ProcessStats {
+        &self.stats
+    }
+
+    /// Names each station's data, giving what `Processor::process_reader` returns
+    pub fn into_results(self) -> Results<A> {
+        let names = self.interner.names.into_inner().unwrap_or_else(PoisonError::into_inner).names;
+        let mut stations = names.into_iter().zip(self.data)
+            .filter_map(|(name, data)| Some((name.to_string(), data?)))
+            .collect::<Vec<_>>();
+        stations.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        Results::from_sorted(stations).with_invalid_lines(self.invalid_lines).with_stats(self.stats)
+    }
+}
+
+// One batch's stations by ID. Names are looked up in the batch's own map before the interner,
+// so the interner is only asked once per station per batch.
+struct IdBatch<'a, 'i, A> {
+    interner: &'i Interner,
+    ids: HashMap<&'a str, u32>,
+    data: Vec<Option<A>>,
+}
+impl<'a, A> IdBatch<'a, '_, A> {
+    fn slot(&mut self, station: &'a str) -> &mut Option<A> {
+        let interner = self.interner;
+        let id = *self.ids.entry(station).or_insert_with(|| interner.intern(station));
+        self.slot_of(id)
+    }
+
+    fn slot_of(&mut self, id: u32) -> &mut Option<A> {
+        let id = id as usize;
+        if id >= self.data.len() {
+            self.data.resize_with(id + 1, || None);
+        }
+        &mut self.data[id]
+    }
+}
+impl<'a, A: Aggregator> Stations<'a, A> for IdBatch<'a, '_, A> {
+    fn update_station(&mut self, station: &'a str, value: i32) {
+        match self.slot(station) {
+            Some(data) => data.update(value),
+            empty => *empty = Some(A::new(value)),
+        }
+    }
+    fn merge_station(&mut self, station: &'a str, data: A) {
+        match self.slot(station) {
+            Some(existing) => existing.merge(data),
+            empty => *empty = Some(data),
+        }
+    }
+    fn merge_owned(&mut self, station: String, data: A) {
+        match self.slot_of(self.interner.intern(&station)) {
+            Some(existing) => existing.merge(data),
+            empty => *empty = Some(data),
+        }
+    }
+}
+
+// Adds the stations of `b`, which comes later in the input, to `a`
+fn merge_dense<A: Aggregator>(mut a: Vec<Option<A>>, b: Vec<Option<A>>) -> Vec<Option<A>> {
+    if a.len() < b.len() {
+        a.resize_with(b.len(), || None);
+    }
+    for (slot, data) in a.iter_mut().zip(b) {
+        let Some(data) = data else {
+            continue;
+        };
+        match slot {
+            Some(existing) => existing.merge(data),
+            empty => *empty = Some(data),
+        }
+    }
+    a
+}
+
+impl<S: BuildHasher + Clone + Send + Sync, A: Aggregator> Processor<S, A> {
+    /// Aggregates a stream of lines like `process_reader`, but names each station once in an
+    /// `Interner` and accumulates every batch into a vector indexed by station ID, so that merging
+    /// two batches adds two vectors rather than joining two maps
+    pub fn process_interned<R: Read + Send>(&self, reader: R) -> Result<Interned<A>, ProcessError> {
+        let start = Instant::now();
+        let progress = Progress::new(self.config.progress.as_ref(), None);
+        let source = source::wrap(Counted::new(reader, &progress), &self.config)?;
+        let line_lengths = self.config.line_lengths(None);
+        let batch_capacity = self.config.batch_lines * (line_lengths.max + 1);
+        let reader = BufReader::with_capacity(self.config.buffer_capacity.unwrap_or(batch_capacity), source);
+
+        let interner = Interner::new();
+        let (results, dense) = (SegQueue::new(), SegQueue::new());
+        let in_flight = InFlight::new(self.config.max_in_flight_batches.unwrap_or(2 * self.pool.num_threads()));
+        let (filled, ready) = crossbeam::channel::bounded(self.config.read_buffers);
+        let buffers = BufferPool::new(batch_capacity);
+        let (read_time, cancelled) = thread::scope(|scope| {
+            let reader = scope.spawn(|| self.read_batches(reader, None, line_lengths, filled, &buffers));
+            self.pool.in_place_scope(|s| {
+                for (index, batch) in ready.iter().enumerate() {
+                    let (results, dense, interner, in_flight, buffers) = (&results, &dense, &interner, &in_flight, &buffers);
+                    in_flight.acquire();
+                    s.spawn(move |_| {
+                        let (result, data) = {
+                            let mut stations = IdBatch { interner, ids: HashMap::new(), data: Vec::new() };
+                            (self.parse_into(&batch, &mut stations), stations.data)
+                        };
+                        dense.push((index, data));
+                        buffers.give(batch);
+                        in_flight.release();
+                        results.push((index, result));
+                    });
+                }
+            });
+            reader.join().map_err(|_| std::io::Error::other("The reader thread panicked"))?
+        })?;
+        let batches = in_order(results.into_iter().collect())?;
+        let mut dense = dense.into_iter().collect::<Vec<_>>();
+        dense.sort_unstable_by_key(|(index, _)| *index);
+        let merge_start = Instant::now();
+        let (mut parsed, data) = self.pool.install(|| (
+            batches.into_par_iter().reduce(|| self.lines_only(), merge_batches),
+            dense.into_par_iter().map(|(_, data)| data).reduce(Vec::new, merge_dense),
+        ));
+        parsed.stats.merge_time += merge_start.elapsed();
+        parsed.stats.read_time += read_time;
+        parsed.stats.cancelled = cancelled;
+        let results = self.finish(parsed, None, &progress, start)?;
+        Ok(Interned { interner, data, invalid_lines: results.invalid_lines().to_vec(), stats: *results.stats() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::LinePolicy;
+
+    #[test]
+    fn test_process_interned() {
+        let input = (0..5000).map(|i| format!("Station {};{}.{}\n", i % 41, i % 100 - 50, i % 10)).collect::<String>() + "bad\nStation 3;1.0";
+        let processor = Processor::builder().line_policy(LinePolicy::Collect).batch_lines(100).threads(3).build().unwrap();
+        let interned = processor.process_interned(input.as_bytes()).unwrap();
+        let expected = processor.process_reader(input.as_bytes()).unwrap();
+
+        let interner = interned.interner();
+        assert_eq!(interner.len(), 41);
+        let id = interner.id("Station 3").unwrap();
+        assert_eq!(interner.name(id).as_deref(), Some("Station 3"));
+        assert_eq!(interner.intern("Station 3"), id);
+        assert_eq!(interned.get(id).map(|data| data.count()), expected.get("Station 3").map(|data| data.count()));
+        assert_eq!(interned.stats().lines, 5002);
+        let results = interned.into_results();
+        assert_eq!(results.to_string(), expected.to_string());
+        assert_eq!(results.invalid_lines(), expected.invalid_lines());
+
+        let strict = Processor::builder().batch_lines(100).build().unwrap().process_interned(input.as_bytes());
+        assert!(matches!(strict, Err(ProcessError::MalformedLine { line_no: 5001, .. })));
+    }
+
+}
@@ -0,0 +1,80 @@
+use std::hash::BuildHasher;
+use std::io;
+use std::time::Duration;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::error::KafkaError;
+use rdkafka::Message;
+use super::ingest::READ_SIZE;
+use super::{Columns, Ingest, ProcessError};
+
+// How long a poll waits for a message before the messages so far are folded in anyway
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+impl<S: BuildHasher + Clone + Send + Sync, A: Columns + Clone> Ingest<S, A> {
+    /// Consumes `topics` as member `group` of the Kafka cluster at `brokers`, such as
+    /// `localhost:9092`, folding in the `station;value` lines of each message until consuming
+    /// fails. Lost brokers are reported on stderr meanwhile. A new group starts from the earliest
+    /// messages. Messages are folded in together once a read's worth has arrived or the topics go
+    /// quiet, and a malformed line under `LinePolicy::Strict` stops consuming with the line
+    /// numbered from the first message.
+    pub fn consume_kafka(&self, brokers: &str, group: &str, topics: &[&str]) -> Result<(), ProcessError> {
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("group.id", group)
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .map_err(io::Error::other)?;
+        consumer.subscribe(topics).map_err(io::Error::other)?;
+
+        let mut lines = 0;
+        let mut batch = Vec::with_capacity(READ_SIZE);
+        loop {
+            match consumer.poll(POLL_TIMEOUT) {
+                // Lost brokers are reconnected to by the client, so their errors are only reported
+                Some(Err(KafkaError::MessageConsumption(error))) => {
+                    eprintln!("Kafka consumer: {error}");
+                    continue;
+                }
+                Some(message) => {
+                    let message = message.map_err(io::Error::other)?;
+                    append_message(&mut batch, message.payload().unwrap_or_default());
+                    if batch.len() < READ_SIZE {
+                        continue;
+                    }
+                }
+                None if batch.is_empty() => continue,
+                None => {}
+            }
+            lines += self.fold(&batch, lines)?;
+            batch.clear();
+        }
+    }
+}
+
+// Adds a message's lines to the batch, ending the last one if the producer did not
+fn append_message(batch: &mut Vec<u8>, payload: &[u8]) {
+    if payload.is_empty() {
+        return;
+    }
+    batch.extend_from_slice(payload);
+    if !payload.ends_with(b"\n") {
+        batch.push(b'\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_append_message() {
+        let mut batch = Vec::new();
+        for payload in [&b"Hamburg;12.0"[..], b"", b"Abha;-3.4\nAbha;1.0\n", "Zürich;0.5".as_bytes()] {
+            append_message(&mut batch, payload);
+        }
+        assert_eq!(batch, "Hamburg;12.0\nAbha;-3.4\nAbha;1.0\nZürich;0.5\n".as_bytes());
+    }
+
+}
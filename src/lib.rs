@@ -1,47 +1,172 @@
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+#![cfg_attr(test, allow(clippy::unwrap_used, clippy::expect_used))]
+#![cfg_attr(feature = "no-unsafe", forbid(unsafe_code))]
+
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
 use std::fmt::{self, Display};
-use std::fs::File;
-use std::io::{stdout, BufReader, BufWriter, Read, Write};
-use std::sync::Arc;
-use rayon::{ThreadPoolBuilder, Scope};
+use std::hash::BuildHasher;
+use std::fs::{self, File};
+use std::marker::PhantomData;
+use std::io::{self, stdout, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder, Scope};
+use crossbeam::channel::{Receiver, Sender};
 use crossbeam::queue::SegQueue;
+use memchr::{memchr, memchr_iter, memrchr};
 use hashbrown::HashMap;
+use hashbrown::hash_map::{Entry, RawEntryMut};
 
-#[derive(Debug)]
-struct Data {
-    sum: i32,
+mod aggregator;
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "async")]
+mod asynchronous;
+mod cache;
+mod cancel;
+mod check;
+mod checkpoint;
+#[cfg(feature = "tui")]
+mod dashboard;
+#[cfg(feature = "object-store")]
+mod cloud;
+mod compression;
+mod diagnostics;
+mod error;
+mod estimate;
+mod filter;
+mod generate;
+#[cfg(feature = "http")]
+mod http;
+mod ingest;
+mod intern;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod manifest;
+mod mmap;
+mod output;
+pub mod parse;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod partition;
+mod preset;
+mod priority;
+mod profile;
+mod reference;
+mod results;
+#[cfg(feature = "serve")]
+mod serve;
+mod source;
+mod scan;
+mod state;
+mod strategy;
+#[cfg(all(feature = "flat-table", not(feature = "hot-cache")))]
+mod table;
+mod uring;
+mod validate;
+pub use aggregator::{Aggregator, Moments, Percentiles};
+pub use cache::{drop_caches, prime_cache};
+use cache::{open_sequential, DirectReader, ReadAhead};
+pub use cancel::CancellationToken;
+pub use check::{check, Check, Violation};
+pub use checkpoint::Checkpoint;
+#[cfg(feature = "tui")]
+pub use dashboard::Dashboard;
+#[cfg(feature = "object-store")]
+pub use cloud::ObjectReader;
+pub use compression::Compression;
+pub use diagnostics::{Diagnostics, ProcessStats, WorkerLoad};
+pub use error::ProcessError;
+use error::BatchError;
+pub use estimate::Estimate;
+pub use filter::StationFilter;
+pub use generate::generate;
+#[cfg(feature = "http")]
+pub use http::{http_shards, HttpReader, ParallelHttpReader, RetryPolicy};
+use diagnostics::BatchRecord;
+pub use ingest::Ingest;
+pub use intern::{Interned, Interner};
+pub use manifest::{verify_manifest, Manifest, Segment};
+pub use output::{parse_results, Columns, Format, Limit, SortBy, Summary};
+pub use preset::Preset;
+use output::{write_results, OutputOptions};
+pub use parse::{FieldOrder, LineFormat};
+use parse::{parse_decimal, parse_i32, parse_wide_i32};
+use scan::{Line, Lines};
+pub use priority::lower_priority;
+pub use profile::{InputProfile, Suggestions};
+pub use reference::{process_file_reference, process_reader_reference};
+pub use results::Results;
+#[cfg(feature = "serve")]
+pub use serve::{serve, serve_jobs, JobRules, JobStatus, Jobs};
+pub use source::{EofPolicy, Throttled};
+use source::{Counted, Progress, ProgressHook};
+pub use strategy::{compare, Comparison, Run, Strategy};
+pub use validate::{validate, Discrepancy, Validation};
+
+/// Running min, max, sum and count of one station's values, kept in tenths of a degree
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Data {
+    // Wide enough for a billion readings at the largest value, where an i32 overflows after ~21M
+    sum: i64,
     count: u32,
     min: i32,
     max: i32,
 }
 impl Display for Data {
+    // A requested width is applied to each of the three numbers, right-aligned. Values follow the
+    // challenge rounding: the mean is rounded half up to a tenth and zero is never negative.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}/{:.1}/{}",
-            self.min as f64 / 10.0,
-            self.sum as f64 / self.count as f64 / 10.0,
-            self.max as f64 / 10.0,
-        )
+        let width = f.width().unwrap_or(0);
+        write!(f, "{:>width$}/{:>width$}/{:>width$}", Tenths(self.min as i64), Tenths(self.mean_tenths()), Tenths(self.max as i64))
     }
 }
 impl Data {
-    fn update(&mut self, value: i32) {
-        self.sum += value;
-        self.count += 1;
-        if value < self.min {
-            self.min = value;
-        } else if value > self.max {
-            self.max = value;
-        }
+    pub fn min(&self) -> f64 {
+        self.min as f64 / 10.0
+    }
+    pub fn mean(&self) -> f64 {
+        self.sum as f64 / self.count as f64 / 10.0
+    }
+    pub fn max(&self) -> f64 {
+        self.max as f64 / 10.0
+    }
+    pub fn count(&self) -> u32 {
+        self.count
     }
-    fn union(&mut self, other: &Data) {
-        // Assumes that the stations are the same
+    /// Folds in the values of another part of the input
+    pub fn merge(&mut self, other: Data) {
         self.sum += other.sum;
         self.count += other.count;
         self.min = if self.min < other.min { self.min } else { other.min };
         self.max = if self.max > other.max { self.max } else { other.max };
     }
+    // Mean in tenths rounded half up, like `Math.round` in the reference implementation. Exact
+    // integer arithmetic, as the float mean of a tie is often just below or above the half.
+    fn mean_tenths(&self) -> i64 {
+        let count = self.count as i64;
+        (2 * self.sum + count).div_euclid(2 * count)
+    }
+}
+
+// A value in tenths printed with one decimal, never as -0.0
+struct Tenths(i64);
+impl Display for Tenths {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let magnitude = self.0.unsigned_abs();
+        match f.width() {
+            Some(_) => f.pad(&format!("{sign}{}.{}", magnitude / 10, magnitude % 10)),
+            None => write!(f, "{sign}{}.{}", magnitude / 10, magnitude % 10),
+        }
+    }
 }
 
 /*
@@ -79,6 +204,8 @@ Changed from f64 to i32 to store values using a custom parser - 8.04s - 5.4% imp
     ** Still has some optimisation potential in the parser
 Improved the parse_i32 function by generalising for the data - 7.99s - 0.6% improvement
 Tuned size of the thread pool - 7.56s - 5.6% improvement
+
+`brc compare <file>` times the pipelines kept as `Strategy` against each other
 */
 
 // Data Constants
@@ -88,142 +215,1827 @@ const MAX_STATION_LENGTH: usize = 100;
 const NEW_LINE: char = '\n';
 const LINE_DELIMITER: char = ';';
 const MAX_LINE_LENGTH: usize = MAX_STATION_LENGTH + 7; // Line formatting: (name: 100);(-)dd.d\n
+const NARROW_INTEGER_DIGITS: usize = 2; // Challenge values are within -99.9..=99.9
+const MAX_INTEGER_DIGITS: usize = 8; // Largest magnitude whose tenths still fit in an i32
 const AVERAGE_LINE_LENGTH: usize = AVERAGE_STATION_LENGTH + 6;
+const LINE_SAMPLE_SIZE: u64 = 64 * 1024;
 const MAX_UNIQUE_STATIONS: usize = 10_000;
 const BATCH_SIZE: usize = 1_000_000;
+const QUEUE_DEPTH: usize = 32;
+const READ_BUFFERS: usize = 2;
+const INLINE_THRESHOLD: u64 = 4 * 1024 * 1024;
+const STDIN_ADDRESS: &str = "-";
+// Addresses read over the network rather than from the file system, through
+// `ParallelHttpReader` and `ObjectReader`
+const HTTP_SCHEMES: &[&str] = &["http://", "https://"];
+const OBJECT_STORE_SCHEMES: &[&str] = &["s3://", "s3a://", "gs://", "az://", "abfs://", "abfss://", "adl://", "azure://", "file://"];
+
+// Splits and parses a single line, for readers that get lines one at a time
+#[cfg(feature = "parquet")]
+fn process_line(line: &str, format: LineFormat, parse: impl Fn(&str) -> Option<i32>) -> Option<(&str, i32)> {
+    let (station, value_str) = parse::split_line(line, format)?;
+    let value = parse(value_str)?;
+    Some((station, value))
+}
+
+// One batch's stations, how many lines it held and the malformed lines kept under `LinePolicy::Collect`,
+// numbered from zero at the start of the batch, along with the work that went into it
+struct ParsedBatch<S, A> {
+    map: HashMap<String, A, S>,
+    lines: u64,
+    invalid_lines: Vec<(u64, String)>,
+    stats: ProcessStats,
+}
+impl<S, A> ParsedBatch<S, A> {
+    fn new(map: HashMap<String, A, S>) -> Self {
+        ParsedBatch { map, lines: 0, invalid_lines: Vec::new(), stats: ProcessStats::default() }
+    }
+}
 
-fn split_line(line: &str) -> Option<(&str, &str)> {
-    let delimiter = line.rfind(LINE_DELIMITER)?;
-    Some((&line[..delimiter], &line[delimiter + 1..]))
+// Combines two adjacent batches, `a` coming first in the input
+fn merge_batches<S: BuildHasher, A: Aggregator>(a: ParsedBatch<S, A>, b: ParsedBatch<S, A>) -> ParsedBatch<S, A> {
+    let mut invalid_lines = a.invalid_lines;
+    invalid_lines.extend(b.invalid_lines.into_iter().map(|(line, contents)| (line + a.lines, contents)));
+    let start = Instant::now();
+    let map = merge_maps(a.map, b.map);
+    let mut stats = a.stats.combine(b.stats);
+    stats.merge_time += start.elapsed();
+    ParsedBatch { map, lines: a.lines + b.lines, invalid_lines, stats }
 }
 
-fn parse_i32(value: &str) -> i32 {
-    const ZERO: i32 = b'0' as i32;
-    let to_digit = |c: char| c as i32 - ZERO;
+// Like merging with `try_reduce`, but keeps the earliest error in the input rather than whichever
+// a worker happened to hit first
+fn merge_results<S: BuildHasher, A: Aggregator>(a: Result<ParsedBatch<S, A>, ProcessError>, b: Result<ParsedBatch<S, A>, ProcessError>) -> Result<ParsedBatch<S, A>, ProcessError> {
+    Ok(merge_batches(a?, b?))
+}
 
-    let mut characters = value.chars().rev();
-    let mut result = to_digit(characters.next().unwrap());
-    let character = characters.nth(1).unwrap();
-    result += to_digit(character) * 10;
-    if let Some(character) = characters.next() {
-        if character == '-' {
-            return -result;
+// Where a batch's readings are accumulated, looked up by names borrowed from the batch
+trait Stations<'a, A: Aggregator> {
+    // Unused under hot-cache and flat-table, which fold their own data in with `merge_station`
+    #[cfg_attr(any(feature = "hot-cache", feature = "flat-table"), allow(dead_code))]
+    fn update_station(&mut self, station: &'a str, value: i32) {
+        self.merge_station(station, A::new(value));
+    }
+    fn merge_station(&mut self, station: &'a str, data: A);
+    // For names that are not in the batch as they are, such as lossily decoded ones
+    fn merge_owned(&mut self, station: String, data: A);
+}
+impl<'a, S: BuildHasher, A: Aggregator> Stations<'a, A> for HashMap<String, A, S> {
+    // Looks up by the borrowed name so a String is only allocated for a station's first line
+    fn update_station(&mut self, station: &'a str, value: i32) {
+        match self.raw_entry_mut().from_key(station) {
+            RawEntryMut::Occupied(mut entry) => entry.get_mut().update(value),
+            RawEntryMut::Vacant(entry) => {
+                entry.insert(station.to_string(), A::new(value));
+            }
+        }
+    }
+    fn merge_station(&mut self, station: &'a str, data: A) {
+        match self.raw_entry_mut().from_key(station) {
+            RawEntryMut::Occupied(mut entry) => entry.get_mut().merge(data),
+            RawEntryMut::Vacant(entry) => {
+                entry.insert(station.to_string(), data);
+            }
+        }
+    }
+    fn merge_owned(&mut self, station: String, data: A) {
+        match self.entry(station) {
+            Entry::Occupied(mut entry) => entry.get_mut().merge(data),
+            Entry::Vacant(entry) => {
+                entry.insert(data);
+            }
         }
-        result += to_digit(character) * 100;
     }
-    if characters.next().is_some() { -result }
-    else { result }
 }
 
-fn process_line(line: &str) -> (&str, i32) {
-    let (station, value_str) = match split_line(line) {
-        Some((station, value_str)) => (station, value_str),
-        None => unreachable!("Invalid line"),
+// Adds the batch's stations to `local_map`, returning its number of lines and the malformed lines
+// kept under `LinePolicy::Collect`. With `challenge_values`, values in the challenge format are
+// parsed as the lines are walked, which `parse` must agree with.
+fn process_batch<'a, A: Aggregator>(batch: &'a str, local_map: &mut impl Stations<'a, A>, format: LineFormat, parse: impl Fn(&str) -> Option<i32>, challenge_values: bool, keep: impl Fn(&str) -> bool, policy: LinePolicy) -> Result<(u64, Vec<(u64, String)>), BatchError> {
+    // Batch has multiple lines contained within it;
+    let lines = batch.strip_suffix(NEW_LINE).unwrap_or(batch);
+
+    #[cfg(feature = "hot-cache")]
+    let mut hot_cache = HotCache::new();
+    #[cfg(all(feature = "flat-table", not(feature = "hot-cache")))]
+    let mut table = table::StationTable::with_capacity((batch.len() / AVERAGE_LINE_LENGTH).min(MAX_UNIQUE_STATIONS));
+    let mut invalid_lines = Vec::new();
+    let mut line_count = 0;
+    let walk = match challenge_values {
+        true => Lines::new(lines.as_bytes(), format.delimiter()).parsing_values(format.order()),
+        false => Lines::new(lines.as_bytes(), format.delimiter()),
     };
-    let value = parse_i32(value_str);
-    (station, value)
+    // Each line comes with its delimiters, found in the same pass as its end
+    for Line { range, first_delimiter, last_delimiter, parsed } in walk {
+        // Tolerates the `\r\n` endings of Windows exports. Only needed when the walk did not parse
+        // the line.
+        let line = || lines[range.clone()].strip_suffix('\r').unwrap_or(&lines[range.clone()]);
+        line_count += 1;
+        let split = match parsed {
+            Some((station, value)) => Some((&lines[station], value)),
+            None => format.split_at(line(), first_delimiter.map(|at| at - range.start), last_delimiter.map(|at| at - range.start))
+                .and_then(|(station, value)| Some((station, parse(value)?))),
+        };
+        let Some((station, value)) = split else {
+            let line = line();
+            // Blank lines, such as those trailing some exports, still count towards line numbers
+            if line.is_empty() {
+                continue;
+            }
+            match policy {
+                LinePolicy::Strict => return Err(BatchError::MalformedLine { line: line_count - 1, contents: line.to_string() }),
+                LinePolicy::Skip => {}
+                LinePolicy::Collect => invalid_lines.push((line_count - 1, line.to_string())),
+            }
+            continue;
+        };
+        if !keep(station) {
+            continue;
+        }
+        #[cfg(feature = "hot-cache")]
+        hot_cache.update(station, value, local_map);
+        #[cfg(all(feature = "flat-table", not(feature = "hot-cache")))]
+        table.update(station, value);
+        #[cfg(not(any(feature = "hot-cache", feature = "flat-table")))]
+        local_map.update_station(station, value);
+    }
+    #[cfg(feature = "hot-cache")]
+    hot_cache.flush(local_map);
+    #[cfg(all(feature = "flat-table", not(feature = "hot-cache")))]
+    table.flush(local_map);
+
+    Ok((line_count, invalid_lines))
 }
 
-fn process_batch(batch: &str) -> HashMap<String, Data> {
-    // Batch has multiple lines contained within it;
-    let lines = batch[..batch.len() - 1].split(NEW_LINE);
+#[cfg(feature = "hot-cache")]
+const HOT_CACHE_SLOTS: usize = 16;
 
-    const LOCAL_CAPACITY: usize = if BATCH_SIZE > MAX_UNIQUE_STATIONS { MAX_UNIQUE_STATIONS } else { BATCH_SIZE };
-    let mut local_map = HashMap::<String, Data>::with_capacity(LOCAL_CAPACITY);
-    for line in lines {
-        let (station, value) = process_line(line);
-        local_map.entry(station.to_string())
-            .and_modify(|data| data.update(value))
-            .or_insert_with(|| Data { sum: value, count: 1, min: value, max: value });
+// Direct-mapped write-back cache of recently seen stations, checked before the map. Sorted or
+// clustered inputs hit it on most lines and skip hashing the name; evicted entries are folded
+// into the map.
+#[cfg(feature = "hot-cache")]
+struct HotCache<'a, A> {
+    slots: [Option<(&'a str, A)>; HOT_CACHE_SLOTS],
+}
+#[cfg(feature = "hot-cache")]
+impl<'a, A: Aggregator> HotCache<'a, A> {
+    fn new() -> Self {
+        HotCache { slots: std::array::from_fn(|_| None) }
+    }
+    // Cheap enough to beat a full hash: the length and the first and last bytes of the name
+    fn slot(station: &str) -> usize {
+        let bytes = station.as_bytes();
+        let first = bytes.first().copied().unwrap_or(0) as usize;
+        let last = bytes.last().copied().unwrap_or(0) as usize;
+        (bytes.len() ^ first.wrapping_mul(31) ^ last.wrapping_mul(7)) % HOT_CACHE_SLOTS
+    }
+    fn update(&mut self, station: &'a str, value: i32, map: &mut impl Stations<'a, A>) {
+        match &mut self.slots[Self::slot(station)] {
+            Some((name, data)) if *name == station => data.update(value),
+            slot => {
+                if let Some((name, data)) = slot.replace((station, A::new(value))) {
+                    map.merge_station(name, data);
+                }
+            }
+        }
     }
+    fn flush(self, map: &mut impl Stations<'a, A>) {
+        for (name, data) in self.slots.into_iter().flatten() {
+            map.merge_station(name, data);
+        }
+    }
+}
 
-    local_map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineLengths {
+    average: usize,
+    max: usize,
+}
+impl LineLengths {
+    // Measures the complete lines of a sample, ignoring a trailing partial line
+    fn detect(sample: &[u8]) -> Option<Self> {
+        let last_newline = sample.iter().rposition(|&b| b == b'\n')?;
+        let lines = sample[..last_newline].split(|&b| b == b'\n');
+        let (count, max) = lines.fold((0, 0), |(count, max), line| (count + 1, max.max(line.len())));
+        Some(LineLengths { average: last_newline.div_ceil(count).max(1), max: max.max(1) })
+    }
 }
 
-pub fn process_file(address: &str) {
-    let max_threads: usize = num_cpus::get();
-    let processing_threads = max_threads * 2;
+/// How the input file is brought into memory for parsing
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Read sequentially into batch buffers that are handed to the workers
+    #[default]
+    Stream,
+    /// Map the file and parse disjoint ranges of the mapping in place, without copying. Fastest
+    /// when the file fits in the page cache. Unavailable with the `no-unsafe` feature.
+    Mmap,
+    /// Split the file into one newline-aligned byte range per worker, each opened, read and
+    /// parsed independently so no single thread does all the reading
+    Partitioned,
+    /// Read ahead with batched asynchronous io_uring reads into registered buffers, keeping
+    /// `ProcessorBuilder::queue_depth` reads in flight while the workers parse. Needs Linux and the
+    /// `io-uring` feature, and is unavailable with the `no-unsafe` feature.
+    IoUring,
+}
 
-    let pool = ThreadPoolBuilder::new()
-        .num_threads(processing_threads)
-        .build()
-        .unwrap();
+/// What happens to lines that are not `station;value`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LinePolicy {
+    /// Abort the run, reporting the line's number and contents
+    #[default]
+    Strict,
+    /// Leave the line out of the results
+    Skip,
+    /// Leave the line out and return it with its number in `Results::invalid_lines`
+    Collect,
+}
+
+/// What happens to input bytes that are not UTF-8
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Utf8Policy {
+    /// Treat their line as malformed, so that under `LinePolicy::Strict` the run fails with
+    /// `ProcessError::InvalidUtf8`, giving where on the line they start
+    #[default]
+    Validate,
+    /// Replace each invalid sequence with U+FFFD and parse the line as usual, so that readings of
+    /// stations with mangled names still count
+    Lossy,
+}
 
-    let results = Arc::new(SegQueue::new());
-    let mut master_map = HashMap::<String, Data>::with_capacity(MAX_UNIQUE_STATIONS);
-    let file = File::open(address).expect("File not found");
-    let mut reader = BufReader::with_capacity((MAX_LINE_LENGTH + 1) * BATCH_SIZE, file);
-    let mut batch = Vec::with_capacity(BATCH_SIZE * (MAX_LINE_LENGTH + 1));
-    let mut remainder = Vec::with_capacity(MAX_LINE_LENGTH + 1);
-    pool.scope(|s: &Scope| {
+#[derive(Debug)]
+struct Config {
+    backend: Backend,
+    line_policy: LinePolicy,
+    utf8_policy: Utf8Policy,
+    #[cfg(not(feature = "no-unsafe"))]
+    assume_utf8: bool,
+    delimiter: char,
+    field_order: FieldOrder,
+    threads: Option<usize>,
+    batch_lines: usize,
+    eof_policy: EofPolicy,
+    end_sentinel: Option<String>,
+    max_lines: Option<u64>,
+    sample: Option<f64>,
+    max_read_mbps: Option<f64>,
+    manifest_path: Option<PathBuf>,
+    diagnostics: bool,
+    max_integer_digits: usize,
+    max_decimals: Option<usize>,
+    station_filter: Option<StationFilter>,
+    avg_line_len: Option<usize>,
+    max_line_len: Option<usize>,
+    detect_line_lengths: bool,
+    buffer_capacity: Option<usize>,
+    expected_stations: usize,
+    max_in_flight_batches: Option<usize>,
+    read_buffers: usize,
+    queue_depth: usize,
+    direct_io: bool,
+    readahead: Option<u64>,
+    inline_threshold: u64,
+    compression: Compression,
+    output: OutputOptions,
+    progress: Option<ProgressHook>,
+    cancellation: Option<CancellationToken>,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            backend: Backend::Stream,
+            line_policy: LinePolicy::Strict,
+            utf8_policy: Utf8Policy::Validate,
+            #[cfg(not(feature = "no-unsafe"))]
+            assume_utf8: false,
+            delimiter: LINE_DELIMITER,
+            field_order: FieldOrder::StationFirst,
+            threads: None,
+            batch_lines: BATCH_SIZE,
+            eof_policy: EofPolicy::Stop,
+            end_sentinel: None,
+            max_lines: None,
+            sample: None,
+            max_read_mbps: None,
+            manifest_path: None,
+            diagnostics: false,
+            max_integer_digits: NARROW_INTEGER_DIGITS,
+            max_decimals: None,
+            station_filter: None,
+            avg_line_len: None,
+            max_line_len: None,
+            detect_line_lengths: false,
+            buffer_capacity: None,
+            expected_stations: MAX_UNIQUE_STATIONS,
+            max_in_flight_batches: None,
+            read_buffers: READ_BUFFERS,
+            queue_depth: QUEUE_DEPTH,
+            direct_io: false,
+            readahead: None,
+            inline_threshold: INLINE_THRESHOLD,
+            compression: Compression::Auto,
+            output: OutputOptions::default(),
+            progress: None,
+            cancellation: None,
+        }
+    }
+}
+impl Config {
+    // Checked when the processor is built
+    fn line_format(&self) -> LineFormat {
+        LineFormat::new(self.delimiter, self.field_order).unwrap_or_default()
+    }
+    fn line_lengths(&self, detected: Option<LineLengths>) -> LineLengths {
+        let defaults = detected.unwrap_or(LineLengths {
+            average: AVERAGE_LINE_LENGTH,
+            max: MAX_LINE_LENGTH + self.max_integer_digits.saturating_sub(NARROW_INTEGER_DIGITS) + self.max_decimals.unwrap_or(1).saturating_sub(1),
+        });
+        LineLengths {
+            average: self.avg_line_len.unwrap_or(defaults.average),
+            max: self.max_line_len.unwrap_or(defaults.max),
+        }
+    }
+    // Bytes read for each batch, enough for `batch_lines` lines of the average length
+    fn read_size(&self, line_lengths: LineLengths) -> usize {
+        self.batch_lines * (line_lengths.average + 1)
+    }
+    fn cancelled(&self) -> bool {
+        self.cancellation.as_ref().is_some_and(CancellationToken::is_cancelled)
+    }
+}
+
+enum Pool {
+    // Started on first use, so that processors only ever given small inputs start no threads.
+    // Should its threads fail to start, the global pool does the work instead.
+    Owned { threads: usize, pool: OnceLock<Option<ThreadPool>> },
+    Shared(Arc<ThreadPool>),
+    Global,
+}
+impl Pool {
+    fn owned(threads: usize) -> Self {
+        Pool::Owned { threads, pool: OnceLock::new() }
+    }
+    fn started(&self) -> Option<&ThreadPool> {
+        match self {
+            Pool::Owned { threads, pool } => pool.get_or_init(|| ThreadPoolBuilder::new().num_threads(*threads).build().ok()).as_ref(),
+            Pool::Shared(pool) => Some(pool),
+            Pool::Global => None,
+        }
+    }
+    fn install<OP: FnOnce() -> R + Send, R: Send>(&self, op: OP) -> R {
+        match self.started() {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+    // Like `rayon::scope`, but on the calling thread rather than one of the pool's
+    fn in_place_scope<'scope, OP: FnOnce(&Scope<'scope>) -> R, R>(&self, op: OP) -> R {
+        match self.started() {
+            Some(pool) => pool.in_place_scope(op),
+            None => rayon::in_place_scope(op),
+        }
+    }
+    fn num_threads(&self) -> usize {
+        match self {
+            Pool::Owned { threads, .. } => *threads,
+            Pool::Shared(pool) => pool.current_num_threads(),
+            Pool::Global => rayon::current_num_threads(),
+        }
+    }
+}
+
+/// Hashes station names unless `ProcessorBuilder::hasher` sets another: FxHash under the `fxhash`
+/// feature, otherwise aHash under `ahash` whatever hashbrown's default becomes, and otherwise
+/// hashbrown's default
+#[cfg(feature = "fxhash")]
+pub type StationHasher = fxhash::FxBuildHasher;
+#[cfg(all(feature = "ahash", not(feature = "fxhash")))]
+pub type StationHasher = ahash::RandomState;
+#[cfg(not(any(feature = "ahash", feature = "fxhash")))]
+pub type StationHasher = hashbrown::hash_map::DefaultHashBuilder;
+
+pub struct ProcessorBuilder<S = StationHasher, A = Data> {
+    config: Config,
+    pool: Option<Pool>,
+    hasher: S,
+    aggregator: PhantomData<fn() -> A>,
+}
+impl Default for ProcessorBuilder {
+    fn default() -> Self {
+        ProcessorBuilder {
+            config: Config::default(),
+            pool: None,
+            hasher: StationHasher::default(),
+            aggregator: PhantomData,
+        }
+    }
+}
+impl<S, A> ProcessorBuilder<S, A> {
+    /// How files passed to `process` and `run` are read. Readers are always streamed.
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.config.backend = backend;
+        self
+    }
+    /// The character between the station and the value, `;` by default. Must be ASCII and not a
+    /// line break.
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.config.delimiter = delimiter;
+        self
+    }
+    /// Whether lines are `station;value`, the default, or `value;station`
+    pub fn field_order(mut self, order: FieldOrder) -> Self {
+        self.config.field_order = order;
+        self
+    }
+    /// Whether malformed lines abort the run, are skipped or are collected into the results
+    pub fn line_policy(mut self, policy: LinePolicy) -> Self {
+        self.config.line_policy = policy;
+        self
+    }
+    /// Whether bytes that are not UTF-8 make their line malformed or are replaced
+    pub fn utf8_policy(mut self, policy: Utf8Policy) -> Self {
+        self.config.utf8_policy = policy;
+        self
+    }
+    /// Skips checking that the input is UTF-8, saving a pass over every batch
+    ///
+    /// # Safety
+    ///
+    /// The input must be UTF-8. Station names are read as `str`, so anything else is undefined
+    /// behaviour.
+    #[cfg(not(feature = "no-unsafe"))]
+    pub unsafe fn assume_utf8(mut self) -> Self {
+        self.config.assume_utf8 = true;
+        self
+    }
+    /// Size of the dedicated thread pool, twice the number of CPUs by default. Ignored when a
+    /// pool is provided.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.config.threads = Some(threads);
+        self
+    }
+    /// Lines parsed per batch. Smaller batches lower peak memory, larger ones cut scheduling
+    /// overhead.
+    pub fn batch_lines(mut self, lines: usize) -> Self {
+        self.config.batch_lines = lines;
+        self
+    }
+    /// Whether a zero-byte read ends the input or is waited out, for pipes fed by producers that
+    /// pause between inputs
+    pub fn eof_policy(mut self, policy: EofPolicy) -> Self {
+        self.config.eof_policy = policy;
+        self
+    }
+    /// Ends the input at the first line equal to `line`, letting a streaming producer finish a run
+    pub fn end_sentinel(mut self, line: impl Into<String>) -> Self {
+        self.config.end_sentinel = Some(line.into());
+        self
+    }
+    /// Aggregates only the first `lines` lines of each input, for a quick look at a large file
+    pub fn max_lines(mut self, lines: u64) -> Self {
+        self.config.max_lines = Some(lines);
+        self
+    }
+    /// Aggregates a random `fraction` of the lines, between 0 and 1. The same input always gives
+    /// the same sample, and error line numbers count only the sampled lines.
+    pub fn sample(mut self, fraction: f64) -> Self {
+        self.config.sample = Some(fraction);
+        self
+    }
+    /// Caps how fast the input is read, in megabytes per second
+    pub fn max_read_mbps(mut self, max_read_mbps: f64) -> Self {
+        self.config.max_read_mbps = Some(max_read_mbps);
+        self
+    }
+    /// Writes a checksum manifest of the input to this path while aggregating. The manifest
+    /// covers every byte of the input, so it cannot be combined with sampling, line limits or an
+    /// end sentinel.
+    pub fn manifest_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.manifest_path = Some(path.into());
+        self
+    }
+    /// Reports per-worker bytes, lines and batch queue-wait times to stderr after each run
+    pub fn diagnostics(mut self, enabled: bool) -> Self {
+        self.config.diagnostics = enabled;
+        self
+    }
+    /// Accepts values with up to this many digits before the decimal point, from 1 to 8. The
+    /// default of 2 covers the challenge range and uses the fixed-window parser.
+    pub fn max_integer_digits(mut self, digits: usize) -> Self {
+        self.config.max_integer_digits = digits;
+        self
+    }
+    /// Accepts values with anything from no decimal places up to `decimals`, like `12` or
+    /// `12.34`, rounding them half away from zero to tenths. By default values need exactly one
+    /// decimal place, which is parsed fastest.
+    pub fn max_decimals(mut self, decimals: usize) -> Self {
+        self.config.max_decimals = Some(decimals);
+        self
+    }
+    /// Aggregates only the stations the filter matches
+    pub fn station_filter(mut self, filter: StationFilter) -> Self {
+        self.config.station_filter = Some(filter);
+        self
+    }
+    /// Expected average line length in bytes, used to size each read
+    pub fn avg_line_len(mut self, length: usize) -> Self {
+        self.config.avg_line_len = Some(length);
+        self
+    }
+    /// Expected longest line in bytes, used to size the batch buffers
+    pub fn max_line_len(mut self, length: usize) -> Self {
+        self.config.max_line_len = Some(length);
+        self
+    }
+    /// Derives any line length hints that were not set from the first 64KB of the input
+    pub fn detect_line_lengths(mut self, enabled: bool) -> Self {
+        self.config.detect_line_lengths = enabled;
+        self
+    }
+    /// Batches the streaming reader may have read ahead of the workers, twice the thread count by
+    /// default. Reading pauses while this many wait to be parsed, so memory for raw input stays
+    /// near this many batch sizes however fast the input arrives.
+    pub fn max_in_flight_batches(mut self, batches: usize) -> Self {
+        self.config.max_in_flight_batches = Some(batches);
+        self
+    }
+    /// Reads `Backend::IoUring` keeps in flight, 32 by default
+    pub fn queue_depth(mut self, reads: usize) -> Self {
+        self.config.queue_depth = reads;
+        self
+    }
+    /// Reads files around the page cache, with O_DIRECT where the file system supports it and
+    /// otherwise by dropping each region from the cache once read, so that a file far larger than
+    /// memory does not evict the working sets of other processes. Linux only, on the stream backend.
+    pub fn direct_io(mut self, enabled: bool) -> Self {
+        self.config.direct_io = enabled;
+        self
+    }
+    /// Asks the OS to prefetch this many bytes ahead of each reader of a file on the stream and
+    /// partitioned backends, beyond the readahead it does for any file read in order. Helps cold
+    /// runs on disks that need deep queues to reach full speed. Linux only, a no-op elsewhere.
+    pub fn readahead(mut self, bytes: u64) -> Self {
+        self.config.readahead = Some(bytes);
+        self
+    }
+    /// Batches the reader thread may have filled while waiting for them to be handed to the pool,
+    /// 2 by default. More smooths over reads that are slow one moment and fast the next.
+    pub fn read_buffers(mut self, batches: usize) -> Self {
+        self.config.read_buffers = batches;
+        self
+    }
+    /// Files smaller than this are parsed on the calling thread in one batch, without starting
+    /// the pool or a reader thread, which would take longer than the parsing. 4 MiB by default,
+    /// 0 to always use the pool. Only applies to the stream backend.
+    pub fn inline_threshold(mut self, bytes: u64) -> Self {
+        self.config.inline_threshold = bytes;
+        self
+    }
+    /// Bytes buffered between the input and the batch reads, the longest batch by default
+    pub fn buffer_capacity(mut self, bytes: usize) -> Self {
+        self.config.buffer_capacity = Some(bytes);
+        self
+    }
+    /// Number of distinct stations the maps are sized for up front, 10000 by default. The maps
+    /// still grow past it, at the cost of rehashing.
+    pub fn expected_stations(mut self, stations: usize) -> Self {
+        self.config.expected_stations = stations;
+        self
+    }
+    /// How files passed to `process` and `run` are compressed, detected from their first bytes by
+    /// default. Compressed files are always streamed.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.config.compression = compression;
+        self
+    }
+    /// Layout of the printed results
+    pub fn format(mut self, format: Format) -> Self {
+        self.config.output.format = format;
+        self
+    }
+    /// Order of the printed stations, by name unless set
+    pub fn sort_by(mut self, column: SortBy) -> Self {
+        self.config.output.sort_by = column;
+        self
+    }
+    /// Prints only the stations with the highest or lowest values of the `sort_by` column
+    pub fn limit(mut self, limit: Limit) -> Self {
+        self.config.output.limit = Some(limit);
+        self
+    }
+    /// Appends each station's number of readings in the text formats, as `name=min/mean/max/count`
+    pub fn with_count(mut self, with_count: bool) -> Self {
+        self.config.output.with_count = with_count;
+        self
+    }
+    /// Calls `hook` with the bytes of input read so far and the total size, when known, as the
+    /// input is read. Compressed files count compressed bytes. The hook runs on the reading
+    /// threads, so it should return quickly.
+    pub fn on_progress<F: Fn(u64, Option<u64>) + Send + Sync + 'static>(mut self, hook: F) -> Self {
+        self.config.progress = Some(ProgressHook(Arc::new(hook)));
+        self
+    }
+    /// Lets `token` stop runs early with partial results. A read blocked waiting for more input,
+    /// as under `EofPolicy::WaitAndRetry`, is not interrupted.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.config.cancellation = Some(token);
+        self
+    }
+    /// Right-aligns each printed number in a column of this many characters
+    pub fn number_width(mut self, width: usize) -> Self {
+        self.config.output.number_width = Some(width);
+        self
+    }
+    /// Hashes station names with `hasher` in both the per-batch maps and the master map
+    pub fn hasher<T: BuildHasher>(self, hasher: T) -> ProcessorBuilder<T, A> {
+        ProcessorBuilder {
+            config: self.config,
+            pool: self.pool,
+            hasher,
+            aggregator: PhantomData,
+        }
+    }
+    /// Accumulates each station's values with `B` instead of `Data`. Printing results needs `B` to
+    /// implement `Columns`, other aggregators are read through `Results`.
+    pub fn aggregator<B: Aggregator>(self) -> ProcessorBuilder<S, B> {
+        ProcessorBuilder {
+            config: self.config,
+            pool: self.pool,
+            hasher: self.hasher,
+            aggregator: PhantomData,
+        }
+    }
+    /// Runs on a caller-owned pool, which can be shared between processors
+    pub fn thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.pool = Some(Pool::Shared(pool));
+        self
+    }
+    /// Runs on the global rayon pool instead of building a dedicated one
+    pub fn global_pool(mut self) -> Self {
+        self.pool = Some(Pool::Global);
+        self
+    }
+    /// Builds the processor. Its own thread pool, unless one was provided, is started by the first
+    /// run that needs it and reused by every later call to `run`.
+    pub fn build(self) -> io::Result<Processor<S, A>> {
+        if !(1..=MAX_INTEGER_DIGITS).contains(&self.config.max_integer_digits) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Integer digits must be between 1 and {MAX_INTEGER_DIGITS}")));
+        }
+        if LineFormat::new(self.config.delimiter, self.config.field_order).is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "The delimiter must be an ASCII character other than a line break"));
+        }
+        if self.config.avg_line_len == Some(0) || self.config.max_line_len == Some(0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Line length hints must be positive"));
+        }
+        if cfg!(feature = "no-unsafe") && self.config.backend == Backend::Mmap {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "Memory maps need unsafe code, which the no-unsafe feature forbids"));
+        }
+        if self.config.backend == Backend::IoUring && !cfg!(all(target_os = "linux", feature = "io-uring", not(feature = "no-unsafe"))) {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "io_uring reads need Linux and the io-uring feature, without no-unsafe"));
+        }
+        if self.config.direct_io && (!cfg!(target_os = "linux") || self.config.backend != Backend::Stream) {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "Direct I/O needs Linux and the stream backend"));
+        }
+        if !(1..=uring::MAX_QUEUE_DEPTH).contains(&self.config.queue_depth) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("The queue depth must be between 1 and {}", uring::MAX_QUEUE_DEPTH)));
+        }
+        if self.config.backend == Backend::Mmap && self.config.max_read_mbps.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Reads from a memory map cannot be rate limited"));
+        }
+        if self.config.sample.is_some_and(|fraction| !(fraction > 0.0 && fraction <= 1.0)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "The sampled fraction must be above 0 and at most 1"));
+        }
+        if !matches!(self.config.backend, Backend::Stream | Backend::IoUring) && (self.config.max_lines.is_some() || self.config.sample.is_some()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Line limits and sampling need the stream backend"));
+        }
+        if self.config.backend != Backend::Stream && (self.config.eof_policy != EofPolicy::Stop || self.config.end_sentinel.is_some()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "End-of-input handling needs the stream backend"));
+        }
+        if self.config.backend == Backend::Partitioned && (self.config.max_read_mbps.is_some() || self.config.manifest_path.is_some()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Rate limits and manifests need a single sequential reader"));
+        }
+        if self.config.manifest_path.is_some() && matches!(self.config.compression, Compression::Gzip | Compression::Zstd) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "A manifest covers the bytes on disk, so it cannot be written for a compressed file"));
+        }
+        // Those read less than the whole input, and the manifest would only cover what they read
+        if self.config.manifest_path.is_some() && (self.config.sample.is_some() || self.config.max_lines.is_some() || self.config.end_sentinel.is_some()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "A manifest covers the whole input, so it cannot be combined with sampling, line limits or an end sentinel"));
+        }
+        if self.config.threads == Some(0) || self.config.batch_lines == 0 || self.config.buffer_capacity == Some(0) || self.config.max_in_flight_batches == Some(0) || self.config.readahead == Some(0) || self.config.read_buffers == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Thread, batch and buffer sizes must be positive"));
+        }
+        let pool = match self.pool {
+            Some(pool) => pool,
+            None => {
+                let max_threads: usize = num_cpus::get();
+                Pool::owned(self.config.threads.unwrap_or(max_threads * 2))
+            }
+        };
+        Ok(Processor {
+            config: self.config,
+            pool,
+            hasher: self.hasher,
+            aggregator: PhantomData,
+        })
+    }
+}
+
+pub struct Processor<S = StationHasher, A = Data> {
+    config: Config,
+    pool: Pool,
+    hasher: S,
+    aggregator: PhantomData<fn() -> A>,
+}
+impl Processor {
+    pub fn builder() -> ProcessorBuilder {
+        ProcessorBuilder::default()
+    }
+}
+impl<S: BuildHasher + Clone + Send + Sync, A: Aggregator> Processor<S, A> {
+
+    /// Aggregates the file and returns the statistics per station. An address of `-` streams
+    /// standard input, whatever the backend, and object store URLs such as
+    /// `s3://bucket/measurements.txt` are streamed with the `object-store` feature.
+    pub fn process(&self, address: &str) -> Result<Results<A>, ProcessError> {
+        let start = Instant::now();
+        let mut manifest = self.config.manifest_path.as_ref().map(|_| Manifest::default());
+        let progress = Progress::new(self.config.progress.as_ref(), input_length(address)?);
+        let parsed = self.aggregate_address(address, manifest.as_mut(), &progress)?;
+        self.finish(parsed, manifest, &progress, start)
+    }
+
+    /// Aggregates several files in parallel and combines them into one set of results. Lines are
+    /// numbered as if the files were concatenated in the given order.
+    pub fn process_files(&self, paths: &[PathBuf]) -> Result<Results<A>, ProcessError> {
+        if let [path] = paths {
+            return self.process(address_of(path)?);
+        }
+        self.process_sources(paths, |_| {})
+    }
+
+    // Aggregates each file on its own and merges them in order, handing each file's batch to
+    // `inspect` before it is merged in
+    fn process_sources(&self, paths: &[PathBuf], mut inspect: impl FnMut(&ParsedBatch<S, A>)) -> Result<Results<A>, ProcessError> {
+        if self.config.manifest_path.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "A manifest covers a single input").into());
+        }
+        let start = Instant::now();
+        let total = paths.iter().map(|path| input_length(address_of(path)?)).sum::<io::Result<Option<u64>>>()?;
+        let progress = Progress::new(self.config.progress.as_ref(), total);
+        let parsed = self.pool.install(|| {
+            paths.par_iter().map(|path| self.aggregate_address(address_of(path)?, None, &progress)).collect::<Vec<_>>()
+        });
+        let mut combined = self.empty_batch();
+        for result in parsed {
+            // An error is reported for the first failing file, after the lines of those before it
+            let lines_before = combined.lines;
+            let batch = result.map_err(|error| error.after_lines(lines_before))?;
+            inspect(&batch);
+            combined = merge_batches(combined, batch);
+        }
+        self.finish(combined, None, &progress, start)
+    }
+
+    fn aggregate_address(&self, address: &str, manifest: Option<&mut Manifest>, progress: &Progress) -> Result<ParsedBatch<S, A>, ProcessError> {
+        if address == STDIN_ADDRESS {
+            let source = compression::decompress(Counted::new(io::stdin(), progress), self.config.compression)?;
+            return self.aggregate(source::wrap(source, &self.config)?, manifest);
+        }
+        if is_url(address) {
+            let source = compression::decompress(Counted::new(open_url(address)?, progress), self.config.compression)?;
+            return self.aggregate(source::wrap(source, &self.config)?, manifest);
+        }
+        let mut file = open_sequential(address)?;
+        match self.config.compression.of_file(&mut file)? {
+            Compression::None => {}
+            // A manifest of the decompressed bytes would never verify against the file
+            _ if manifest.is_some() => return Err(io::Error::new(io::ErrorKind::InvalidInput, "A manifest covers the bytes on disk, so it cannot be written for a compressed file").into()),
+            compression => {
+                let source = match self.config.direct_io {
+                    true => compression::decoder(BufReader::new(Counted::new(DirectReader::open(address)?, progress)), compression)?,
+                    false => compression::decoder(BufReader::new(Counted::new(ReadAhead::new(file, 0, self.config.readahead), progress)), compression)?,
+                };
+                return self.aggregate(source::wrap(source, &self.config)?, manifest);
+            }
+        }
+        match self.config.backend {
+            Backend::Stream if self.inlined(&file)? => self.aggregate_inline(source::wrap(Counted::new(file, progress), &self.config)?, manifest),
+            Backend::Stream if self.config.direct_io => self.aggregate(source::wrap(Counted::new(DirectReader::open(address)?, progress), &self.config)?, manifest),
+            Backend::Stream => self.aggregate(source::wrap(Counted::new(ReadAhead::new(file, 0, self.config.readahead), progress), &self.config)?, manifest),
+            Backend::Mmap => self.aggregate_mapped(&file, manifest, progress),
+            Backend::Partitioned => self.aggregate_partitioned(address, progress),
+            Backend::IoUring => self.aggregate(source::wrap(Counted::new(uring::open(file, self.config.queue_depth)?, progress), &self.config)?, manifest),
+        }
+    }
+
+    /// Aggregates any stream of `station;value` lines, such as a socket, an in-memory buffer or
+    /// a decompressing reader, on the same parallel pipeline as files
+    pub fn process_reader<R: Read + Send>(&self, reader: R) -> Result<Results<A>, ProcessError> {
+        let start = Instant::now();
+        let progress = Progress::new(self.config.progress.as_ref(), None);
+        let source = source::wrap(Counted::new(reader, &progress), &self.config)?;
+        let mut manifest = self.config.manifest_path.as_ref().map(|_| Manifest::default());
+        let parsed = self.aggregate(source, manifest.as_mut())?;
+        self.finish(parsed, manifest, &progress, start)
+    }
+
+    /// Aggregates lines already in memory as a single batch on the calling thread, without a
+    /// reader thread or the pool. Suits small inputs, and measuring the per-batch work alone.
+    pub fn process_slice(&self, lines: &[u8]) -> Result<Results<A>, ProcessError> {
+        let start = Instant::now();
+        let progress = Progress::new(self.config.progress.as_ref(), None);
+        let parsed = self.parse_batch(lines).map_err(|error| error.after_lines(0))?;
+        self.finish(parsed, None, &progress, start)
+    }
+
+    fn finish(&self, parsed: ParsedBatch<S, A>, manifest: Option<Manifest>, progress: &Progress, start: Instant) -> Result<Results<A>, ProcessError> {
+        if let (Some(manifest), Some(path)) = (manifest, &self.config.manifest_path) {
+            manifest.write_to(BufWriter::new(File::create(path)?))?;
+        }
+        let merge_start = Instant::now();
+        let invalid_lines = parsed.invalid_lines.into_iter().map(|(line, contents)| (line + 1, contents)).collect();
+        let results = Results::from_map(parsed.map).with_invalid_lines(invalid_lines);
+        let stats = ProcessStats {
+            // Input read ahead of the last batch dispatched before a cancellation was never parsed
+            bytes_read: if parsed.stats.cancelled { parsed.stats.bytes_read } else { progress.bytes_read() },
+            lines: parsed.lines,
+            merge_time: parsed.stats.merge_time + merge_start.elapsed(),
+            total_time: start.elapsed(),
+            sampled: self.config.max_lines.is_some() || self.config.sample.is_some(),
+            ..parsed.stats
+        };
+        Ok(results.with_stats(stats))
+    }
+
+    fn aggregate<R: Read + Send>(&self, mut source: R, manifest: Option<&mut Manifest>) -> Result<ParsedBatch<S, A>, ProcessError> {
+        let mut sample = Vec::new();
+        let mut read_time = Duration::ZERO;
+        if self.config.detect_line_lengths {
+            let read_start = Instant::now();
+            source.by_ref().take(LINE_SAMPLE_SIZE).read_to_end(&mut sample)?;
+            read_time += read_start.elapsed();
+        }
+        let line_lengths = self.config.line_lengths(LineLengths::detect(&sample));
+        let source = io::Cursor::new(sample).chain(source);
+
+        let results = SegQueue::new();
+        let batch_capacity = self.config.batch_lines * (line_lengths.max + 1);
+        let reader = BufReader::with_capacity(self.config.buffer_capacity.unwrap_or(batch_capacity), source);
+        let records = self.config.diagnostics.then(SegQueue::new);
+        let in_flight = InFlight::new(self.config.max_in_flight_batches.unwrap_or(2 * self.pool.num_threads()));
+        // One map per worker, kept across batches, when the order batches are merged in does not matter
+        let locals = A::ORDER_INDEPENDENT.then(|| (0..self.pool.num_threads()).map(|_| Mutex::new(None)).collect::<Vec<_>>());
+        // Batches filled by the reader thread, whose buffers go back to it once parsed
+        let (filled, ready) = crossbeam::channel::bounded(self.config.read_buffers);
+        let buffers = BufferPool::new(batch_capacity);
+        let (reader_time, cancelled) = thread::scope(|scope| {
+            let reader = scope.spawn(|| self.read_batches(reader, manifest, line_lengths, filled, &buffers));
+            // Dispatched from the calling thread, so that every thread of the pool is left to parse
+            self.pool.in_place_scope(|s| {
+                for (index, batch) in ready.iter().enumerate() {
+                    let (results, records, in_flight, locals, buffers) = (&results, records.as_ref(), &in_flight, locals.as_deref(), &buffers);
+                    in_flight.acquire();
+                    let queued = Instant::now();
+                    s.spawn(move |_| {
+                        let queue_wait = queued.elapsed();
+                        let result = match locals {
+                            Some(locals) => {
+                                let slot = &locals[rayon::current_thread_index().unwrap_or(0) % locals.len()];
+                                let mut local = slot.lock().unwrap_or_else(PoisonError::into_inner);
+                                self.parse_into(&batch, local.get_or_insert_with(|| self.empty_map()))
+                            }
+                            None => self.parse_batch(&batch),
+                        };
+                        if let (Some(records), Ok(parsed)) = (records, &result) {
+                            records.push(BatchRecord::new(batch.len(), parsed.lines, queue_wait));
+                        }
+                        buffers.give(batch);
+                        in_flight.release();
+                        results.push((index, result));
+                    });
+                }
+            });
+            reader.join().map_err(|_| io::Error::other("The reader thread panicked"))?
+        })?;
+        read_time += reader_time;
+        if let Some(records) = records {
+            eprint!("{}", Diagnostics::from_records(records));
+        }
+        let batches = in_order(results.into_iter().collect())?;
+        // Tree-reduce the batches on the pool so the merge tail scales with the thread count. The
+        // reduction keeps adjacent batches together, so line numbers stay in input order.
+        let mut merged = self.pool.install(|| batches.into_par_iter().reduce(|| self.empty_batch(), merge_batches));
+        merged.stats.read_time += read_time;
+        merged.stats.cancelled = cancelled;
+        if let Some(locals) = locals {
+            // The batches only carried line counts, the stations are all in the workers' maps
+            let start = Instant::now();
+            let maps = locals.into_iter().filter_map(|local| local.into_inner().unwrap_or_else(PoisonError::into_inner)).collect::<Vec<_>>();
+            merged.map = self.pool.install(|| maps.into_par_iter().reduce(|| self.empty_map(), merge_maps));
+            merged.stats.merge_time += start.elapsed();
+        }
+        Ok(merged)
+    }
+
+    // Whether the file is small enough to parse on the calling thread. Diagnostics report on the
+    // parallel pipeline, and inputs waited on may grow past any size, so both always take it.
+    fn inlined(&self, file: &File) -> io::Result<bool> {
+        let small = file.metadata()?.len() < self.config.inline_threshold;
+        Ok(small && !self.config.direct_io && !self.config.diagnostics && self.config.eof_policy == EofPolicy::Stop)
+    }
+
+    // Reads the whole source and parses it as a single batch, on the calling thread
+    fn aggregate_inline<R: Read>(&self, mut source: R, manifest: Option<&mut Manifest>) -> Result<ParsedBatch<S, A>, ProcessError> {
+        if self.config.cancelled() {
+            let mut parsed = self.empty_batch();
+            parsed.stats.cancelled = true;
+            return Ok(parsed);
+        }
+        let read_start = Instant::now();
+        let mut bytes = Vec::new();
+        source.read_to_end(&mut bytes)?;
+        let read_time = read_start.elapsed();
+        if let Some(manifest) = manifest {
+            // Segments as long as the stream reader's reads, so both write the same manifest
+            let sample = if self.config.detect_line_lengths { &bytes[..bytes.len().min(LINE_SAMPLE_SIZE as usize)] } else { &[] };
+            let line_lengths = self.config.line_lengths(LineLengths::detect(sample));
+            bytes.chunks(self.config.read_size(line_lengths)).for_each(|segment| manifest.push(segment));
+        }
+        let mut parsed = self.parse_batch(&bytes).map_err(|error| error.after_lines(0))?;
+        parsed.stats.read_time = read_time;
+        Ok(parsed)
+    }
+
+    // Runs on its own thread, reading batches of whole lines into buffers from the pool and
+    // handing them over in input order. Returns the time spent reading and whether the run was
+    // cancelled.
+    fn read_batches<R: Read>(&self, mut reader: R, mut manifest: Option<&mut Manifest>, line_lengths: LineLengths, filled: Sender<Vec<u8>>, buffers: &BufferPool) -> io::Result<(Duration, bool)> {
+        let read_size = self.config.read_size(line_lengths) as u64;
+        let mut read_time = Duration::ZERO;
+        let mut batch = buffers.take();
+        let mut remainder = Vec::with_capacity(line_lengths.max + 1);
         loop {
+            if self.config.cancelled() {
+                return Ok((read_time, true));
+            }
             batch.clear();
             batch.extend_from_slice(&remainder);
             remainder.clear();
-            let bytes_read = reader.by_ref().take((BATCH_SIZE * (AVERAGE_LINE_LENGTH + 1)) as u64).read_to_end(&mut batch).unwrap();
+            let read_start = Instant::now();
+            let bytes_read = reader.by_ref().take(read_size).read_to_end(&mut batch)?;
+            read_time += read_start.elapsed();
             if bytes_read == 0 { // EOF reached
                 break;
             }
-            if let Some(last_newline) = batch.iter().rposition(|&b| b == b'\n') {
-                remainder = batch.split_off(last_newline + 1);
+            if let Some(manifest) = manifest.as_mut() {
+                manifest.push(&batch[batch.len() - bytes_read..]);
             }
-            if !remainder.is_empty() && remainder[0] & 0b1100_0000 == 0b1000_0000 {
-                let mut char_start = remainder.len();
-                while char_start > 0 && remainder[char_start - 1] & 0b1100_0000 == 0b1000_0000 {
-                    char_start -= 1;
+            match memrchr(b'\n', &batch) {
+                Some(last_newline) => {
+                    remainder.extend_from_slice(&batch[last_newline + 1..]);
+                    batch.truncate(last_newline + 1);
                 }
-                let incomplete_char = remainder.split_off(char_start);
-                batch.extend(incomplete_char);
-            }
-            let cloned_results = Arc::clone(&results);
-            s.spawn(move |_| unsafe {
-                let batch_str = String::from_utf8_unchecked(batch);
-                let result = process_batch(&batch_str);
-                cloned_results.push(result);
-            });
-            batch = Vec::with_capacity(BATCH_SIZE * (MAX_LINE_LENGTH + 1));
+                // Reads shorter than a line are carried over until one ends
+                None => {
+                    std::mem::swap(&mut batch, &mut remainder);
+                    continue;
+                }
+            }
+            filled.send(batch).map_err(|_| io::Error::other("Batches stopped being dispatched"))?;
+            batch = buffers.take();
+        }
+        // A final line without a trailing newline is left over once the reader is exhausted
+        if !batch.is_empty() {
+            filled.send(batch).map_err(|_| io::Error::other("Batches stopped being dispatched"))?;
+        }
+        Ok((read_time, false))
+    }
+
+    // Parses one batch as read into a map of its own
+    fn parse_batch(&self, batch: &[u8]) -> Result<ParsedBatch<S, A>, BatchError> {
+        // A batch cannot hold more stations than lines
+        let capacity = self.config.expected_stations.min(self.config.batch_lines);
+        let mut map = HashMap::with_capacity_and_hasher(capacity, self.hasher.clone());
+        let mut parsed = self.parse_into(batch, &mut map)?;
+        parsed.map = map;
+        Ok(parsed)
+    }
+
+    // Parses one batch as read into `map`, timing it. The returned batch only counts its lines.
+    fn parse_into<'b>(&self, batch: &'b [u8], map: &mut impl Stations<'b, A>) -> Result<ParsedBatch<S, A>, BatchError> {
+        let start = Instant::now();
+        let mut parsed = self.parse_bytes(batch, map)?;
+        parsed.stats.bytes_read = batch.len() as u64;
+        parsed.stats.batches = 1;
+        parsed.stats.parse_time = start.elapsed();
+        Ok(parsed)
+    }
+
+    fn parse_bytes<'b>(&self, batch: &'b [u8], map: &mut impl Stations<'b, A>) -> Result<ParsedBatch<S, A>, BatchError> {
+        #[cfg(not(feature = "no-unsafe"))]
+        if self.config.assume_utf8 {
+            // SAFETY: the caller of `ProcessorBuilder::assume_utf8` vouched for the input being UTF-8
+            return self.parse_str(unsafe { std::str::from_utf8_unchecked(batch) }, map);
         }
-    });
-    let results = Arc::try_unwrap(results).expect("Arc still has multiple owners");
-    for local_map in results {
-        for (station, data) in local_map {
-            master_map.entry(station)
-            .and_modify(|master_data| master_data.union(&data))
-            .or_insert(data);
+        // Each pass parses the lines up to the next invalid bytes and then the line holding them,
+        // which is decoded lossily or is malformed. A loop rather than recursion, as a batch may
+        // hold any number of such lines.
+        let (mut parsed, mut rest, policy) = (self.lines_only(), batch, self.config.line_policy);
+        while let Some(chunk) = rest.utf8_chunks().next() {
+            let valid = chunk.valid();
+            if chunk.invalid().is_empty() {
+                let lines = parsed.lines;
+                return Ok(merge_batches(parsed, self.parse_str(valid, map).map_err(|error| error.later(lines))?));
+            }
+            let line_start = memrchr(b'\n', valid.as_bytes()).map_or(0, |newline| newline + 1);
+            let line_end = memchr(b'\n', &rest[valid.len()..]).map_or(rest.len(), |newline| valid.len() + newline + 1);
+            if policy == LinePolicy::Strict && self.config.utf8_policy == Utf8Policy::Validate {
+                let line = parsed.lines + memchr_iter(b'\n', valid.as_bytes()).count() as u64;
+                return Err(BatchError::InvalidUtf8 { line, offset: valid.len() - line_start });
+            }
+            if line_start > 0 {
+                let lines = parsed.lines;
+                parsed = merge_batches(parsed, self.parse_str(&valid[..line_start], map).map_err(|error| error.later(lines))?);
+            }
+            let line = &rest[line_start..line_end];
+            let invalid = match self.config.utf8_policy {
+                Utf8Policy::Lossy => self.parse_lossy(line, map).map_err(|error| error.later(parsed.lines))?,
+                Utf8Policy::Validate => {
+                    let mut invalid = self.lines_only();
+                    invalid.lines = 1;
+                    if policy == LinePolicy::Collect {
+                        invalid.invalid_lines.push((0, String::from_utf8_lossy(line.strip_suffix(b"\n").unwrap_or(line)).into_owned()));
+                    }
+                    invalid
+                }
+            };
+            parsed = merge_batches(parsed, invalid);
+            rest = &rest[line_end..];
+        }
+        Ok(parsed)
+    }
+
+    // Parses a line with each sequence that is not UTF-8 replaced by U+FFFD. The names it decodes
+    // to are not in the batch, so they are gathered in a map of their own first.
+    fn parse_lossy<'b>(&self, line: &[u8], map: &mut impl Stations<'b, A>) -> Result<ParsedBatch<S, A>, BatchError> {
+        let line = String::from_utf8_lossy(line);
+        let mut stations = HashMap::with_hasher(self.hasher.clone());
+        let parsed = self.parse_str(&line, &mut stations)?;
+        for (station, data) in stations {
+            map.merge_owned(station, data);
+        }
+        Ok(parsed)
+    }
+
+    fn parse_str<'b>(&self, batch: &'b str, map: &mut impl Stations<'b, A>) -> Result<ParsedBatch<S, A>, BatchError> {
+        // Without a filter the check compiles away
+        match &self.config.station_filter {
+            None => self.parse_stations(batch, map, |_| true),
+            Some(filter) => self.parse_stations(batch, map, |station| filter.matches(station)),
+        }
+    }
+
+    fn parse_stations<'b>(&self, batch: &'b str, map: &mut impl Stations<'b, A>, keep: impl Fn(&str) -> bool) -> Result<ParsedBatch<S, A>, BatchError> {
+        let (format, policy) = (self.config.line_format(), self.config.line_policy);
+        let (lines, invalid_lines) = match (self.config.max_integer_digits, self.config.max_decimals) {
+            (NARROW_INTEGER_DIGITS, None) => process_batch(batch, map, format, parse_i32, true, &keep, policy)?,
+            (digits, None) => process_batch(batch, map, format, |value| parse_wide_i32(value, digits), false, &keep, policy)?,
+            (digits, Some(decimals)) => process_batch(batch, map, format, |value| parse_decimal(value, digits, decimals), false, &keep, policy)?,
+        };
+        Ok(ParsedBatch { lines, invalid_lines, ..self.lines_only() })
+    }
+
+    fn empty_map(&self) -> HashMap<String, A, S> {
+        HashMap::with_capacity_and_hasher(self.config.expected_stations, self.hasher.clone())
+    }
+
+    fn empty_batch(&self) -> ParsedBatch<S, A> {
+        ParsedBatch::new(self.empty_map())
+    }
+
+    // A batch whose stations went into another map, so its own map never allocates
+    fn lines_only(&self) -> ParsedBatch<S, A> {
+        ParsedBatch::new(HashMap::with_hasher(self.hasher.clone()))
+    }
+}
+impl<S: BuildHasher + Clone + Send + Sync, A: Columns> Processor<S, A> {
+
+    /// Aggregates the file and prints the results to stdout in the configured format. Collected
+    /// invalid lines are listed on stderr.
+    pub fn run(&self, address: &str) -> Result<(), ProcessError> {
+        Ok(self.write_results(&self.process(address)?, stdout())?)
+    }
+
+    /// Aggregates several files like `process_files`, also keeping the challenge values of each
+    /// file on its own, in the order given, to trace an outlying value to the file it came from
+    /// with `Results::extreme_sources`
+    pub fn process_files_by_source(&self, paths: &[PathBuf]) -> Result<(Results<A>, Vec<Results>), ProcessError> {
+        let mut sources = Vec::with_capacity(paths.len());
+        let results = self.process_sources(paths, |batch| {
+            let stations = batch.map.iter().map(|(station, columns)| (station.clone(), *columns.data())).collect::<HashMap<_, _>>();
+            let stats = ProcessStats { lines: batch.lines, ..batch.stats };
+            sources.push(Results::from_map(stations).with_stats(stats));
+        })?;
+        Ok((results, sources))
+    }
+
+    /// Aggregates several files with `process_files` and prints the combined results like `run`
+    pub fn run_files(&self, paths: &[PathBuf]) -> Result<(), ProcessError> {
+        Ok(self.write_results(&self.process_files(paths)?, stdout())?)
+    }
+
+    /// Writes results to any sink in the configured format, listing collected invalid lines on
+    /// stderr as `run` does
+    pub fn write_results<W: Write>(&self, results: &Results<A>, writer: W) -> io::Result<()> {
+        for (line_no, contents) in results.invalid_lines() {
+            eprintln!("Invalid line {line_no}: {contents:?}");
         }
+        write_results(results, self.config.output, writer)
     }
-    let mut stations = master_map.keys().collect::<Vec<_>>();
-    stations.sort_unstable();
+}
 
-    let writer_capacity: usize = stations.len() * (AVERAGE_STATION_LENGTH + 21);
+// Slots for batches read but not yet parsed. One is taken before a batch is handed to the pool,
+// so at most this many batch buffers are alive besides those the reader thread is filling.
+struct InFlight {
+    release: Sender<()>,
+    acquire: Receiver<()>,
+}
+impl InFlight {
+    fn new(slots: usize) -> Self {
+        let (release, acquire) = crossbeam::channel::bounded(slots);
+        let in_flight = InFlight { release, acquire };
+        (0..slots).for_each(|_| in_flight.release());
+        in_flight
+    }
+    fn acquire(&self) {
+        while self.acquire.try_recv().is_err() {
+            // The dispatcher may be the pool's only thread, so it parses queued batches itself while
+            // waiting, and otherwise sleeps until a worker frees a slot
+            if !matches!(rayon::yield_now(), Some(rayon::Yield::Executed)) && self.acquire.recv_timeout(Duration::from_millis(1)).is_ok() {
+                return;
+            }
+        }
+    }
+    fn release(&self) {
+        // Never full, as only slots that were taken are given back
+        let _ = self.release.try_send(());
+    }
+}
 
-    let num_stations = stations.len();
-    let mut stations_iter = stations.into_iter();
-    let mut stdout = BufWriter::with_capacity(writer_capacity, stdout());
-    write!(stdout, "{{").unwrap();
-    for station in stations_iter.by_ref().take(num_stations - 1) {
-        write!(stdout, "{}={}, ", station, master_map[station]).unwrap();
+// Batch buffers given back once parsed, for the reader to fill again. A run only allocates as many
+// as are alive at once: those in flight, those filled ahead, and the one being filled.
+struct BufferPool {
+    free: SegQueue<Vec<u8>>,
+    capacity: usize,
+    allocated: AtomicUsize,
+}
+impl BufferPool {
+    fn new(capacity: usize) -> Self {
+        BufferPool { free: SegQueue::new(), capacity, allocated: AtomicUsize::new(0) }
+    }
+    fn take(&self) -> Vec<u8> {
+        self.free.pop().unwrap_or_else(|| {
+            self.allocated.fetch_add(1, Ordering::Relaxed);
+            Vec::with_capacity(self.capacity)
+        })
     }
-    if let Some(station) = stations_iter.next() {
-        write!(stdout, "{}={}", station, master_map[station]).unwrap();
+    fn give(&self, mut buffer: Vec<u8>) {
+        buffer.clear();
+        self.free.push(buffer);
     }
-    writeln!(stdout, "}}").unwrap();
-    stdout.flush().unwrap();
+}
+
+// A batch's position in the input and its outcome
+type BatchResult<S, A> = (usize, Result<ParsedBatch<S, A>, BatchError>);
+
+// Batches finish out of order, so to number a failing line the lines of every earlier batch are counted
+fn in_order<S, A>(mut results: Vec<BatchResult<S, A>>) -> Result<Vec<ParsedBatch<S, A>>, ProcessError> {
+    results.sort_unstable_by_key(|(index, _)| *index);
+    let mut lines_before = 0;
+    results.into_iter().map(|(_, result)| {
+        let parsed = result.map_err(|error| error.after_lines(lines_before))?;
+        lines_before += parsed.lines;
+        Ok(parsed)
+    }).collect()
+}
+
+// Length of the input for progress reports, unknown for standard input and URLs
+fn input_length(address: &str) -> io::Result<Option<u64>> {
+    match address {
+        STDIN_ADDRESS => Ok(None),
+        address if is_url(address) => Ok(None),
+        address => Ok(Some(fs::metadata(address)?.len())),
+    }
+}
+
+fn is_url(address: &str) -> bool {
+    HTTP_SCHEMES.iter().chain(OBJECT_STORE_SCHEMES).any(|scheme| address.starts_with(scheme))
+}
+
+// Streams an input from the network, by its URL scheme
+fn open_url(url: &str) -> io::Result<Box<dyn Read + Send>> {
+    if HTTP_SCHEMES.iter().any(|scheme| url.starts_with(scheme)) {
+        #[cfg(feature = "http")]
+        return Ok(Box::new(ParallelHttpReader::open(url)?));
+        #[cfg(not(feature = "http"))]
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "Reading from URLs needs the http feature"));
+    }
+    #[cfg(feature = "object-store")]
+    return Ok(Box::new(ObjectReader::open(url)?));
+    #[cfg(not(feature = "object-store"))]
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Reading from object stores needs the object-store feature"))
+}
+
+fn address_of(path: &Path) -> io::Result<&str> {
+    path.to_str().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Path is not UTF-8: {}", path.display())))
+}
+
+fn merge_maps<S: BuildHasher, A: Aggregator>(mut a: HashMap<String, A, S>, mut b: HashMap<String, A, S>) -> HashMap<String, A, S> {
+    // Fold the smaller map into the larger one, keeping `a`'s values first in each merge
+    let swapped = a.len() < b.len();
+    if swapped {
+        std::mem::swap(&mut a, &mut b);
+    }
+    for (station, data) in b {
+        match a.entry(station) {
+            Entry::Occupied(mut entry) if swapped => {
+                let later = std::mem::replace(entry.get_mut(), data);
+                entry.get_mut().merge(later);
+            }
+            Entry::Occupied(mut entry) => entry.get_mut().merge(data),
+            Entry::Vacant(entry) => {
+                entry.insert(data);
+            }
+        }
+    }
+    a
+}
+
+pub fn process_file_to_results(address: &str) -> Result<Results, ProcessError> {
+    Processor::builder().build()?.process(address)
+}
+
+pub fn process_reader<R: Read + Send>(reader: R) -> Result<Results, ProcessError> {
+    Processor::builder().build()?.process_reader(reader)
+}
+
+pub fn process_file(address: &str) -> Result<(), ProcessError> {
+    process_file_to_writer(address, stdout())
+}
+
+pub fn process_file_to_writer<W: Write>(address: &str, writer: W) -> Result<(), ProcessError> {
+    let results = process_file_to_results(address)?;
+    Ok(write_results(&results, OutputOptions::default(), writer)?)
 }
 
 #[cfg(test)]
 mod tests {
 
+    use std::io::Write;
+    use tempfile::NamedTempFile;
     use super::*;
 
+    // The backends available in this build
+    fn backends() -> &'static [Backend] {
+        if cfg!(feature = "no-unsafe") { &[Backend::Stream, Backend::Partitioned] } else { &[Backend::Stream, Backend::Mmap, Backend::Partitioned] }
+    }
+
+    // A temporary file holding the input, removed when dropped
+    fn measurements(input: impl AsRef<[u8]>) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(input.as_ref()).unwrap();
+        file
+    }
+
+    fn aggregate(input: &[u8]) -> Result<HashMap<String, Data, StationHasher>, ProcessError> {
+        Ok(Processor::builder().build().unwrap().aggregate(input, None)?.map)
+    }
+
     #[test]
-    fn test_parse_i32() {
-        assert_eq!(parse_i32("-12.3"), -123);
-        assert_eq!(parse_i32("12.3"), 123);
-        assert_eq!(parse_i32("-1.3"), -13);
-        assert_eq!(parse_i32("2.3"), 23);
-        assert_eq!(parse_i32("-0.3"), -3);
-        assert_eq!(parse_i32("0.3"), 3);
+    fn test_official_rounding() {
+        let data = |values: &[i32]| {
+            let mut data = Data::new(values[0]);
+            values[1..].iter().for_each(|&value| data.update(value));
+            data.to_string()
+        };
+        // Ties round up, towards positive infinity
+        assert_eq!(data(&[1, 2]), "0.1/0.2/0.2");
+        assert_eq!(data(&[-1, -2]), "-0.2/-0.1/-0.1");
+        assert_eq!(data(&[146, 147, 147, 147]), "14.6/14.7/14.7");
+        assert_eq!(data(&[-5, 10]), "-0.5/0.3/1.0");
+        // A mean that rounds to zero is printed without a sign
+        assert_eq!(data(&[-1, 0, 0]), "-0.1/0.0/0.0");
+        assert_eq!(data(&[-999, -999]), "-99.9/-99.9/-99.9");
+        let mut padded = Data::new(-1);
+        padded.update(0);
+        padded.update(0);
+        assert_eq!(format!("{padded:>5}"), " -0.1/  0.0/  0.0");
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_skewed_sum_does_not_overflow() {
+        // 2^30 readings of 99.9 at one station, built by doubling rather than parsing
+        let mut data = Data::new(999);
+        for _ in 0..30 {
+            let copy = data;
+            data.merge(copy);
+        }
+        assert_eq!(data.count, 1 << 30);
+        assert!(data.sum > i32::MAX as i64);
+        assert_eq!(data.to_string(), "99.9/99.9/99.9");
+        let mut merged = merge_maps(HashMap::from([("Hot".to_string(), data)]), HashMap::from([("Hot".to_string(), Data::new(-999))]));
+        assert_eq!(merged.remove("Hot").unwrap().mean_tenths(), 999);
+    }
+
+    #[test]
+    fn test_wide_values() {
+        let processor = Processor::builder().max_integer_digits(4).build().unwrap();
+        let map = processor.aggregate(&b"Everest;8848.9\nEverest;-123.4\n"[..], None).unwrap().map;
+        assert_eq!((map["Everest"].min, map["Everest"].max), (-1234, 88489));
+        assert!(aggregate(b"Everest;8848.9\n").is_err());
+        assert!(Processor::builder().max_integer_digits(9).build().is_err());
+    }
+
+    #[test]
+    fn test_max_decimals() {
+        let processor = Processor::builder().max_decimals(2).build().unwrap();
+        let results = processor.process_reader(&b"Hamburg;12\nHamburg;-3.45\nHamburg;7.5\n"[..]).unwrap();
+        assert_eq!(results.get("Hamburg").unwrap().to_string(), "-3.5/5.3/12.0");
+        assert!(processor.process_reader(&b"Hamburg;1.234\n"[..]).is_err());
+        assert!(aggregate(b"Hamburg;12\n").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let results = aggregate(b"Hamburg;12.0\nHamburg;-3.4\n").unwrap();
+        let json = serde_json::to_string(&results["Hamburg"]).unwrap();
+        assert_eq!(json, r#"{"sum":86,"count":2,"min":-34,"max":120}"#);
+        let data: Data = serde_json::from_str(&json).unwrap();
+        assert_eq!(data.to_string(), "-3.4/4.3/12.0");
+    }
+
+    #[test]
+    fn test_line_format() {
+        let processor = Processor::builder().delimiter(',').field_order(FieldOrder::ValueFirst).build().unwrap();
+        let results = processor.process_reader(&b"12.0,Hamburg\n8.9,Bulawayo, Zimbabwe\n-3.4,Hamburg\n"[..]).unwrap();
+        assert_eq!(results.get("Hamburg").unwrap().to_string(), "-3.4/4.3/12.0");
+        assert_eq!(results.get("Bulawayo, Zimbabwe").unwrap().count(), 1);
+        assert!(processor.process_reader(&b"Hamburg;12.0\n"[..]).is_err());
+        assert!(Processor::builder().delimiter('\n').build().is_err());
+    }
+
+    #[test]
+    fn test_detect_line_lengths() {
+        let detected = LineLengths::detect(b"Hamburg;12.0\nBulawayo;8.9\nPartial;1");
+        assert_eq!(detected, Some(LineLengths { average: 13, max: 12 }));
+        assert_eq!(LineLengths::detect(b"No newline"), None);
+        let processor = Processor::builder().detect_line_lengths(true).build().unwrap();
+        let map = processor.aggregate(&b"Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n"[..], None).unwrap().map;
+        assert_eq!(map["Hamburg"].count, 2);
+    }
+
+    #[test]
+    fn test_process_batch_custom_hasher() {
+        let hasher = std::collections::hash_map::RandomState::new();
+        let mut map = HashMap::<String, Data, _>::with_hasher(hasher);
+        process_batch("Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n", &mut map, LineFormat::default(), parse_i32, true, |_| true, LinePolicy::Strict).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["Hamburg"].count, 2);
+        assert_eq!(map["Hamburg"].min, -34);
+        assert_eq!(map["Bulawayo"].sum, 89);
+    }
+
+    #[test]
+    fn test_adversarial_inputs_do_not_panic() {
+        let inputs: [&[u8]; 12] = [
+            b"", b"\n", b"\n\n", b";", b"Hamburg\n", b"Hamburg;\n", b"Hamburg;1.\n",
+            b"Hamburg;12.34\n", b"Hamburg;1.0;\n", "Zürich;1é\n".as_bytes(), "é".as_bytes(), b";;;\n;;\n",
+        ];
+        for input in inputs {
+            let _ = aggregate(input);
+        }
+        let mut output = Vec::new();
+        write_results(&Results::from_map(aggregate(b"").unwrap()), OutputOptions::default(), &mut output).unwrap();
+        assert_eq!(output, b"{}\n");
+    }
+
+    #[test]
+    fn test_reused_pools() {
+        let pool = Arc::new(ThreadPoolBuilder::new().num_threads(2).build().unwrap());
+        let shared = Processor::builder().thread_pool(Arc::clone(&pool)).build().unwrap();
+        let global = Processor::builder().global_pool().build().unwrap();
+        for processor in [&shared, &global, &shared] {
+            let map = processor.aggregate(&b"Hamburg;12.0\nHamburg;-1.0\n"[..], None).unwrap().map;
+            assert_eq!(map["Hamburg"].sum, 110);
+        }
+    }
+
+    #[test]
+    fn test_buffer_pool() {
+        let input = "Hamburg;12.0\nBulawayo;8.9\nZürich;-1.0\n".repeat(2_000);
+        let processor = Processor::builder().batch_lines(10).avg_line_len(1).build().unwrap();
+        let line_lengths = processor.config.line_lengths(None);
+        let buffers = BufferPool::new(64);
+        let (filled, ready) = crossbeam::channel::bounded(1);
+        let mut read = Vec::new();
+        thread::scope(|scope| {
+            let reader = scope.spawn(|| processor.read_batches(input.as_bytes(), None, line_lengths, filled, &buffers));
+            for batch in ready {
+                assert!(batch.ends_with(b"\n"));
+                read.extend_from_slice(&batch);
+                buffers.give(batch);
+            }
+            reader.join().unwrap().unwrap();
+        });
+        assert_eq!(read, input.as_bytes());
+        // One being filled, one waiting in the channel, one blocked on sending and one just received
+        assert!(buffers.allocated.load(Ordering::Relaxed) <= 4, "{:?}", buffers.allocated);
+    }
+
+    #[test]
+    fn test_sizing_options() {
+        let processor = Processor::builder().buffer_capacity(7).expected_stations(1).batch_lines(2).build().unwrap();
+        let map = processor.aggregate(&b"Hamburg;12.0\nBulawayo;8.9\nHamburg;-1.0\nZurich;3.0\n"[..], None).unwrap().map;
+        assert_eq!((map.len(), map["Hamburg"].sum), (3, 110));
+        // Reads shorter than a line
+        let processor = Processor::builder().batch_lines(1).avg_line_len(1).build().unwrap();
+        let map = processor.aggregate(&b"Hamburg;12.0\nBulawayo;8.9\n"[..], None).unwrap().map;
+        assert_eq!((map.len(), map["Bulawayo"].sum), (2, 89));
+        assert!(Processor::builder().buffer_capacity(0).build().is_err());
+    }
+
+    #[test]
+    fn test_max_in_flight_batches() {
+        let input = "Hamburg;12.0\nBulawayo;8.9\nHamburg;-1.0\n".repeat(500);
+        // A single thread both reads and parses, so a full window must not stall it
+        for (threads, slots, buffers) in [(1, 1, 1), (1, 3, 2), (4, 1, 1), (4, 2, 8)] {
+            let processor = Processor::builder().threads(threads).max_in_flight_batches(slots).read_buffers(buffers).batch_lines(7).avg_line_len(1).build().unwrap();
+            let map = processor.aggregate(input.as_bytes(), None).unwrap().map;
+            assert_eq!((map["Hamburg"].count, map["Hamburg"].sum, map["Bulawayo"].sum), (1_000, 55_000, 44_500));
+        }
+        let in_flight = InFlight::new(2);
+        in_flight.acquire();
+        in_flight.acquire();
+        assert!(in_flight.acquire.is_empty());
+        in_flight.release();
+        assert_eq!(in_flight.acquire.len(), 1);
+        assert!(Processor::builder().max_in_flight_batches(0).build().is_err());
+        assert!(Processor::builder().read_buffers(0).build().is_err());
+    }
+
+    // Naive fold over the lines giving (min, max, sum, count) per station
+    fn reference(input: &str) -> Vec<(String, (i32, i32, i64, u32))> {
+        process_reader_reference(input.as_bytes()).unwrap().iter()
+            .map(|(station, data)| (station.to_string(), (data.min, data.max, data.sum, data.count)))
+            .collect()
+    }
+
+    #[test]
+    fn test_fault_injected_reads() {
+        const NAMES: [&str; 5] = ["東京", "Zürich", "🌍 Earth", "Абакан", "Hamburg"];
+        let read_size = BATCH_SIZE * 2;
+        let mut split_mid_character = false;
+        for shift in 0..12 {
+            let mut input = format!("{};0.0\n", "x".repeat(shift + 1));
+            let mut seed = 42u32;
+            while input.len() < read_size + 100_000 {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                let value = (seed >> 16) as i32 % 1999 - 999;
+                input += &format!("{}{};{:.1}\n", NAMES[(seed % 5) as usize], NAMES[(seed >> 8) as usize % 5], value as f64 / 10.0);
+            }
+            split_mid_character |= !input.is_char_boundary(read_size);
+
+            let processor = Processor::builder().avg_line_len(1).build().unwrap();
+            let map = processor.aggregate(source::FaultInjector::new(input.as_bytes()), None).unwrap().map;
+            let expected = reference(&input);
+            assert_eq!(map.len(), expected.len());
+            for (station, (min, max, sum, count)) in expected {
+                let data = &map[&station];
+                assert_eq!((data.min, data.max, data.sum, data.count), (min, max, sum, count), "{station}");
+            }
+        }
+        assert!(split_mid_character);
+    }
+
+    #[test]
+    fn test_process_reader() {
+        let input = io::Cursor::new("Hamburg;12.0\nBulawayo;8.9\n").chain(&b"Hamburg;-3.4\n"[..]);
+        let results = process_reader(input).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results.get("Hamburg").map(Data::min), Some(-3.4));
+    }
+
+    #[test]
+    fn test_process_file_to_writer() {
+        let file = measurements("Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n");
+        let mut output = Vec::new();
+        process_file_to_writer(file.path().to_str().unwrap(), &mut output).unwrap();
+        assert_eq!(output, b"{Bulawayo=8.9/8.9/8.9, Hamburg=-3.4/4.3/12.0}\n");
+    }
+
+    #[test]
+    fn test_final_line_without_newline() {
+        let map = aggregate(b"Hamburg;12.0\nBulawayo;8.9").unwrap();
+        assert_eq!(map["Bulawayo"].sum, 89);
+        assert_eq!(aggregate("Zürich;-5.5".as_bytes()).unwrap()["Zürich"].min, -55);
+    }
+
+    #[test]
+    fn test_empty_and_unterminated_files() {
+        let file = NamedTempFile::new().unwrap();
+        let (path, address) = (file.path(), file.path().to_str().unwrap());
+        for &backend in backends() {
+            let processor = Processor::builder().backend(backend).batch_lines(1).avg_line_len(1).build().unwrap();
+            std::fs::write(path, "Hamburg;12.0\nBulawayo;-8.9").unwrap();
+            assert_eq!(processor.process(address).unwrap().get("Bulawayo").map(Data::min), Some(-8.9), "{backend:?}");
+            std::fs::write(path, "").unwrap();
+            let results = processor.process(address).unwrap();
+            for (format, expected) in [(Format::Text, "{}\n"), (Format::Lines, ""), (Format::Json, "{}\n"), (Format::Csv, "station,min,mean,max,count\n")] {
+                let mut output = Vec::new();
+                write_results(&results, OutputOptions { format, ..Default::default() }, &mut output).unwrap();
+                assert_eq!(String::from_utf8(output).unwrap(), expected, "{backend:?}, {format:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_crlf_and_blank_lines() {
+        let map = aggregate(b"Hamburg;12.0\r\n\r\nBulawayo;8.9\r\nHamburg;-3.4\r\n\n\r\n").unwrap();
+        assert_eq!((map["Hamburg"].min, map["Hamburg"].max, map["Bulawayo"].sum), (-34, 120, 89));
+        let result = aggregate(b"Hamburg;12.0\r\n\r\nbad\r\n");
+        assert!(matches!(result, Err(ProcessError::MalformedLine { line_no: 3, contents }) if contents == "bad"));
+    }
+
+    #[test]
+    fn test_error_positions() {
+        // Two-line batches, so the failing lines are found by workers after the first batch
+        let processor = Processor::builder().batch_lines(2).avg_line_len(5).build().unwrap();
+        let result = processor.aggregate(&b"a;1.0\nb;2.0\nc;3.0\nd;4.0\nbad\ne;5.0\n"[..], None);
+        assert!(matches!(result, Err(ProcessError::MalformedLine { line_no: 5, contents }) if contents == "bad"));
+        let result = processor.aggregate(&b"a;1.0\nb;2.0\nc;3.0\n\xff;4.0\n"[..], None);
+        assert!(matches!(result, Err(ProcessError::InvalidUtf8 { line_no: 4, offset: 0 })));
+        assert!(matches!(process_file_to_results("/nonexistent/measurements.txt"), Err(ProcessError::Io(_))));
+    }
+
+    #[test]
+    fn test_line_policies() {
+        let input = b"a;1.0\nb;2.0\nbad\nc;3.0\n\xff;4.0\nd;5.0\ne;\n";
+        let processor = |policy| Processor::builder().line_policy(policy).batch_lines(2).avg_line_len(5).build().unwrap();
+        assert!(processor(LinePolicy::Strict).aggregate(&input[..], None).is_err());
+        let skipped = processor(LinePolicy::Skip).aggregate(&input[..], None).unwrap();
+        assert_eq!((skipped.map.len(), skipped.invalid_lines.len()), (4, 0));
+        let results = processor(LinePolicy::Collect).process_reader(&input[..]).unwrap();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results.invalid_lines(), [(3, "bad".to_string()), (5, "\u{FFFD};4.0".to_string()), (7, "e;".to_string())]);
+    }
+
+    #[test]
+    fn test_many_invalid_utf8_lines() {
+        // Thousands of Latin-1 lines in one batch, every other line
+        let input = b"M\xfcnchen;1.0\nHamburg;2.0\n".repeat(10_000);
+        let processor = |policy| Processor::builder().line_policy(policy).build().unwrap();
+        let result = processor(LinePolicy::Strict).process_slice(&input);
+        assert!(matches!(result, Err(ProcessError::InvalidUtf8 { line_no: 1, offset: 1 })));
+        let results = processor(LinePolicy::Skip).process_slice(&input).unwrap();
+        assert_eq!((results.stats().lines, results.get("Hamburg").map(|data| data.count()), results.len()), (20_000, Some(10_000), 1));
+        let results = processor(LinePolicy::Collect).process_slice(&input).unwrap();
+        assert_eq!(results.invalid_lines().len(), 10_000);
+        assert_eq!(results.invalid_lines()[9_999], (19_999, "M\u{FFFD}nchen;1.0".to_string()));
+    }
+
+    #[test]
+    fn test_utf8_policies() {
+        let input = b"a;1.0\nb\xffc;2.0\na\xfe\xfd;3.0\nb\xffc;-4.0\nd;5.0\n";
+        let processor = |policy| Processor::builder().utf8_policy(policy).batch_lines(2).avg_line_len(5).build().unwrap();
+        let result = processor(Utf8Policy::Validate).aggregate(&input[..], None);
+        assert!(matches!(result, Err(ProcessError::InvalidUtf8 { line_no: 2, offset: 1 })));
+        let results = processor(Utf8Policy::Lossy).process_reader(&input[..]).unwrap();
+        assert_eq!(results.to_string(), "{a=1.0/1.0/1.0, a\u{FFFD}\u{FFFD}=3.0/3.0/3.0, b\u{FFFD}c=-4.0/-1.0/2.0, d=5.0/5.0/5.0}");
+        // Lines are still counted from the start of the input past a lossily decoded one
+        let result = processor(Utf8Policy::Lossy).aggregate(&b"a\xff;1.0\nb;2.0\nbad\n"[..], None);
+        assert!(matches!(result, Err(ProcessError::MalformedLine { line_no: 3, .. })));
+        // Thousands of lossily decoded lines in one batch, the last of them malformed
+        let mut many = b"M\xfcnchen;1.0\nHamburg;2.0\n".repeat(5_000);
+        let results = processor(Utf8Policy::Lossy).process_slice(&many).unwrap();
+        assert_eq!((results.stats().lines, results.get("M\u{FFFD}nchen").map(|data| data.count())), (10_000, Some(5_000)));
+        many.extend_from_slice(b"M\xfcnchen\n");
+        let result = processor(Utf8Policy::Lossy).process_slice(&many);
+        assert!(matches!(result, Err(ProcessError::MalformedLine { line_no: 10_001, .. })));
+        #[cfg(not(feature = "no-unsafe"))]
+        {
+            // SAFETY: the input is UTF-8
+            let trusted = unsafe { Processor::builder().assume_utf8() }.build().unwrap();
+            assert_eq!(trusted.process_reader(&b"a;1.0\nb;2.0\n"[..]).unwrap().len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_process_files() {
+        let files = ["a;1.0\nb;2.0\n", "a;3.0\nbad\n", "c;-1.0"].map(measurements);
+        let paths = files.each_ref().map(|file| file.path().to_path_buf());
+        let processor = |policy| Processor::builder().line_policy(policy).build().unwrap();
+        let results = processor(LinePolicy::Collect).process_files(&paths).unwrap();
+        assert_eq!(results.iter().map(|(station, data)| (station, data.count)).collect::<Vec<_>>(), [("a", 2), ("b", 1), ("c", 1)]);
+        assert_eq!(results.invalid_lines(), [(4, "bad".to_string())]);
+        let result = processor(LinePolicy::Strict).process_files(&paths);
+        assert!(matches!(result, Err(ProcessError::MalformedLine { line_no: 4, .. })));
+    }
+
+    #[test]
+    fn test_sampling() {
+        let input = (0..10_000).map(|line| format!("s{};{}.0\n", line % 10, line % 100)).collect::<String>();
+        let results = Processor::builder().max_lines(25).batch_lines(7).build().unwrap().process_reader(input.as_bytes()).unwrap();
+        assert_eq!((results.stats().lines, results.stats().sampled), (25, true));
+        assert_eq!(results.get("s4").unwrap().max(), 24.0);
+        let results = Processor::builder().sample(0.01).build().unwrap().process_reader(input.as_bytes()).unwrap();
+        assert!((50..150).contains(&results.stats().lines));
+        assert!(!Processor::builder().build().unwrap().process_reader(input.as_bytes()).unwrap().stats().sampled);
+        assert!(Processor::builder().sample(0.0).build().is_err());
+        assert!(Processor::builder().backend(Backend::Partitioned).max_lines(1).build().is_err());
+    }
+
+    #[test]
+    fn test_manifest_of_sampled_runs() {
+        let file = measurements((0..10_000).map(|line| format!("s{};{}.0\n", line % 10, line % 100)).collect::<String>());
+        let (path, manifest) = (file.path(), NamedTempFile::new().unwrap());
+        let manifest_path = manifest.path();
+        let builder = || Processor::builder().manifest_path(manifest_path).batch_lines(100);
+        for sampled in [builder().sample(0.5), builder().max_lines(10), builder().end_sentinel("s3;3.0")] {
+            assert_eq!(sampled.build().err().map(|error| error.kind()), Some(io::ErrorKind::InvalidInput));
+        }
+        // Written over the whole input, the manifest verifies against it
+        builder().build().unwrap().process(path.to_str().unwrap()).unwrap();
+        let manifest = Manifest::read_from(File::open(manifest_path).unwrap()).unwrap();
+        assert_eq!(verify_manifest(path.to_str().unwrap(), &manifest).unwrap(), []);
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let input = "Hamburg;12.0\n".repeat(10_000);
+        let file = measurements(&input);
+        for &backend in backends() {
+            let token = CancellationToken::new();
+            let hook = { let token = token.clone(); move |read, _| if read > 10_000 { token.cancel() } };
+            let processor = Processor::builder().backend(backend).threads(2).batch_lines(10).inline_threshold(0).cancellation(token).on_progress(hook).build().unwrap();
+            let results = processor.process(file.path().to_str().unwrap()).unwrap();
+            let stats = results.stats();
+            assert!(stats.cancelled && stats.lines < 10_000, "{backend:?}");
+            assert_eq!(stats.bytes_read, stats.lines * 13, "{backend:?}");
+            assert_eq!(results.get("Hamburg").map_or(0, |data| data.count() as u64), stats.lines, "{backend:?}");
+        }
+        let token = CancellationToken::new();
+        token.cancel();
+        let results = Processor::builder().cancellation(token).build().unwrap().process_reader(input.as_bytes()).unwrap();
+        assert!(results.stats().cancelled && results.is_empty());
+        assert!(!Processor::builder().build().unwrap().process_reader(input.as_bytes()).unwrap().stats().cancelled);
+    }
+
+    #[test]
+    fn test_inline_small_inputs() {
+        let input = "Hamburg;12.0\nBulawayo;8.9\n".repeat(500) + "bad\nHamburg;-3.4";
+        let file = measurements(&input);
+        let address = file.path().to_str().unwrap();
+        let inline = Processor::builder().line_policy(LinePolicy::Collect).batch_lines(10).build().unwrap().process(address).unwrap();
+        let pooled = Processor::builder().line_policy(LinePolicy::Collect).batch_lines(10).inline_threshold(0).build().unwrap().process(address).unwrap();
+        let strict = Processor::builder().batch_lines(10).build().unwrap().process(address);
+        assert_eq!(inline.to_string(), pooled.to_string());
+        assert_eq!(inline.invalid_lines(), pooled.invalid_lines());
+        assert_eq!((inline.stats().batches, inline.stats().lines, inline.stats().bytes_read), (1, 1002, input.len() as u64));
+        assert!(pooled.stats().batches > 1);
+        assert!(matches!(strict, Err(ProcessError::MalformedLine { line_no: 1001, .. })));
+    }
+
+    #[test]
+    fn test_process_slice() {
+        let input = "Hamburg;12.0\nBulawayo;8.9\n".repeat(500) + "bad\nHamburg;-3.4";
+        let processor = |policy| Processor::builder().line_policy(policy).batch_lines(10).build().unwrap();
+        let sliced = processor(LinePolicy::Collect).process_slice(input.as_bytes()).unwrap();
+        let streamed = processor(LinePolicy::Collect).process_reader(input.as_bytes()).unwrap();
+        assert_eq!(sliced.to_string(), streamed.to_string());
+        assert_eq!(sliced.invalid_lines(), streamed.invalid_lines());
+        let strict = processor(LinePolicy::Strict).process_slice(input.as_bytes());
+        assert!(matches!(strict, Err(ProcessError::MalformedLine { line_no: 1001, .. })));
+    }
+
+    #[test]
+    fn test_invalid_utf8_across_reads() {
+        // A partial line starting with a continuation byte stays whole when carried over, however
+        // the reads fall
+        let input = b"a;1.0\n\x94a\xa9\nb;2.0\n\x94a\xa9";
+        let whole = Processor::builder().line_policy(LinePolicy::Collect).build().unwrap().process_slice(input).unwrap();
+        assert_eq!(whole.invalid_lines(), [(2, "\u{FFFD}a\u{FFFD}".to_string()), (4, "\u{FFFD}a\u{FFFD}".to_string())]);
+        for line_length in 1..input.len() {
+            // Reads of `line_length + 1` bytes
+            let processor = Processor::builder().line_policy(LinePolicy::Collect).batch_lines(1).avg_line_len(line_length).build().unwrap();
+            let streamed = processor.process_reader(&input[..]).unwrap();
+            assert_eq!(streamed.invalid_lines(), whole.invalid_lines(), "{line_length}");
+            assert_eq!(streamed.to_string(), whole.to_string());
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        let input = "Hamburg;12.0\nBulawayo;8.9\n".repeat(1_000);
+        let file = measurements(&input);
+        for &backend in backends() {
+            let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let hook = { let reports = reports.clone(); move |read, total| reports.lock().unwrap().push((read, total)) };
+            let processor = Processor::builder().backend(backend).threads(3).batch_lines(100).on_progress(hook).build().unwrap();
+            processor.process(file.path().to_str().unwrap()).unwrap();
+            let reports = reports.lock().unwrap();
+            assert!(reports.len() > 1, "{backend:?}");
+            assert_eq!(reports.iter().max(), Some(&(input.len() as u64, Some(input.len() as u64))), "{backend:?}");
+        }
+        let last = Arc::new(std::sync::Mutex::new(None));
+        let hook = { let last = last.clone(); move |read, total| *last.lock().unwrap() = Some((read, total)) };
+        Processor::builder().on_progress(hook).build().unwrap().process_reader(input.as_bytes()).unwrap();
+        assert_eq!(*last.lock().unwrap(), Some((input.len() as u64, None)));
+    }
+
+    #[test]
+    fn test_process_stats() {
+        let input = "Hamburg;12.0\nBulawayo;8.9\nbad\n".repeat(1_000);
+        let file = measurements(&input);
+        for backend in [Backend::Stream, Backend::Partitioned] {
+            let processor = Processor::builder().backend(backend).line_policy(LinePolicy::Skip).threads(2).batch_lines(100).avg_line_len(1).inline_threshold(0).build().unwrap();
+            let stats = *processor.process(file.path().to_str().unwrap()).unwrap().stats();
+            assert_eq!((stats.bytes_read, stats.lines), (input.len() as u64, 3_000), "{backend:?}");
+            assert!(stats.batches > 2 && stats.parse_time > Duration::ZERO && stats.total_time > Duration::ZERO, "{backend:?}");
+            assert!(stats.to_json().starts_with(&format!("{{\"bytes_read\":{},\"lines\":3000,", input.len())));
+        }
+    }
+
+    // Run with `cargo test -- --ignored`, it checks a thousand random inputs and configurations
+    #[test]
+    #[ignore]
+    fn test_chaos_against_reference() {
+        const NAMES: [&str; 6] = ["東京", "Zürich", "a", "Hamburg", "St. John's", "🌍"];
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path();
+        let mut seed = 7u64;
+        let mut random = |bound: u64| {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            (seed >> 33) % bound
+        };
+        for round in 0..1_000 {
+            // Any of these keeps the names and values whole, and leaves `bad` without a delimiter
+            let delimiter = [';', ',', '\t', '|', ':'][random(5) as usize];
+            let field_order = [FieldOrder::StationFirst, FieldOrder::ValueFirst][random(2) as usize];
+            let (mut input, mut valid, mut invalid_lines) = (String::new(), String::new(), Vec::new());
+            for line_no in 1..=random(2_000) {
+                if random(50) == 0 {
+                    input += "bad\n";
+                    invalid_lines.push((line_no, "bad".to_string()));
+                } else if random(50) == 0 {
+                    input += ["\n", "\r\n"][random(2) as usize];
+                } else {
+                    let (name, value) = (NAMES[random(6) as usize], format!("{:.1}", (random(1_999) as f64 - 999.0) / 10.0));
+                    input += &match field_order {
+                        FieldOrder::StationFirst => format!("{name}{delimiter}{value}"),
+                        FieldOrder::ValueFirst => format!("{value}{delimiter}{name}"),
+                    };
+                    input += ["\n", "\r\n"][random(2) as usize];
+                    // The reference reads the challenge's own format
+                    valid += &format!("{name};{value}\n");
+                }
+            }
+            std::fs::write(path, &input).unwrap();
+
+            let backends = backends();
+            let policy = [LinePolicy::Strict, LinePolicy::Skip, LinePolicy::Collect][random(3) as usize];
+            let mut builder = Processor::builder()
+                .backend(backends[random(backends.len() as u64) as usize])
+                .delimiter(delimiter)
+                .field_order(field_order)
+                .line_policy(policy)
+                .threads(random(4) as usize + 1)
+                .batch_lines(random(50) as usize + 1)
+                .expected_stations(random(20) as usize)
+                .max_integer_digits(random(3) as usize + 2)
+                .detect_line_lengths(random(2) == 0);
+            if random(2) == 0 {
+                builder = builder.avg_line_len(random(30) as usize + 1);
+            }
+            if random(2) == 0 {
+                builder = builder.buffer_capacity(random(64) as usize + 1);
+            }
+            let context = format!("round {round}: {:?}", builder.config);
+            let result = builder.build().unwrap().process(path.to_str().unwrap());
+
+            if policy == LinePolicy::Strict && !invalid_lines.is_empty() {
+                assert!(matches!(result, Err(ProcessError::MalformedLine { line_no, .. }) if line_no == invalid_lines[0].0), "{context}");
+                continue;
+            }
+            let results = result.unwrap();
+            let expected = reference(&valid);
+            assert_eq!(results.len(), expected.len(), "{context}");
+            for ((station, data), (expected_station, (min, max, sum, count))) in results.iter().zip(expected) {
+                assert_eq!((station, data.min, data.max, data.sum, data.count), (expected_station.as_str(), min, max, sum, count), "{context}");
+            }
+            let collected = if policy == LinePolicy::Collect { &invalid_lines[..] } else { &[] };
+            assert_eq!(results.invalid_lines(), collected, "{context}");
+        }
+    }
+
+}
@@ -1,6 +1,1110 @@
-use rust_billion_row_challenge::process_file;
+use std::fs::{self, File};
+use std::hash::BuildHasher;
+use std::io;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use clap::{Parser, Subcommand, ValueEnum};
+use indicatif::{ProgressBar, ProgressStyle};
+use rust_billion_row_challenge::{check, compare, drop_caches, generate, lower_priority, prime_cache, validate, verify_manifest, Backend, CancellationToken, Columns, Compression, EofPolicy, FieldOrder, Format, Ingest, InputProfile, Limit, LinePolicy, Manifest, Moments, Percentiles, Preset, ProcessStats, Processor, ProcessorBuilder, Results, SortBy, StationFilter, Strategy, Utf8Policy};
 
-fn main() {
-    let address = std::env::var("MEASUREMENTS_FILE").expect("No file specified");
-    process_file(&address);
-}
\ No newline at end of file
+/// Aggregates min/mean/max per station from `station;value` measurement files
+#[derive(Parser)]
+#[command(name = "brc", version, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Run at the lowest CPU priority and idle I/O priority to stay out of the way of other work
+    #[arg(long, global = true)]
+    background: bool,
+    #[command(flatten)]
+    process: ProcessArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Aggregate a measurements file, the default when no subcommand is given
+    Process(Box<ProcessArgs>),
+    /// Predict the runtime and peak memory of a full run from samples of the input
+    Estimate {
+        /// Measurements file to sample
+        file: String,
+        /// Megabytes sampled from each of the start, middle and end of the file
+        #[arg(long, default_value_t = 128, value_parser = clap::value_parser!(u64).range(1..))]
+        sample_mb: u64,
+        #[command(flatten)]
+        tuning: TuningArgs,
+    },
+    /// Report station name lengths and value shapes, and suggest matching settings
+    Profile {
+        /// Measurements file to profile
+        file: String,
+        /// Only profile the first this many megabytes
+        #[arg(long)]
+        sample_mb: Option<u64>,
+    },
+    /// Check every line against the official format, listing violations and failing on any
+    Check {
+        /// Measurements file to check, or `-` for standard input
+        file: String,
+    },
+    /// Combine partial results saved by `brc process --shard` into one report
+    Merge {
+        /// Partial results, one per shard
+        #[arg(required = true)]
+        parts: Vec<PathBuf>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Aggregate a file once and answer JSON queries about the results over HTTP: `/stations`,
+    /// `/stations/{name}` and `/top?n=10`. With `--allow-jobs`, other files and URLs POSTed to
+    /// `/jobs` are aggregated in the background and queried under `/jobs/{id}`.
+    Serve {
+        /// Measurements file to aggregate at startup, serving only jobs if not given
+        #[arg(long, required_unless_present = "allow_jobs")]
+        file: Option<PathBuf>,
+        #[command(flatten)]
+        jobs: JobArgs,
+        /// Port to listen on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        /// Address to listen on, such as 0.0.0.0 for every interface
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        #[command(flatten)]
+        tuning: TuningArgs,
+    },
+    /// Listen on a socket or consume Kafka topics for `station;value` lines and write the
+    /// results so far on demand: on each line read from stdin, and at a regular interval
+    Ingest(Box<IngestArgs>),
+    /// Write random measurements in the challenge format, as the official generator does
+    Generate {
+        #[arg(long, default_value_t = 1_000_000_000)]
+        rows: u64,
+        /// Use only the first this many of the 413 official stations
+        #[arg(long, default_value_t = 413)]
+        stations: usize,
+        /// The same seed always produces the same file
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+        /// File to write, standard output if not given
+        #[arg(long, value_name = "PATH")]
+        out: Option<PathBuf>,
+    },
+    /// Aggregate a file with each internal pipeline in turn, printing how long each took and
+    /// failing when any output differs from the first
+    Compare {
+        /// Measurements file to aggregate
+        file: String,
+        /// Pipelines to run, in order [default: every one this build supports]
+        #[arg(long, value_enum, value_delimiter = ',')]
+        strategies: Vec<StrategyArg>,
+        #[command(flatten)]
+        tuning: TuningArgs,
+    },
+    /// Compare two results station by station, failing when any values differ
+    Validate {
+        /// Known-good results
+        #[arg(long, value_name = "PATH")]
+        expected: PathBuf,
+        /// Results to check
+        #[arg(long, value_name = "PATH")]
+        actual: PathBuf,
+        /// Largest accepted difference in degrees
+        #[arg(long, default_value_t = 0.0)]
+        tolerance: f64,
+    },
+}
+
+#[derive(clap::Args)]
+struct ProcessArgs {
+    /// Measurements files to process, `-` to read standard input, or object store URLs such as
+    /// s3://bucket/measurements.txt. Several files are combined into one report, and quoted
+    /// patterns such as 'measurements-*.txt' are expanded.
+    #[arg(env = "MEASUREMENTS_FILE")]
+    files: Vec<String>,
+    /// Read the files into the page cache before processing for warm runs
+    #[arg(long, conflicts_with = "drop_caches")]
+    prime_cache: bool,
+    /// Evict the files from the page cache before processing for cold runs
+    #[arg(long)]
+    drop_caches: bool,
+    /// Read around the page cache, for files far larger than memory that would otherwise evict
+    /// the caches of other processes
+    #[arg(long, conflicts_with_all = ["prime_cache", "backend"])]
+    direct_io: bool,
+    /// Write per-segment checksums of the input to this manifest while aggregating
+    #[arg(long, value_name = "PATH", conflicts_with = "verify")]
+    manifest: Option<PathBuf>,
+    /// Check the input against a manifest instead of aggregating it
+    #[arg(long, value_name = "MANIFEST")]
+    verify: Option<PathBuf>,
+    /// How the file is read: sequentially into batches, through a memory map, as one byte range
+    /// per worker, or with io_uring reads kept in flight [default: stream]
+    #[arg(long, value_enum)]
+    backend: Option<BackendArg>,
+    /// Treat an empty read as a pause and read again after this many milliseconds, for pipes
+    /// whose producers start and stop
+    #[arg(long, value_name = "MS")]
+    wait_on_eof: Option<u64>,
+    /// Stop reading at the first line equal to this one
+    #[arg(long, value_name = "LINE")]
+    end_sentinel: Option<String>,
+    /// Aggregate only the first this many lines of each file
+    #[arg(long, value_name = "N")]
+    limit: Option<u64>,
+    /// Aggregate only a random fraction of the lines, such as 0.01
+    #[arg(long, value_name = "FRACTION", value_parser = parse_positive)]
+    sample: Option<f64>,
+    /// How the input is compressed, detected from its first bytes by default
+    #[arg(long, value_enum, default_value_t = CompressionArg::Auto)]
+    compression: CompressionArg,
+    /// The character between the station and the value
+    #[arg(long, value_name = "CHAR", default_value_t = ';')]
+    delimiter: char,
+    /// Which field comes first on each line
+    #[arg(long, value_enum, default_value_t = FieldOrderArg::StationFirst)]
+    field_order: FieldOrderArg,
+    /// Aggregate only the stations named in this file, one per line
+    #[arg(long, value_name = "PATH", group = "filter")]
+    stations_file: Option<PathBuf>,
+    /// Aggregate only the stations whose names start with this
+    #[arg(long, value_name = "PREFIX", group = "filter")]
+    station_prefix: Option<String>,
+    /// Aggregate only the stations whose names match this regular expression
+    #[arg(long, value_name = "REGEX", group = "filter")]
+    station_regex: Option<String>,
+    /// Abort on malformed lines, skip them, or skip them and list them on stderr
+    #[arg(long, value_enum, default_value_t = LinePolicyArg::Strict)]
+    invalid_lines: LinePolicyArg,
+    /// Treat lines that are not UTF-8 as malformed, or replace their invalid bytes with U+FFFD
+    #[arg(long, value_enum, default_value_t = Utf8Arg::Validate)]
+    utf8: Utf8Arg,
+    /// Add each station's standard deviation after its maximum
+    #[arg(long)]
+    stddev: bool,
+    /// Add each station's median, 95th and 99th percentile after its maximum
+    #[arg(long, conflicts_with = "stddev")]
+    percentiles: bool,
+    /// Print where the time went to stderr once the results are written
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    timings: Option<TimingsArg>,
+    /// Show a progress bar on stderr while reading the input
+    #[arg(long)]
+    progress: bool,
+    /// Also write every reading to this Parquet file, reading the input a second time
+    #[arg(long, value_name = "PATH")]
+    parquet_rows: Option<PathBuf>,
+    /// Aggregate only shard K of N newline-aligned byte ranges of the file, and write the
+    /// partial results for `brc merge` instead of a report
+    #[arg(long, value_name = "K/N", value_parser = parse_shard, conflicts_with_all = ["manifest", "verify", "stddev", "percentiles", "parquet_rows"])]
+    shard: Option<(usize, usize)>,
+    /// Keep reading what is appended to the file and write the results so far every this many
+    /// seconds, until the file shrinks
+    #[arg(long, value_name = "SECONDS", num_args = 0..=1, default_missing_value = "1", value_parser = parse_positive,
+        conflicts_with_all = ["shard", "manifest", "verify", "stddev", "percentiles", "parquet_rows"])]
+    follow: Option<f64>,
+    /// Show the results so far in a live terminal view while following, rather than writing them
+    /// at every interval. They are written once the view is closed.
+    #[arg(long, requires = "follow")]
+    tui: bool,
+    /// With several files, also list on stderr which file each station's min and max came from
+    #[arg(long, conflicts_with_all = ["shard", "follow", "checkpoint"])]
+    per_file: bool,
+    /// Resume from the checkpoint at this path if there is one, saving a new one every gigabyte,
+    /// so that a rerun over a file that has grown only parses the appended bytes
+    #[arg(long, value_name = "PATH",
+        conflicts_with_all = ["shard", "follow", "manifest", "verify", "stddev", "percentiles", "limit", "sample"])]
+    checkpoint: Option<PathBuf>,
+    #[command(flatten)]
+    output: OutputArgs,
+    #[command(flatten)]
+    tuning: TuningArgs,
+}
+
+#[derive(clap::Args)]
+struct JobArgs {
+    /// Take jobs from clients, reading files under `--job-root` and URLs of `--job-schemes`
+    #[arg(long)]
+    allow_jobs: bool,
+    /// Directory that the files of jobs must lie under
+    #[arg(long, value_name = "DIR", default_value = ".", requires = "allow_jobs")]
+    job_root: PathBuf,
+    /// Comma-separated URL schemes that jobs may read, such as https,s3; none by default
+    #[arg(long, value_name = "SCHEMES", value_delimiter = ',', requires = "allow_jobs")]
+    job_schemes: Vec<String>,
+    /// Most jobs aggregated at once, the rest waiting their turn
+    #[arg(long, default_value_t = 2, requires = "allow_jobs")]
+    max_jobs: usize,
+    /// Finished jobs kept with their results, the oldest being dropped past this
+    #[arg(long, default_value_t = 100, requires = "allow_jobs")]
+    max_finished_jobs: usize,
+}
+
+#[derive(clap::Args)]
+struct OutputArgs {
+    /// Output layout
+    #[arg(long, value_enum, default_value_t = FormatArg::Text)]
+    format: FormatArg,
+    /// Order the stations by this column, ascending [default: station, or mean with --top and
+    /// --bottom]
+    #[arg(long, visible_alias = "by", value_enum)]
+    sort_by: Option<SortByArg>,
+    /// Print only this many stations, those with the highest values of the sort column
+    #[arg(long, value_name = "N")]
+    top: Option<usize>,
+    /// Print only this many stations, those with the lowest values of the sort column
+    #[arg(long, value_name = "N", conflicts_with = "top")]
+    bottom: Option<usize>,
+    /// Print each station's number of readings after its values. JSON, CSV and TSV always do.
+    #[arg(long)]
+    count: bool,
+    /// Right-align every number in a column of this width
+    #[arg(long, value_name = "WIDTH")]
+    number_width: Option<usize>,
+    /// Write the results to this file instead of stdout
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+#[command(group = clap::ArgGroup::new("source").required(true))]
+struct IngestArgs {
+    /// TCP address to listen on, such as 127.0.0.1:9000
+    #[arg(long, value_name = "ADDRESS", group = "source")]
+    listen: Option<String>,
+    /// Unix socket to create and listen on
+    #[arg(long, value_name = "PATH", group = "source")]
+    unix: Option<PathBuf>,
+    /// Consume the messages of these comma-separated Kafka topics instead of listening
+    #[arg(long, value_name = "TOPICS", value_delimiter = ',', group = "source", requires = "kafka_brokers")]
+    kafka_topics: Vec<String>,
+    /// Kafka brokers to consume from, such as localhost:9092
+    #[arg(long, value_name = "BROKERS")]
+    kafka_brokers: Option<String>,
+    /// Kafka consumer group, whose committed offsets say where consuming resumes
+    #[arg(long, value_name = "GROUP", default_value = "brc")]
+    kafka_group: String,
+    /// Also write the results every this many seconds
+    #[arg(long, value_name = "SECONDS", value_parser = parse_positive)]
+    snapshot_every: Option<f64>,
+    /// Show the results so far in a live terminal view rather than writing them on each line of
+    /// stdin. They are written once the view is closed.
+    #[arg(long, conflicts_with = "snapshot_every")]
+    tui: bool,
+    /// Drop a client on a malformed line, skip the line, or skip it and list it on stderr
+    #[arg(long, value_enum, default_value_t = LinePolicyArg::Strict)]
+    invalid_lines: LinePolicyArg,
+    #[command(flatten)]
+    output: OutputArgs,
+    #[command(flatten)]
+    tuning: TuningArgs,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FieldOrderArg {
+    StationFirst,
+    ValueFirst,
+}
+impl From<FieldOrderArg> for FieldOrder {
+    fn from(order: FieldOrderArg) -> Self {
+        match order {
+            FieldOrderArg::StationFirst => FieldOrder::StationFirst,
+            FieldOrderArg::ValueFirst => FieldOrder::ValueFirst,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FormatArg {
+    Text,
+    Lines,
+    Json,
+    Csv,
+    Tsv,
+    Parquet,
+}
+impl From<FormatArg> for Format {
+    fn from(format: FormatArg) -> Self {
+        match format {
+            FormatArg::Text => Format::Text,
+            FormatArg::Lines => Format::Lines,
+            FormatArg::Json => Format::Json,
+            FormatArg::Csv => Format::Csv,
+            FormatArg::Tsv => Format::Tsv,
+            FormatArg::Parquet => Format::Parquet,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SortByArg {
+    Station,
+    Min,
+    Mean,
+    Max,
+    Count,
+}
+impl From<SortByArg> for SortBy {
+    fn from(column: SortByArg) -> Self {
+        match column {
+            SortByArg::Station => SortBy::Station,
+            SortByArg::Min => SortBy::Min,
+            SortByArg::Mean => SortBy::Mean,
+            SortByArg::Max => SortBy::Max,
+            SortByArg::Count => SortBy::Count,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TimingsArg {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LinePolicyArg {
+    Strict,
+    Skip,
+    Collect,
+}
+impl From<LinePolicyArg> for LinePolicy {
+    fn from(policy: LinePolicyArg) -> Self {
+        match policy {
+            LinePolicyArg::Strict => LinePolicy::Strict,
+            LinePolicyArg::Skip => LinePolicy::Skip,
+            LinePolicyArg::Collect => LinePolicy::Collect,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Utf8Arg {
+    Validate,
+    Lossy,
+}
+impl From<Utf8Arg> for Utf8Policy {
+    fn from(policy: Utf8Arg) -> Self {
+        match policy {
+            Utf8Arg::Validate => Utf8Policy::Validate,
+            Utf8Arg::Lossy => Utf8Policy::Lossy,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BackendArg {
+    Stream,
+    Mmap,
+    Partitioned,
+    IoUring,
+}
+impl From<BackendArg> for Backend {
+    fn from(backend: BackendArg) -> Self {
+        match backend {
+            BackendArg::Stream => Backend::Stream,
+            BackendArg::Mmap => Backend::Mmap,
+            BackendArg::Partitioned => Backend::Partitioned,
+            BackendArg::IoUring => Backend::IoUring,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CompressionArg {
+    Auto,
+    None,
+    Gzip,
+    Zstd,
+}
+impl From<CompressionArg> for Compression {
+    fn from(compression: CompressionArg) -> Self {
+        match compression {
+            CompressionArg::Auto => Compression::Auto,
+            CompressionArg::None => Compression::None,
+            CompressionArg::Gzip => Compression::Gzip,
+            CompressionArg::Zstd => Compression::Zstd,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum StrategyArg {
+    Buffered,
+    Mmap,
+    Partitioned,
+    SingleThreaded,
+}
+impl From<StrategyArg> for Strategy {
+    fn from(strategy: StrategyArg) -> Self {
+        match strategy {
+            StrategyArg::Buffered => Strategy::Buffered,
+            StrategyArg::Mmap => Strategy::Mmap,
+            StrategyArg::Partitioned => Strategy::Partitioned,
+            StrategyArg::SingleThreaded => Strategy::SingleThreaded,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PresetArg {
+    Laptop,
+    Server,
+    MaxThroughput,
+}
+impl From<PresetArg> for Preset {
+    fn from(preset: PresetArg) -> Self {
+        match preset {
+            PresetArg::Laptop => Preset::Laptop,
+            PresetArg::Server => Preset::Server,
+            PresetArg::MaxThroughput => Preset::MaxThroughput,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct TuningArgs {
+    /// Start from settings suited to this class of machine, overridden by any other option
+    #[arg(long, value_enum)]
+    preset: Option<PresetArg>,
+    /// Worker threads [default: twice the number of CPUs]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    threads: Option<u64>,
+    /// Lines parsed per batch [default: 1000000]
+    #[arg(long, visible_alias = "batch-size", value_parser = clap::value_parser!(u64).range(1..))]
+    batch_lines: Option<u64>,
+    /// Limit input reads to this many megabytes per second
+    #[arg(long, value_parser = parse_positive)]
+    max_read_mbps: Option<f64>,
+    /// Report how work was distributed across threads and batches to stderr
+    #[arg(long)]
+    diagnostics: bool,
+    /// Maximum digits before the decimal point, raise above 2 for values outside -99.9..=99.9
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(1..=8))]
+    max_integer_digits: u8,
+    /// Accept values with up to this many decimal places, or none, rounding them to tenths
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=9))]
+    max_decimals: Option<u8>,
+    /// Expected average line length in bytes, used to size reads
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    avg_line_len: Option<u64>,
+    /// Expected longest line in bytes, used to size batch buffers
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_line_len: Option<u64>,
+    /// Sample the start of the input to pick the line length hints
+    #[arg(long)]
+    detect_line_lengths: bool,
+    /// Bytes buffered between the input and the batch reads [default: the longest batch]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    buffer_capacity: Option<u64>,
+    /// Distinct stations the maps are sized for up front [default: 10000]
+    #[arg(long)]
+    expected_stations: Option<u64>,
+    /// Batches read ahead of the workers before reading pauses [default: twice the threads]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_in_flight_batches: Option<u64>,
+    /// Batches the reader thread may fill ahead of the workers [default: 2]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    read_buffers: Option<u64>,
+    /// Megabytes the OS is asked to prefetch ahead of each reader of a file
+    #[arg(long, value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+    readahead_mb: Option<u64>,
+    /// Files smaller than this many bytes are parsed on the main thread, 0 to always use the pool [default: 4 MiB]
+    #[arg(long, value_name = "BYTES")]
+    inline_threshold: Option<u64>,
+    /// Reads kept in flight by the io-uring backend [default: 32]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..=4096))]
+    queue_depth: Option<u64>,
+}
+impl OutputArgs {
+    fn apply<S, A>(&self, builder: ProcessorBuilder<S, A>) -> ProcessorBuilder<S, A> {
+        let limit = match (self.top, self.bottom) {
+            (Some(n), _) => Some(Limit::Top(n)),
+            (_, Some(n)) => Some(Limit::Bottom(n)),
+            _ => None,
+        };
+        let mut builder = builder
+            .format(self.format.into())
+            .sort_by(self.sort_by.unwrap_or(if limit.is_some() { SortByArg::Mean } else { SortByArg::Station }).into())
+            .with_count(self.count);
+        if let Some(limit) = limit {
+            builder = builder.limit(limit);
+        }
+        if let Some(width) = self.number_width {
+            builder = builder.number_width(width);
+        }
+        builder
+    }
+
+    // The order and limit of the stations in a terminal view, the highest means by default
+    fn view(&self) -> (SortBy, Option<Limit>) {
+        let limit = match (self.top, self.bottom) {
+            (Some(n), _) => Some(Limit::Top(n)),
+            (_, Some(n)) => Some(Limit::Bottom(n)),
+            _ => None,
+        };
+        (self.sort_by.unwrap_or(SortByArg::Mean).into(), limit)
+    }
+}
+
+impl TuningArgs {
+    fn builder(&self) -> ProcessorBuilder {
+        let mut builder = Processor::builder();
+        if let Some(preset) = self.preset {
+            builder = builder.preset(preset.into());
+        }
+        if let Some(threads) = self.threads {
+            builder = builder.threads(threads as usize);
+        }
+        if let Some(lines) = self.batch_lines {
+            builder = builder.batch_lines(lines as usize);
+        }
+        builder = builder
+            .diagnostics(self.diagnostics)
+            .max_integer_digits(self.max_integer_digits as usize)
+            .detect_line_lengths(self.detect_line_lengths);
+        if let Some(decimals) = self.max_decimals {
+            builder = builder.max_decimals(decimals as usize);
+        }
+        if let Some(max_read_mbps) = self.max_read_mbps {
+            builder = builder.max_read_mbps(max_read_mbps);
+        }
+        if let Some(length) = self.avg_line_len {
+            builder = builder.avg_line_len(length as usize);
+        }
+        if let Some(length) = self.max_line_len {
+            builder = builder.max_line_len(length as usize);
+        }
+        if let Some(bytes) = self.buffer_capacity {
+            builder = builder.buffer_capacity(bytes as usize);
+        }
+        if let Some(stations) = self.expected_stations {
+            builder = builder.expected_stations(stations as usize);
+        }
+        if let Some(batches) = self.max_in_flight_batches {
+            builder = builder.max_in_flight_batches(batches as usize);
+        }
+        if let Some(batches) = self.read_buffers {
+            builder = builder.read_buffers(batches as usize);
+        }
+        if let Some(megabytes) = self.readahead_mb {
+            builder = builder.readahead(megabytes * 1024 * 1024);
+        }
+        if let Some(bytes) = self.inline_threshold {
+            builder = builder.inline_threshold(bytes);
+        }
+        if let Some(reads) = self.queue_depth {
+            builder = builder.queue_depth(reads as usize);
+        }
+        builder
+    }
+}
+
+fn parse_positive(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) if number > 0.0 => Ok(number),
+        _ => Err(format!("expected a positive number, got `{value}`")),
+    }
+}
+
+// `K/N` with shards numbered from 1
+fn parse_shard(value: &str) -> Result<(usize, usize), String> {
+    let parsed = value.split_once('/').and_then(|(shard, shards)| Some((shard.parse().ok()?, shards.parse().ok()?)));
+    match parsed {
+        Some((shard, shards)) if (1..=shards).contains(&shard) => Ok((shard, shards)),
+        _ => Err(format!("expected a shard K/N with 1 <= K <= N, got `{value}`")),
+    }
+}
+
+fn verify(file: &str, manifest_path: &PathBuf) -> io::Result<ExitCode> {
+    let manifest = Manifest::read_from(File::open(manifest_path)?)?;
+    let mismatches = verify_manifest(file, &manifest)?;
+    for segment in &mismatches {
+        eprintln!("Mismatch in bytes {}..{}", segment.offset, segment.offset + segment.length);
+    }
+    if mismatches.is_empty() {
+        println!("OK: {} segments verified", manifest.segments.len());
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+// Expands glob patterns the shell left alone, keeping plain paths and `-` as given
+fn expand(patterns: &[String]) -> io::Result<Vec<String>> {
+    let mut files = Vec::with_capacity(patterns.len());
+    for pattern in patterns {
+        if !pattern.contains(['*', '?', '[']) {
+            files.push(pattern.clone());
+            continue;
+        }
+        let matched = files.len();
+        for path in glob::glob(pattern).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))? {
+            let path = path.map_err(io::Error::from)?;
+            files.push(path.into_os_string().into_string()
+                .map_err(|path| io::Error::new(io::ErrorKind::InvalidInput, format!("Path is not UTF-8: {path:?}")))?);
+        }
+        if files.len() == matched {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("No files match {pattern}")));
+        }
+    }
+    Ok(files)
+}
+
+fn process(args: ProcessArgs) -> io::Result<ExitCode> {
+    let files = expand(&args.files)?;
+    if files.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "No file specified"));
+    }
+    if let Some(manifest_path) = &args.verify {
+        let [file] = files.as_slice() else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "A manifest is verified against a single file"));
+        };
+        return verify(file, manifest_path);
+    }
+    if args.parquet_rows.is_some() && files.len() > 1 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "A Parquet rows file covers a single input"));
+    }
+    for file in &files {
+        if args.prime_cache {
+            prime_cache(file)?;
+        }
+        if args.drop_caches {
+            if let Err(error) = drop_caches(file) {
+                eprintln!("Could not drop caches, continuing with a warm cache: {error}");
+            }
+        }
+    }
+    let mut builder = args.output.apply(args.tuning.builder())
+        .delimiter(args.delimiter)
+        .field_order(args.field_order.into())
+        .line_policy(args.invalid_lines.into())
+        .utf8_policy(args.utf8.into())
+        .compression(args.compression.into())
+        .direct_io(args.direct_io);
+    if let Some(backend) = args.backend {
+        builder = builder.backend(backend.into());
+    }
+    if let Some(filter) = station_filter(args.stations_file, args.station_prefix, args.station_regex)? {
+        builder = builder.station_filter(filter);
+    }
+    if let Some(manifest_path) = args.manifest {
+        builder = builder.manifest_path(manifest_path);
+    }
+    if let Some(interval) = args.wait_on_eof {
+        builder = builder.eof_policy(EofPolicy::WaitAndRetry(Duration::from_millis(interval)));
+    }
+    if let Some(lines) = args.limit {
+        builder = builder.max_lines(lines);
+    }
+    if let Some(fraction) = args.sample {
+        builder = builder.sample(fraction);
+    }
+    if let Some(sentinel) = args.end_sentinel {
+        builder = builder.end_sentinel(sentinel);
+    }
+    let bar = args.progress.then(|| {
+        let bar = ProgressBar::no_length();
+        bar.set_style(ProgressStyle::with_template("{wide_bar} {bytes}/{total_bytes} {binary_bytes_per_sec} {eta}").expect("valid template"));
+        bar
+    });
+    if let Some(bar) = bar.clone() {
+        builder = builder.on_progress(move |read, total| {
+            if let Some(total) = total {
+                bar.set_length(total);
+            }
+            bar.set_position(read);
+        });
+    }
+    // A followed file has no end to stop at, so Ctrl+C keeps ending it at once
+    if args.follow.is_none() {
+        builder = builder.cancellation(cancel_on_interrupt()?);
+    }
+    let files = files.into_iter().map(PathBuf::from).collect::<Vec<_>>();
+    let view = args.tui.then(|| args.output.view());
+    let output = args.output.output;
+    let result = if let Some(interval) = args.follow {
+        follow(builder, &files, interval, output, view)
+    } else if let Some((shard, shards)) = args.shard {
+        save_shard(builder, &files, shard, shards, output, args.timings)
+    } else if let Some(checkpoint) = args.checkpoint {
+        resume(builder, &files, &checkpoint, output, args.timings)
+    } else if args.stddev {
+        report(builder.aggregator::<Moments>(), &files, output, args.parquet_rows, args.timings, args.per_file)
+    } else if args.percentiles {
+        report(builder.aggregator::<Percentiles>(), &files, output, args.parquet_rows, args.timings, args.per_file)
+    } else {
+        report(builder, &files, output, args.parquet_rows, args.timings, args.per_file)
+    };
+    if let Some(bar) = bar {
+        bar.finish_and_clear();
+    }
+    result
+}
+
+fn report<S: BuildHasher + Clone + Send + Sync, A: Columns>(builder: ProcessorBuilder<S, A>, files: &[PathBuf], output: Option<PathBuf>, rows: Option<PathBuf>,
+    timings: Option<TimingsArg>, per_file: bool) -> io::Result<ExitCode> {
+    let processor = builder.build()?;
+    let (results, sources) = match per_file {
+        true => processor.process_files_by_source(files)?,
+        false => (processor.process_files(files)?, Vec::new()),
+    };
+    match output {
+        // Created only once aggregation succeeded, so a failed run leaves an existing file alone
+        Some(path) => processor.write_results(&results, File::create(path)?)?,
+        None => processor.write_results(&results, io::stdout())?,
+    }
+    if let (Some(path), [file]) = (rows, files) {
+        write_rows(&processor, file, path)?;
+    }
+    for (station, columns) in &results {
+        // None for every station unless the files were aggregated by source
+        if let Some((min, max)) = results.extreme_sources(station, &sources) {
+            let data = columns.data();
+            eprintln!("{station}: min {:.1} from {}, max {:.1} from {}", data.min(), files[min].display(), data.max(), files[max].display());
+        }
+    }
+    if results.stats().sampled {
+        eprintln!("Results cover a sample of {} lines of the input", results.stats().lines);
+    }
+    print_timings(results.stats(), timings);
+    Ok(exit_code(results.stats()))
+}
+
+// Prints the run's statistics to stderr when asked for
+fn print_timings(stats: &ProcessStats, timings: Option<TimingsArg>) {
+    match timings {
+        Some(TimingsArg::Text) => eprint!("{stats}"),
+        Some(TimingsArg::Json) => eprintln!("{}", stats.to_json()),
+        None => {}
+    }
+}
+
+// The path as the address the library takes, as long as it is UTF-8
+fn address(path: &Path) -> io::Result<&str> {
+    path.to_str().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Path is not UTF-8: {}", path.display())))
+}
+
+// Exit status of a run stopped by Ctrl+C, as shells report an interrupted command
+const CANCELLED_EXIT: u8 = 130;
+
+// The first Ctrl+C stops the run with partial results, a second one exits at once
+fn cancel_on_interrupt() -> io::Result<CancellationToken> {
+    let token = CancellationToken::new();
+    let handler = token.clone();
+    ctrlc::set_handler(move || {
+        if handler.is_cancelled() {
+            std::process::exit(CANCELLED_EXIT.into());
+        }
+        eprintln!("Finishing the batches in flight, press Ctrl+C again to quit at once");
+        handler.cancel();
+    }).map_err(io::Error::other)?;
+    Ok(token)
+}
+
+fn exit_code(stats: &ProcessStats) -> ExitCode {
+    if !stats.cancelled {
+        return ExitCode::SUCCESS;
+    }
+    eprintln!("Cancelled: results cover {} lines, {} bytes of the input", stats.lines, stats.bytes_read);
+    ExitCode::from(CANCELLED_EXIT)
+}
+
+// Writes the partial results of one shard, numbered from 1 on the command line
+fn save_shard(builder: ProcessorBuilder, files: &[PathBuf], shard: usize, shards: usize, output: Option<PathBuf>, timings: Option<TimingsArg>) -> io::Result<ExitCode> {
+    let [file] = files else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "A shard is taken from a single file"));
+    };
+    let address = address(file)?;
+    let results = builder.build()?.process_shard(address, shard - 1, shards)?;
+    if results.stats().cancelled {
+        return Err(io::Error::new(io::ErrorKind::Interrupted, "Cancelled before the shard was aggregated"));
+    }
+    match output {
+        Some(path) => results.write_state(File::create(path)?)?,
+        None => results.write_state(io::stdout().lock())?,
+    }
+    print_timings(results.stats(), timings);
+    Ok(ExitCode::SUCCESS)
+}
+
+// Reports on a single file, resuming from and saving checkpoints
+fn resume(builder: ProcessorBuilder, files: &[PathBuf], checkpoint: &Path, output: Option<PathBuf>, timings: Option<TimingsArg>) -> io::Result<ExitCode> {
+    let [file] = files else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "A checkpoint covers a single file"));
+    };
+    let address = address(file)?;
+    let processor = builder.build()?;
+    let results = processor.process_resumable(address, checkpoint)?;
+    match output {
+        Some(path) => processor.write_results(&results, File::create(path)?)?,
+        None => processor.write_results(&results, io::stdout())?,
+    }
+    print_timings(results.stats(), timings);
+    Ok(exit_code(results.stats()))
+}
+
+fn merge(parts: &[PathBuf], output: OutputArgs) -> io::Result<ExitCode> {
+    let mut results = Results::default();
+    for part in parts {
+        let state = Results::read_state(File::open(part)?)
+            .map_err(|error| io::Error::new(error.kind(), format!("{}: {error}", part.display())))?;
+        results.merge(state);
+    }
+    let processor = output.apply(Processor::builder()).build()?;
+    match output.output {
+        Some(path) => processor.write_results(&results, File::create(path)?)?,
+        None => processor.write_results(&results, io::stdout())?,
+    }
+    if results.stats().sampled {
+        eprintln!("Results cover a sample of {} lines of the input", results.stats().lines);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn ingest(args: IngestArgs) -> io::Result<ExitCode> {
+    let processor = args.output.apply(args.tuning.builder()).line_policy(args.invalid_lines.into()).build()?;
+    let ingest = Arc::new(Ingest::new(processor));
+    let listener = match (args.listen, args.unix) {
+        (Some(address), _) => {
+            let listener = TcpListener::bind(&address)?;
+            eprintln!("Listening on {}", listener.local_addr()?);
+            let ingest = Arc::clone(&ingest);
+            thread::spawn(move || ingest.listen(listener.incoming()))
+        }
+        (None, Some(path)) => listen_unix(Arc::clone(&ingest), path)?,
+        (None, None) => match args.kafka_brokers {
+            Some(brokers) => consume_kafka(Arc::clone(&ingest), brokers, args.kafka_group, args.kafka_topics),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "No socket to listen on")),
+        },
+    };
+    let view = args.tui.then(|| args.output.view());
+    let output = args.output.output;
+    if let Some(view) = view {
+        watch(&ingest, "ingest", view, Duration::from_secs(1), || None, || listener.is_finished())?;
+        snapshot(&ingest, output.as_deref())?;
+        if !listener.is_finished() {
+            return Ok(ExitCode::SUCCESS);
+        }
+    }
+    if let Some(seconds) = args.snapshot_every {
+        let (ingest, output) = (Arc::clone(&ingest), output.clone());
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs_f64(seconds));
+            if let Err(error) = snapshot(&ingest, output.as_deref()) {
+                eprintln!("Could not write a snapshot: {error}");
+            }
+        });
+    }
+    for line in io::stdin().lines() {
+        line?;
+        snapshot(&ingest, output.as_deref())?;
+    }
+    // Without stdin, run on until listening fails
+    listener.join().map_err(|_| io::Error::other("The listener stopped unexpectedly"))??;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn snapshot(ingest: &Ingest, output: Option<&Path>) -> io::Result<()> {
+    match output {
+        // Written next to the target and renamed, so readers never see a partial file
+        Some(path) => {
+            let partial = path.with_extension("partial");
+            ingest.write_snapshot(File::create(&partial)?)?;
+            fs::rename(partial, path)
+        }
+        None => ingest.write_snapshot(io::stdout().lock()),
+    }
+}
+
+// Longest wait for appends to a followed file
+const FOLLOW_POLL: Duration = Duration::from_millis(100);
+
+// Aggregates the file as it grows, writing the results so far at every interval
+fn follow(builder: ProcessorBuilder, files: &[PathBuf], interval: f64, output: Option<PathBuf>, view: Option<(SortBy, Option<Limit>)>) -> io::Result<ExitCode> {
+    let [file] = files else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Only a single file can be followed"));
+    };
+    let address = address(file)?.to_string();
+    let ingest = Arc::new(Ingest::new(builder.build()?));
+    let interval = Duration::from_secs_f64(interval);
+    let follower = {
+        let (ingest, address) = (Arc::clone(&ingest), address.clone());
+        // Appends are looked for more often than results are written
+        thread::spawn(move || ingest.follow(&address, interval.min(FOLLOW_POLL)))
+    };
+    match view {
+        Some(view) => {
+            watch(&ingest, &address, view, interval, || fs::metadata(&address).ok().map(|metadata| metadata.len()), || follower.is_finished())?;
+            snapshot(&ingest, output.as_deref())?;
+            // Closing the view ends following
+            if !follower.is_finished() {
+                return Ok(ExitCode::SUCCESS);
+            }
+        }
+        None => while !follower.is_finished() {
+            thread::sleep(interval);
+            snapshot(&ingest, output.as_deref())?;
+        },
+    }
+    follower.join().map_err(|_| io::Error::other("Following stopped unexpectedly"))??;
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(feature = "tui")]
+fn watch(ingest: &Ingest, title: &str, (sort_by, limit): (SortBy, Option<Limit>), interval: Duration,
+    input_length: impl Fn() -> Option<u64>, stop: impl Fn() -> bool) -> io::Result<()> {
+    rust_billion_row_challenge::Dashboard::new(title, sort_by, limit).run(ingest, interval, input_length, stop)
+}
+
+#[cfg(not(feature = "tui"))]
+fn watch(_ingest: &Ingest, _title: &str, _view: (SortBy, Option<Limit>), _interval: Duration,
+    _input_length: impl Fn() -> Option<u64>, _stop: impl Fn() -> bool) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "A terminal view needs the tui feature"))
+}
+
+#[cfg(unix)]
+fn listen_unix(ingest: Arc<Ingest>, path: PathBuf) -> io::Result<thread::JoinHandle<io::Result<()>>> {
+    let listener = std::os::unix::net::UnixListener::bind(&path)?;
+    eprintln!("Listening on {}", path.display());
+    Ok(thread::spawn(move || ingest.listen(listener.incoming())))
+}
+
+#[cfg(not(unix))]
+fn listen_unix(_ingest: Arc<Ingest>, _path: PathBuf) -> io::Result<thread::JoinHandle<io::Result<()>>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Unix sockets are not available on this platform"))
+}
+
+#[cfg(feature = "kafka")]
+fn consume_kafka(ingest: Arc<Ingest>, brokers: String, group: String, topics: Vec<String>) -> thread::JoinHandle<io::Result<()>> {
+    eprintln!("Consuming {} from {brokers}", topics.join(", "));
+    thread::spawn(move || {
+        let topics = topics.iter().map(String::as_str).collect::<Vec<_>>();
+        Ok(ingest.consume_kafka(&brokers, &group, &topics)?)
+    })
+}
+
+#[cfg(not(feature = "kafka"))]
+fn consume_kafka(_ingest: Arc<Ingest>, _brokers: String, _group: String, _topics: Vec<String>) -> thread::JoinHandle<io::Result<()>> {
+    thread::spawn(|| Err(io::Error::new(io::ErrorKind::Unsupported, "Consuming Kafka topics needs the kafka feature")))
+}
+
+fn station_filter(names: Option<PathBuf>, prefix: Option<String>, regex: Option<String>) -> io::Result<Option<StationFilter>> {
+    if let Some(path) = names {
+        return StationFilter::names_from_reader(io::BufReader::new(File::open(path)?)).map(Some);
+    }
+    if let Some(prefix) = prefix {
+        return Ok(Some(StationFilter::Prefix(prefix)));
+    }
+    match regex {
+        #[cfg(feature = "regex")]
+        Some(regex) => Ok(Some(StationFilter::Regex(regex::Regex::new(&regex).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?))),
+        #[cfg(not(feature = "regex"))]
+        Some(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "Filtering by regular expression needs the regex feature")),
+        None => Ok(None),
+    }
+}
+
+#[cfg(feature = "parquet")]
+fn write_rows<S: BuildHasher + Clone + Send + Sync, A: Columns>(processor: &Processor<S, A>, file: &Path, path: PathBuf) -> io::Result<()> {
+    let address = address(file)?;
+    processor.write_rows_parquet(address, io::BufWriter::new(File::create(path)?))?;
+    Ok(())
+}
+
+#[cfg(feature = "serve")]
+fn serve(processor: Processor, file: Option<PathBuf>, jobs: JobArgs, address: &str) -> io::Result<()> {
+    let results = match file {
+        Some(file) => processor.process_files(&[file])?,
+        None => Results::default(),
+    };
+    if !jobs.allow_jobs {
+        eprintln!("Serving {} stations on http://{address}", results.len());
+        return rust_billion_row_challenge::serve(&results, address);
+    }
+    eprintln!("Serving {} stations and up to {} jobs at once, reading files under {}, on http://{address}", results.len(), jobs.max_jobs,
+        jobs.job_root.display());
+    let rules = rust_billion_row_challenge::JobRules { root: Some(jobs.job_root), schemes: jobs.job_schemes, max_finished: jobs.max_finished_jobs };
+    rust_billion_row_challenge::serve_jobs(&results, &rust_billion_row_challenge::Jobs::new(processor, jobs.max_jobs, rules), address)
+}
+
+#[cfg(not(feature = "serve"))]
+fn serve(_processor: Processor, _file: Option<PathBuf>, _jobs: JobArgs, _address: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Serving results needs the serve feature"))
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_rows<S, A>(_processor: &Processor<S, A>, _file: &Path, _path: PathBuf) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Writing Parquet needs the parquet feature"))
+}
+
+fn run(cli: Cli) -> io::Result<ExitCode> {
+    if cli.background {
+        if let Err(error) = lower_priority() {
+            eprintln!("Could not lower priority, continuing at normal priority: {error}");
+        }
+    }
+    match cli.command {
+        Some(Command::Estimate { file, sample_mb, tuning }) => {
+            let estimate = tuning.builder().build()?.estimate(&file, sample_mb * 1_000_000)?;
+            print!("{estimate}");
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Profile { file, sample_mb }) => {
+            let profile = InputProfile::from_reader(File::open(&file)?, sample_mb.map(|mb| mb * 1_000_000))?;
+            print!("{profile}");
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Check { file }) => {
+            let outcome = match file.as_str() {
+                "-" => check(io::stdin().lock())?,
+                file => check(File::open(file)?)?,
+            };
+            print!("{outcome}");
+            Ok(if outcome.is_ok() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+        }
+        Some(Command::Merge { parts, output }) => merge(&parts, output),
+        Some(Command::Serve { file, jobs, port, host, tuning }) => {
+            serve(tuning.builder().build()?, file, jobs, &format!("{host}:{port}"))?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Ingest(args)) => ingest(*args),
+        Some(Command::Generate { rows, stations, seed, out }) => {
+            match out {
+                Some(path) => generate(rows, stations, seed, File::create(path)?)?,
+                None => generate(rows, stations, seed, io::stdout().lock())?,
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Compare { file, strategies, tuning }) => {
+            let strategies = match strategies.is_empty() {
+                true => Strategy::available(),
+                false => strategies.into_iter().map(Strategy::from).collect(),
+            };
+            let comparison = compare(&file, || tuning.builder(), &strategies)?;
+            print!("{comparison}");
+            Ok(if comparison.is_consistent() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+        }
+        Some(Command::Validate { expected, actual, tolerance }) => {
+            let validation = validate(&fs::read_to_string(expected)?, &fs::read_to_string(actual)?, tolerance)?;
+            print!("{validation}");
+            Ok(if validation.is_ok() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+        }
+        Some(Command::Process(args)) => process(*args),
+        None => process(cli.process),
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
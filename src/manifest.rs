@@ -0,0 +1,149 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use rayon::prelude::*;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Checksum of one contiguous byte range of the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub offset: u64,
+    pub length: u64,
+    pub hash: u64,
+}
+
+/// Per-segment xxHash3 checksums of a file, stored as one `offset length hash` line per segment
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub segments: Vec<Segment>,
+}
+impl Manifest {
+    pub(crate) fn push(&mut self, bytes: &[u8]) {
+        let offset = self.segments.last().map_or(0, |last| last.offset + last.length);
+        self.segments.push(Segment { offset, length: bytes.len() as u64, hash: xxh3_64(bytes) });
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for segment in &self.segments {
+            writeln!(writer, "{} {} {:016x}", segment.offset, segment.length, segment.hash)?;
+        }
+        writer.flush()
+    }
+
+    pub fn read_from<R: Read>(reader: R) -> io::Result<Self> {
+        let invalid = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid manifest line: {line}"));
+        let mut segments = Vec::new();
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            let mut fields = line.split_whitespace();
+            let (Some(offset), Some(length), Some(hash), None) = (fields.next(), fields.next(), fields.next(), fields.next()) else {
+                return Err(invalid(&line));
+            };
+            segments.push(Segment {
+                offset: offset.parse().map_err(|_| invalid(&line))?,
+                length: length.parse().map_err(|_| invalid(&line))?,
+                hash: u64::from_str_radix(hash, 16).map_err(|_| invalid(&line))?,
+            });
+        }
+        Ok(Manifest { segments })
+    }
+}
+
+/// Re-hashes every segment of the file in parallel and returns the ones that no longer match.
+/// Bytes past the last segment, or a file that is too short, are reported as an extra mismatch.
+pub fn verify_manifest(address: &str, manifest: &Manifest) -> io::Result<Vec<Segment>> {
+    let file_length = File::open(address)?.metadata()?.len();
+    let mut mismatches = manifest.segments.par_iter().map(|segment| {
+        let mut file = File::open(address)?;
+        file.seek(SeekFrom::Start(segment.offset))?;
+        let mut bytes = Vec::new();
+        file.take(segment.length).read_to_end(&mut bytes)?;
+        let matches = bytes.len() as u64 == segment.length && xxh3_64(&bytes) == segment.hash;
+        Ok((!matches).then_some(*segment))
+    }).filter_map(Result::transpose).collect::<io::Result<Vec<_>>>()?;
+
+    let covered = manifest.segments.last().map_or(0, |last| last.offset + last.length);
+    if file_length != covered {
+        let offset = covered.min(file_length);
+        mismatches.push(Segment { offset, length: covered.abs_diff(file_length), hash: 0 });
+    }
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let mut manifest = Manifest::default();
+        manifest.push(b"Hamburg;12.0\n");
+        manifest.push(b"Bulawayo;8.9\n");
+        let mut written = Vec::new();
+        manifest.write_to(&mut written).unwrap();
+        assert_eq!(Manifest::read_from(written.as_slice()).unwrap(), manifest);
+        assert_eq!(manifest.segments[1].offset, 13);
+    }
+
+    // A manifest of the file written while aggregating it with the processor
+    fn written_manifest(address: &str, processor: crate::ProcessorBuilder) -> Manifest {
+        let manifest_path = format!("{address}.manifest");
+        processor.manifest_path(&manifest_path).build().unwrap().process(address).unwrap();
+        let manifest = Manifest::read_from(File::open(&manifest_path).unwrap()).unwrap();
+        std::fs::remove_file(&manifest_path).unwrap();
+        manifest
+    }
+
+    #[test]
+    fn test_manifest_of_each_backend() {
+        let path = std::env::temp_dir().join(format!("manifest_backends_{}.txt", std::process::id()));
+        let input = (0..20_000).map(|line| format!("Station {};{}.{}\n", line % 97, line % 100 - 50, line % 10)).collect::<String>();
+        std::fs::write(&path, &input).unwrap();
+        let address = path.to_str().unwrap();
+        let builder = || crate::Processor::builder().batch_lines(1_000);
+        let mut processors = vec![("inline", builder()), ("stream", builder().inline_threshold(0))];
+        if !cfg!(feature = "no-unsafe") {
+            processors.push(("mmap", builder().backend(crate::Backend::Mmap)));
+        }
+        for (name, processor) in processors {
+            let manifest = written_manifest(address, processor);
+            assert_eq!(manifest.segments.iter().map(|segment| segment.length).sum::<u64>(), input.len() as u64, "{name}");
+            assert_eq!(verify_manifest(address, &manifest).unwrap(), [], "{name}");
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_manifest_mismatches() {
+        let path = std::env::temp_dir().join(format!("manifest_mismatches_{}.txt", std::process::id()));
+        let input = (0..20_000).map(|line| format!("Station {};{}.{}\n", line % 97, line % 100 - 50, line % 10)).collect::<String>().into_bytes();
+        std::fs::write(&path, &input).unwrap();
+        let address = path.to_str().unwrap();
+        let manifest = written_manifest(address, crate::Processor::builder().batch_lines(1_000).inline_threshold(0));
+        assert!(manifest.segments.len() > 2);
+        let length = input.len() as u64;
+
+        // A changed byte fails only the segment holding it
+        let middle = &manifest.segments[manifest.segments.len() / 2];
+        let mut corrupted = input.clone();
+        corrupted[(middle.offset + middle.length / 2) as usize] ^= 1;
+        std::fs::write(&path, &corrupted).unwrap();
+        assert_eq!(verify_manifest(address, &manifest).unwrap(), [*middle]);
+
+        // A truncated file fails its last segment and reports the missing bytes
+        std::fs::write(&path, &input[..input.len() - 5]).unwrap();
+        let last = manifest.segments[manifest.segments.len() - 1];
+        assert_eq!(verify_manifest(address, &manifest).unwrap(), [last, Segment { offset: length - 5, length: 5, hash: 0 }]);
+
+        // An extended file keeps every segment and reports the bytes past them
+        let mut extended = input.clone();
+        extended.extend_from_slice(b"Hamburg;12.0\n");
+        std::fs::write(&path, &extended).unwrap();
+        assert_eq!(verify_manifest(address, &manifest).unwrap(), [Segment { offset: length, length: 13, hash: 0 }]);
+
+        std::fs::write(&path, &input).unwrap();
+        assert_eq!(verify_manifest(address, &manifest).unwrap(), []);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+}
@@ -0,0 +1,133 @@
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::ops::Range;
+use std::time::Duration;
+use memchr::{memchr, memchr_iter};
+use memmap2::Mmap;
+use rayon::prelude::*;
+use super::{merge_batches, merge_results, Aggregator, BatchRecord, Diagnostics, LineLengths, Manifest, ParsedBatch, ProcessError, Processor, Progress, LINE_SAMPLE_SIZE};
+
+impl<S: BuildHasher + Clone + Send + Sync, A: Aggregator> Processor<S, A> {
+    // Parses the file straight out of a read-only mapping, one batch-sized range per task
+    pub(crate) fn aggregate_mapped(&self, file: &File, manifest: Option<&mut Manifest>, progress: &Progress) -> Result<ParsedBatch<S, A>, ProcessError> {
+        if file.metadata()?.len() == 0 {
+            return Ok(self.empty_batch());
+        }
+        let mapping = map(file)?;
+        let bytes = &mapping[..];
+
+        let sample = &bytes[..bytes.len().min(LINE_SAMPLE_SIZE as usize)];
+        let detected = if self.config.detect_line_lengths { LineLengths::detect(sample) } else { None };
+        let read_size = self.config.read_size(self.config.line_lengths(detected));
+        if let Some(manifest) = manifest {
+            // Segments match the reads of the streaming backend, so manifests are interchangeable
+            bytes.chunks(read_size).for_each(|segment| manifest.push(segment));
+        }
+
+        let records = self.config.diagnostics.then(crossbeam::queue::SegQueue::new);
+        let master_map = self.pool.install(|| {
+            // Each fold covers a contiguous run of ranges, parsed into one map in input order
+            line_ranges(bytes, read_size).into_par_iter().fold(|| Ok(self.empty_batch()), |parsed_run: Result<_, ProcessError>, range| {
+                let mut parsed_run = parsed_run?;
+                // Ranges not yet started when the run is cancelled are skipped
+                if self.config.cancelled() {
+                    parsed_run.stats.cancelled = true;
+                    return Ok(parsed_run);
+                }
+                let batch = &bytes[range.clone()];
+                let parsed = self.parse_into(batch, &mut parsed_run.map)
+                    .map_err(|error| error.after_lines(memchr_iter(b'\n', &bytes[..range.start]).count() as u64))?;
+                if let Some(records) = &records {
+                    records.push(BatchRecord::new(batch.len(), parsed.lines, Duration::ZERO));
+                }
+                progress.advance(batch.len());
+                Ok(merge_batches(parsed_run, parsed))
+            }).reduce(|| Ok(self.empty_batch()), merge_results)
+        })?;
+        if let Some(records) = records {
+            eprint!("{}", Diagnostics::from_records(records));
+        }
+        Ok(master_map)
+    }
+}
+
+#[cfg(not(feature = "no-unsafe"))]
+fn map(file: &File) -> std::io::Result<Mmap> {
+    // SAFETY: the mapping is read-only and private to this call. It is only valid while no other
+    // process truncates or rewrites the file, which the documentation of `Backend::Mmap` relies on.
+    unsafe { Mmap::map(file) }
+}
+
+#[cfg(feature = "no-unsafe")]
+fn map(_file: &File) -> std::io::Result<Mmap> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Memory maps need unsafe code"))
+}
+
+// Splits `bytes` into ranges of roughly `size` bytes, each ending just after a newline or at the end
+fn line_ranges(bytes: &[u8], size: usize) -> Vec<Range<usize>> {
+    let mut ranges = Vec::with_capacity(bytes.len() / size.max(1) + 1);
+    let mut start = 0;
+    while start < bytes.len() {
+        let split = (start + size.max(1)).min(bytes.len());
+        let end = match memchr(b'\n', &bytes[split..]) {
+            Some(newline) => split + newline + 1,
+            None => bytes.len(),
+        };
+        ranges.push(start..end);
+        start = end;
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Backend;
+
+    #[test]
+    fn test_line_ranges() {
+        let bytes = b"Hamburg;12.0\nBulawayo;8.9\nPalembang;38.8\nPartial;1";
+        for size in 1..bytes.len() + 2 {
+            let ranges = line_ranges(bytes, size);
+            assert_eq!(ranges.first().unwrap().start, 0);
+            assert_eq!(ranges.last().unwrap().end, bytes.len());
+            for pair in ranges.windows(2) {
+                assert_eq!(pair[0].end, pair[1].start);
+                assert_eq!(bytes[pair[0].end - 1], b'\n');
+            }
+        }
+        assert!(line_ranges(b"", 4).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "no-unsafe")]
+    fn test_mmap_forbidden() {
+        assert!(Processor::builder().backend(Backend::Mmap).build().is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-unsafe"))]
+    fn test_mmap_backend() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("mmap_backend_{}.txt", std::process::id()));
+        let input = "Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\nZürich;-5.5";
+        File::create(&path).unwrap().write_all(input.as_bytes()).unwrap();
+        let address = path.to_str().unwrap();
+        let mapped = Processor::builder().backend(Backend::Mmap).avg_line_len(1).build().unwrap().process(address).unwrap();
+        let streamed = Processor::builder().build().unwrap().process(address).unwrap();
+        assert_eq!(mapped.len(), 3);
+        for ((name, data), (expected_name, expected)) in mapped.iter().zip(streamed.iter()) {
+            assert_eq!((name, data.to_string()), (expected_name, expected.to_string()));
+        }
+        File::create(&path).unwrap().write_all(b"Hamburg;12.0\nBulawayo;8.9\nbad\n").unwrap();
+        let result = Processor::builder().backend(Backend::Mmap).batch_lines(1).avg_line_len(1).build().unwrap().process(address);
+        assert!(matches!(result, Err(ProcessError::MalformedLine { line_no: 3, .. })));
+        File::create(&path).unwrap();
+        assert!(Processor::builder().backend(Backend::Mmap).build().unwrap().process(address).unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+        assert!(Processor::builder().backend(Backend::Mmap).max_read_mbps(1.0).build().is_err());
+    }
+
+}
@@ -0,0 +1,339 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufWriter, Write};
+use super::{Aggregator, Data, Results, Tenths, AVERAGE_STATION_LENGTH};
+
+/// How the aggregated stations are written out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// The challenge format, `{name=min/mean/max, ...}` on a single line
+    #[default]
+    Text,
+    /// One `name=min/mean/max` per line. Like `Text`, adds `/count` when counts are requested.
+    Lines,
+    /// A JSON object of `{"min", "mean", "max", "count"}` objects keyed by station name. This and
+    /// the tabular formats always include the count.
+    Json,
+    /// A `station,min,mean,max,count` header followed by one row per station
+    Csv,
+    /// As `Csv`, separated by tabs
+    Tsv,
+    /// The columns of `Csv` in a Parquet file, for every station in station order. Needs the
+    /// `parquet` feature.
+    Parquet,
+}
+
+/// Order of the stations in the output, ascending. Ties keep the order of their names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortBy {
+    /// Station name, as the challenge prints them
+    #[default]
+    Station,
+    Min,
+    Mean,
+    Max,
+    /// Number of readings
+    Count,
+}
+
+/// Keeps only the stations with the highest or lowest values of the sort column, for a quick
+/// look at large outputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// The highest this many, highest first
+    Top(usize),
+    /// The lowest this many, lowest first
+    Bottom(usize),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct OutputOptions {
+    pub format: Format,
+    pub number_width: Option<usize>,
+    pub sort_by: SortBy,
+    pub with_count: bool,
+    pub limit: Option<Limit>,
+}
+
+/// Aggregators whose results can be printed in every format: the challenge values from `Data`,
+/// followed by any extra named values in tenths of a degree
+pub trait Columns: Aggregator {
+    /// Names of the extra values, as used for JSON keys and CSV headers
+    const EXTRA: &'static [&'static str] = &[];
+
+    fn data(&self) -> &Data;
+    /// One value per name in `EXTRA`, in tenths
+    fn extra(&self) -> Vec<i64> {
+        Vec::new()
+    }
+}
+impl Columns for Data {
+    fn data(&self) -> &Data {
+        self
+    }
+}
+
+pub(crate) fn write_results<A: Columns, W: Write>(results: &Results<A>, options: OutputOptions, writer: W) -> io::Result<()> {
+    let writer_capacity: usize = results.len() * (AVERAGE_STATION_LENGTH + 21 + 6 * A::EXTRA.len());
+
+    let mut writer = BufWriter::with_capacity(writer_capacity, writer);
+    let stations = ordered(results, options.sort_by, options.limit).into_iter()
+        .map(|(station, columns)| (station, columns.data(), columns.extra()))
+        .collect::<Vec<_>>();
+    // The challenge's min/mean/max, then the extra values and the count, each padded to the number width
+    let write_values = |writer: &mut BufWriter<W>, data: &Data, extra: &[i64]| -> io::Result<()> {
+        let width = options.number_width.unwrap_or(0);
+        write!(writer, "{data:>width$}")?;
+        extra.iter().try_for_each(|&value| write!(writer, "/{:>width$}", Tenths(value)))?;
+        if options.with_count {
+            write!(writer, "/{:>width$}", data.count())?;
+        }
+        Ok(())
+    };
+    match options.format {
+        Format::Text => {
+            write!(writer, "{{")?;
+            for (index, (station, data, extra)) in stations.iter().enumerate() {
+                if index > 0 {
+                    write!(writer, ", ")?;
+                }
+                write!(writer, "{station}=")?;
+                write_values(&mut writer, data, extra)?;
+            }
+            writeln!(writer, "}}")?;
+        }
+        Format::Lines => {
+            // Pad names too so that the numeric columns line up
+            let name_width = match options.number_width {
+                Some(_) => stations.iter().map(|(station, _, _)| station.chars().count()).max().unwrap_or(0),
+                None => 0,
+            };
+            for (station, data, extra) in &stations {
+                write!(writer, "{station:<name_width$}=")?;
+                write_values(&mut writer, data, extra)?;
+                writeln!(writer)?;
+            }
+        }
+        Format::Json => {
+            write!(writer, "{{")?;
+            for (index, (station, data, extra)) in stations.iter().enumerate() {
+                if index > 0 {
+                    write!(writer, ",")?;
+                }
+                write_json_string(&mut writer, station)?;
+                write!(writer, ":")?;
+                write_json_values::<A, _>(&mut writer, data, extra)?;
+            }
+            writeln!(writer, "}}")?;
+        }
+        Format::Csv | Format::Tsv => {
+            let separator = if options.format == Format::Csv { ',' } else { '\t' };
+            write!(writer, "station{separator}min{separator}mean{separator}max")?;
+            for name in A::EXTRA {
+                write!(writer, "{separator}{name}")?;
+            }
+            writeln!(writer, "{separator}count")?;
+            for (station, data, extra) in &stations {
+                write_csv_field(&mut writer, station, separator)?;
+                let (min, mean, max) = (Tenths(data.min as i64), Tenths(data.mean_tenths()), Tenths(data.max as i64));
+                write!(writer, "{separator}{min}{separator}{mean}{separator}{max}")?;
+                for &value in extra {
+                    write!(writer, "{separator}{}", Tenths(value))?;
+                }
+                writeln!(writer, "{separator}{}", data.count())?;
+            }
+        }
+        #[cfg(feature = "parquet")]
+        Format::Parquet => crate::parquet::write_results(results, &mut writer)?,
+        #[cfg(not(feature = "parquet"))]
+        Format::Parquet => return Err(io::Error::new(io::ErrorKind::Unsupported, "Writing Parquet needs the parquet feature")),
+    }
+    writer.flush()
+}
+
+// The stations in output order, descending for the top of a limit. Ties keep the order of
+// their names either way.
+pub(crate) fn ordered<A: Columns>(results: &Results<A>, sort_by: SortBy, limit: Option<Limit>) -> Vec<(&str, &A)> {
+    let mut stations = results.iter().collect::<Vec<_>>();
+    let descending = matches!(limit, Some(Limit::Top(_)));
+    stations.sort_by(|(a_station, a), (b_station, b)| {
+        let (a, b) = (a.data(), b.data());
+        let ordering = match sort_by {
+            SortBy::Station => a_station.cmp(b_station),
+            SortBy::Min => a.min.cmp(&b.min),
+            SortBy::Mean => a.mean_tenths().cmp(&b.mean_tenths()),
+            SortBy::Max => a.max.cmp(&b.max),
+            SortBy::Count => a.count.cmp(&b.count),
+        };
+        if descending { ordering.reverse() } else { ordering }
+    });
+    if let Some(Limit::Top(n) | Limit::Bottom(n)) = limit {
+        stations.truncate(n);
+    }
+    stations
+}
+
+// Quotes names holding the separator, quotes or line breaks, doubling any quotes as in RFC 4180
+fn write_csv_field<W: Write>(writer: &mut W, value: &str, separator: char) -> io::Result<()> {
+    if value.contains([separator, '"', '\n', '\r']) {
+        write!(writer, "\"{}\"", value.replace('"', "\"\""))
+    } else {
+        write!(writer, "{value}")
+    }
+}
+
+// One station's `{"min", "mean", "max", ..., "count"}` object
+pub(crate) fn write_json_values<A: Columns, W: Write>(writer: &mut W, data: &Data, extra: &[i64]) -> io::Result<()> {
+    let (min, mean, max) = (Tenths(data.min as i64), Tenths(data.mean_tenths()), Tenths(data.max as i64));
+    write!(writer, "{{\"min\":{min},\"mean\":{mean},\"max\":{max}")?;
+    for (name, &value) in A::EXTRA.iter().zip(extra) {
+        write!(writer, ",\"{name}\":{}", Tenths(value))?;
+    }
+    write!(writer, ",\"count\":{}}}", data.count())
+}
+
+pub(crate) fn write_json_string<W: Write>(writer: &mut W, value: &str) -> io::Result<()> {
+    write!(writer, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(writer, "\\\"")?,
+            '\\' => write!(writer, "\\\\")?,
+            c if c.is_control() => write!(writer, "\\u{:04x}", c as u32)?,
+            c => write!(writer, "{c}")?,
+        }
+    }
+    write!(writer, "\"")
+}
+
+/// One station's values as read back from printed results
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Summary {
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+/// Reads printed results back into a map by station name. Accepts both formats, padded numbers
+/// and the output of other implementations that follow the challenge layout. Values after the
+/// maximum, such as counts, are ignored.
+pub fn parse_results(text: &str) -> io::Result<BTreeMap<String, Summary>> {
+    let text = text.trim();
+    let body = text.strip_prefix('{').and_then(|body| body.strip_suffix('}')).unwrap_or(text);
+    let mut stations = BTreeMap::new();
+    // Names may themselves contain ", ", so pieces are joined until they form a whole entry
+    let mut pending = String::new();
+    for line in body.lines() {
+        for piece in line.split(", ") {
+            if pending.is_empty() && piece.trim().is_empty() {
+                continue;
+            }
+            pending.push_str(piece);
+            match parse_entry(&pending) {
+                Some((station, summary)) => {
+                    stations.insert(station.to_string(), summary);
+                    pending.clear();
+                }
+                None => pending.push_str(", "),
+            }
+        }
+        if !pending.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid result: {:?}", pending.trim_end_matches(", "))));
+        }
+    }
+    Ok(stations)
+}
+
+fn parse_entry(entry: &str) -> Option<(&str, Summary)> {
+    let (station, values) = entry.rsplit_once('=')?;
+    let mut values = values.split('/').map(|value| value.trim().parse::<f64>().ok());
+    let (Some(Some(min)), Some(Some(mean)), Some(Some(max))) = (values.next(), values.next(), values.next()) else {
+        return None;
+    };
+    Some((station.trim_end(), Summary { min, mean, max }))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use hashbrown::HashMap;
+    use super::*;
+
+    #[test]
+    fn test_padded_lines() {
+        let mut map = HashMap::<String, Data>::new();
+        map.insert("Abha".to_string(), Data { sum: 180, count: 1, min: -12, max: 403 });
+        map.insert("Zürich".to_string(), Data { sum: -5, count: 1, min: -5, max: -5 });
+        let options = OutputOptions { format: Format::Lines, number_width: Some(5), ..Default::default() };
+        let mut output = Vec::new();
+        write_results(&Results::from_map(map), options, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "Abha  = -1.2/ 18.0/ 40.3\nZürich= -0.5/ -0.5/ -0.5\n");
+    }
+
+    #[test]
+    fn test_parse_results() {
+        let mut map = HashMap::<String, Data>::new();
+        map.insert("Abha".to_string(), Data { sum: 180, count: 1, min: -12, max: 403 });
+        map.insert("Washington, D.C.".to_string(), Data { sum: -5, count: 1, min: -5, max: -5 });
+        let results = Results::from_map(map);
+        for options in [OutputOptions::default(), OutputOptions { format: Format::Lines, number_width: Some(6), ..Default::default() }] {
+            let mut output = Vec::new();
+            write_results(&results, options, &mut output).unwrap();
+            let parsed = parse_results(std::str::from_utf8(&output).unwrap()).unwrap();
+            assert_eq!(parsed.len(), 2);
+            assert_eq!(parsed["Abha"], Summary { min: -1.2, mean: 18.0, max: 40.3 });
+            assert_eq!(parsed["Washington, D.C."].max, -0.5);
+        }
+        assert!(parse_results("{}").unwrap().is_empty());
+        assert!(parse_results("{Abha=1.0/2.0}").is_err());
+    }
+
+    #[test]
+    fn test_with_count() {
+        let mut map = HashMap::<String, Data>::new();
+        map.insert("Abha".to_string(), Data { sum: 180, count: 2, min: -12, max: 192 });
+        map.insert("Zürich".to_string(), Data { sum: -5, count: 1, min: -5, max: -5 });
+        let results = Results::from_map(map);
+        let mut output = Vec::new();
+        write_results(&results, OutputOptions { with_count: true, ..Default::default() }, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output.clone()).unwrap(), "{Abha=-1.2/9.0/19.2/2, Zürich=-0.5/-0.5/-0.5/1}\n");
+        assert_eq!(parse_results(std::str::from_utf8(&output).unwrap()).unwrap()["Abha"], Summary { min: -1.2, mean: 9.0, max: 19.2 });
+        let mut output = Vec::new();
+        write_results(&results, OutputOptions { format: Format::Lines, number_width: Some(5), with_count: true, ..Default::default() }, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "Abha  = -1.2/  9.0/ 19.2/    2\nZürich= -0.5/ -0.5/ -0.5/    1\n");
+    }
+
+    #[test]
+    fn test_json() {
+        let mut map = HashMap::<String, Data>::new();
+        map.insert("Abha".to_string(), Data { sum: 180, count: 2, min: -12, max: 192 });
+        map.insert("Quote\"d\n".to_string(), Data { sum: -5, count: 1, min: -5, max: -5 });
+        let mut output = Vec::new();
+        write_results(&Results::from_map(map), OutputOptions { format: Format::Json, ..Default::default() }, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"Abha\":{\"min\":-1.2,\"mean\":9.0,\"max\":19.2,\"count\":2},\"Quote\\\"d\\u000a\":{\"min\":-0.5,\"mean\":-0.5,\"max\":-0.5,\"count\":1}}\n",
+        );
+    }
+
+    #[test]
+    fn test_csv_and_sorting() {
+        let mut map = HashMap::<String, Data>::new();
+        map.insert("Abha".to_string(), Data { sum: 180, count: 2, min: -12, max: 192 });
+        map.insert("Washington, D.C.".to_string(), Data { sum: -5, count: 1, min: -5, max: -5 });
+        map.insert("Zürich".to_string(), Data { sum: 30, count: 3, min: 10, max: 10 });
+        let results = Results::from_map(map);
+        let mut output = Vec::new();
+        results.write_csv(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "station,min,mean,max,count\nAbha,-1.2,9.0,19.2,2\n\"Washington, D.C.\",-0.5,-0.5,-0.5,1\nZürich,1.0,1.0,1.0,3\n",
+        );
+        let mut output = Vec::new();
+        write_results(&results, OutputOptions { format: Format::Tsv, sort_by: SortBy::Mean, ..Default::default() }, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "station\tmin\tmean\tmax\tcount\nWashington, D.C.\t-0.5\t-0.5\t-0.5\t1\nZürich\t1.0\t1.0\t1.0\t3\nAbha\t-1.2\t9.0\t19.2\t2\n",
+        );
+    }
+
+}
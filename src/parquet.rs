@@ -0,0 +1,181 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::Arc;
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float64Type, Int64Type, UInt32Type};
+use arrow_array::{Array, ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::file::reader::ChunkReader;
+use super::parse::{parse_decimal, parse_wide_i32};
+use super::{compression, process_line, Aggregator, Columns, Data, LinePolicy, ProcessError, Processor, Results, Utf8Policy, STDIN_ADDRESS};
+
+// Readings per row group of a rows file, bounding the memory held before each write
+const ROWS_PER_BATCH: usize = 1 << 20;
+const SUM_COLUMN: &str = "sum_tenths";
+
+// Writes the columns of `Results::to_arrow` as a single row group, followed by each station's
+// exact sum in tenths so that `Results::read_parquet` recovers the aggregates the rounded mean
+// cannot
+pub(crate) fn write_results<A: Columns, W: Write>(results: &Results<A>, mut writer: W) -> io::Result<()> {
+    let batch = results.to_arrow().map_err(io::Error::other)?;
+    let mut fields = batch.schema().fields().iter().map(|field| field.as_ref().clone()).collect::<Vec<_>>();
+    fields.push(Field::new(SUM_COLUMN, DataType::Int64, false));
+    let mut columns = batch.columns().to_vec();
+    columns.push(Arc::new(Int64Array::from_iter_values(results.iter().map(|(_, columns)| columns.data().sum))));
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).map_err(io::Error::other)?;
+    // The encoder wants a `Send` writer, so the file is built in memory, which is small for results
+    let mut buffer = Vec::new();
+    let mut encoder = ArrowWriter::try_new(&mut buffer, batch.schema(), None).map_err(io::Error::other)?;
+    encoder.write(&batch).map_err(io::Error::other)?;
+    encoder.close().map_err(io::Error::other)?;
+    writer.write_all(&buffer)
+}
+
+impl Results<Data> {
+    /// Reads back results written in `Format::Parquet`, exactly as they were aggregated, from the
+    /// `station`, `min`, `max`, `count` and `sum_tenths` columns. Stats and invalid lines are not
+    /// part of the file.
+    pub fn read_parquet<R: ChunkReader + 'static>(reader: R) -> io::Result<Self> {
+        let batches = ParquetRecordBatchReaderBuilder::try_new(reader).and_then(|builder| builder.build()).map_err(io::Error::other)?;
+        let mut entries: Vec<(String, Data)> = Vec::new();
+        for batch in batches {
+            let batch = batch.map_err(io::Error::other)?;
+            let column = |name: &str| batch.column_by_name(name).filter(|column| column.null_count() == 0)
+                .ok_or_else(|| invalid(&format!("No complete {name} column")));
+            let mismatch = || invalid("Unexpected column type");
+            let stations = column("station")?.as_string_opt::<i32>().ok_or_else(mismatch)?;
+            let mins = column("min")?.as_primitive_opt::<Float64Type>().ok_or_else(mismatch)?;
+            let maxes = column("max")?.as_primitive_opt::<Float64Type>().ok_or_else(mismatch)?;
+            let counts = column("count")?.as_primitive_opt::<UInt32Type>().ok_or_else(mismatch)?;
+            let sums = column(SUM_COLUMN)?.as_primitive_opt::<Int64Type>().ok_or_else(mismatch)?;
+            // The extremes are written in degrees, one decimal place being exact once rounded
+            let tenths = |degrees: f64| (degrees * 10.0).round() as i32;
+            for row in 0..batch.num_rows() {
+                let data = Data { sum: sums.value(row), count: counts.value(row), min: tenths(mins.value(row)), max: tenths(maxes.value(row)) };
+                if data.count == 0 || data.min > data.max {
+                    return Err(invalid("Inconsistent station values"));
+                }
+                let station = stations.value(row);
+                if entries.last().is_some_and(|(last, _)| last.as_str() >= station) {
+                    return Err(invalid("Stations out of order"));
+                }
+                entries.push((station.to_string(), data));
+            }
+        }
+        Ok(Results::from_sorted(entries))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl<S: BuildHasher + Clone + Send + Sync, A: Aggregator> Processor<S, A> {
+    /// Writes every reading of the input as a Parquet row of `station` and `value`, for analysis
+    /// beyond the aggregates, and returns the number of rows. The input is read sequentially,
+    /// with the decompression, value range and line policy of `process`.
+    pub fn write_rows_parquet<W: Write + Send>(&self, address: &str, writer: W) -> Result<u64, ProcessError> {
+        let mut source: Box<dyn BufRead + Send> = if address == STDIN_ADDRESS {
+            Box::new(BufReader::new(compression::decompress(io::stdin(), self.config.compression)?))
+        } else {
+            let mut file = File::open(address)?;
+            let compression = self.config.compression.of_file(&mut file)?;
+            Box::new(BufReader::new(compression::decoder(BufReader::new(file), compression)?))
+        };
+        let schema = Arc::new(Schema::new(vec![Field::new("station", DataType::Utf8, false), Field::new("value", DataType::Float64, false)]));
+        let mut encoder = ArrowWriter::try_new(writer, schema.clone(), None).map_err(io::Error::other)?;
+
+        let (mut stations, mut values) = (Vec::with_capacity(ROWS_PER_BATCH), Vec::with_capacity(ROWS_PER_BATCH));
+        let (mut line, mut line_no, mut rows) = (Vec::new(), 0, 0);
+        let parse = |value: &str| match self.config.max_decimals {
+            Some(decimals) => parse_decimal(value, self.config.max_integer_digits, decimals),
+            None => parse_wide_i32(value, self.config.max_integer_digits),
+        };
+        while source.read_until(b'\n', &mut line)? > 0 {
+            line_no += 1;
+            let decoded = match self.config.utf8_policy {
+                Utf8Policy::Validate => Cow::Borrowed(std::str::from_utf8(&line).map_err(|error| ProcessError::InvalidUtf8 { line_no, offset: error.valid_up_to() })?),
+                Utf8Policy::Lossy => String::from_utf8_lossy(&line),
+            };
+            let text = decoded.strip_suffix('\n').unwrap_or(&decoded);
+            let text = text.strip_suffix('\r').unwrap_or(text);
+            match process_line(text, self.config.line_format(), parse) {
+                Some((station, value)) => {
+                    stations.push(station.to_string());
+                    values.push(value as f64 / 10.0);
+                }
+                None if text.is_empty() => {}
+                None if self.config.line_policy == LinePolicy::Strict => {
+                    return Err(ProcessError::MalformedLine { line_no, contents: text.to_string() });
+                }
+                None => {}
+            }
+            line.clear();
+            if stations.len() == ROWS_PER_BATCH {
+                rows += write_rows(&mut encoder, &schema, &mut stations, &mut values)?;
+            }
+        }
+        if !stations.is_empty() {
+            rows += write_rows(&mut encoder, &schema, &mut stations, &mut values)?;
+        }
+        encoder.close().map_err(io::Error::other)?;
+        Ok(rows)
+    }
+}
+
+fn write_rows<W: Write + Send>(encoder: &mut ArrowWriter<W>, schema: &SchemaRef, stations: &mut Vec<String>, values: &mut Vec<f64>) -> io::Result<u64> {
+    let rows = stations.len() as u64;
+    let columns: Vec<ArrayRef> = vec![Arc::new(StringArray::from(std::mem::take(stations))), Arc::new(Float64Array::from(std::mem::take(values)))];
+    let batch = RecordBatch::try_new(schema.clone(), columns).map_err(io::Error::other)?;
+    encoder.write(&batch).map_err(io::Error::other)?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Format;
+
+    fn read(bytes: Vec<u8>) -> Vec<RecordBatch> {
+        ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(bytes)).unwrap().build().unwrap().collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn test_parquet_results() {
+        let input = &b"Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n"[..];
+        let processor = Processor::builder().format(Format::Parquet).build().unwrap();
+        let results = processor.process_reader(input).unwrap();
+        let mut output = Vec::new();
+        processor.write_results(&results, &mut output).unwrap();
+        let batches = read(output.clone());
+        let arrow = results.to_arrow().unwrap();
+        assert_eq!(batches[0].project(&(0..arrow.num_columns()).collect::<Vec<_>>()).unwrap(), arrow);
+        assert_eq!(batches[0].column_by_name(SUM_COLUMN).unwrap().as_primitive::<Int64Type>().values(), &[89, 86]);
+
+        let restored = Results::read_parquet(bytes::Bytes::from(output)).unwrap();
+        assert_eq!(restored.to_string(), results.to_string());
+        assert!(Results::read_parquet(bytes::Bytes::from(&b"Hamburg;12.0\n"[..])).is_err());
+    }
+
+    #[test]
+    fn test_parquet_rows() {
+        let path = std::env::temp_dir().join(format!("parquet_rows_{}.txt", std::process::id()));
+        std::fs::write(&path, "Hamburg;12.0\r\nbad\nBulawayo;8.9\r\n\r\nHamburg;-3.4").unwrap();
+        let address = path.to_str().unwrap();
+        let mut output = Vec::new();
+        let processor = Processor::builder().line_policy(LinePolicy::Skip).build().unwrap();
+        assert_eq!(processor.write_rows_parquet(address, &mut output).unwrap(), 3);
+        let batches = read(output);
+        assert_eq!(batches[0].column(0).as_string::<i32>().iter().flatten().collect::<Vec<_>>(), ["Hamburg", "Bulawayo", "Hamburg"]);
+        assert_eq!(batches[0].column(1).as_primitive::<Float64Type>().values(), &[12.0, 8.9, -3.4]);
+        let result = Processor::builder().build().unwrap().write_rows_parquet(address, io::sink());
+        assert!(matches!(result, Err(ProcessError::MalformedLine { line_no: 2, .. })));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+}
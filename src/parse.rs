@@ -0,0 +1,292 @@
+//! The line splitter and fixed-point value parsers the pipeline is built on, for reuse in other
+//! pipelines. Values are in tenths, the unit `Data` keeps them in.
+
+use memchr::{memchr, memrchr};
+use super::{LINE_DELIMITER, MAX_INTEGER_DIGITS};
+
+/// Which of the two fields of a line comes first
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FieldOrder {
+    /// `station;value`, as in the challenge
+    #[default]
+    StationFirst,
+    /// `value;station`
+    ValueFirst,
+}
+
+/// The delimiter and field order of the input lines, `station;value` by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineFormat {
+    delimiter: u8,
+    order: FieldOrder,
+}
+impl Default for LineFormat {
+    fn default() -> Self {
+        LineFormat { delimiter: LINE_DELIMITER as u8, order: FieldOrder::StationFirst }
+    }
+}
+impl LineFormat {
+    /// `None` unless the delimiter is a single ASCII character other than a line break, which
+    /// keeps every split on a character boundary
+    pub fn new(delimiter: char, order: FieldOrder) -> Option<LineFormat> {
+        (delimiter.is_ascii() && delimiter != '\n' && delimiter != '\r').then_some(LineFormat { delimiter: delimiter as u8, order })
+    }
+
+    pub(crate) fn delimiter(&self) -> u8 {
+        self.delimiter
+    }
+
+    pub(crate) fn order(&self) -> FieldOrder {
+        self.order
+    }
+
+    // Splits a line like `split_line`, given the offsets of its first and last delimiters
+    pub(crate) fn split_at<'a>(&self, line: &'a str, first_delimiter: Option<usize>, last_delimiter: Option<usize>) -> Option<(&'a str, &'a str)> {
+        match self.order {
+            FieldOrder::StationFirst => {
+                let delimiter = last_delimiter?;
+                Some((&line[..delimiter], &line[delimiter + 1..]))
+            }
+            FieldOrder::ValueFirst => {
+                let delimiter = first_delimiter?;
+                Some((&line[delimiter + 1..], &line[..delimiter]))
+            }
+        }
+    }
+}
+
+/// Splits a line into its station and value. The split is at the delimiter next to the value, so
+/// station names may themselves hold the delimiter.
+pub fn split_line(line: &str, format: LineFormat) -> Option<(&str, &str)> {
+    match format.order {
+        FieldOrder::StationFirst => {
+            let delimiter = memrchr(format.delimiter, line.as_bytes())?;
+            Some((&line[..delimiter], &line[delimiter + 1..]))
+        }
+        FieldOrder::ValueFirst => {
+            let delimiter = memchr(format.delimiter, line.as_bytes())?;
+            Some((&line[delimiter + 1..], &line[..delimiter]))
+        }
+    }
+}
+
+fn to_digit(c: u8) -> Option<i32> {
+    c.is_ascii_digit().then(|| (c - b'0') as i32)
+}
+
+/// Parses a value in the challenge's format, `-?d?d.d`, into tenths. Anything else is rejected,
+/// including values with more or fewer digits.
+pub fn parse_i32(value: &str) -> Option<i32> {
+    // Whatever the branch-light parse cannot vouch for is settled by matching its shape
+    parse_fixed_width(value).or_else(|| parse_by_shape(value))
+}
+
+// Branch-light parse of the four shapes, `d.d`, `dd.d`, `-d.d` and `-dd.d`, from one 8-byte word.
+// The point is the only byte of those shapes besides the sign with bit 4 clear, so its position
+// comes from a single trailing-zeros count. The digits are then shifted to fixed places and
+// combined with one multiplication.
+fn parse_fixed_width(value: &str) -> Option<i32> {
+    let bytes = value.as_bytes();
+    let mut word = [0; 8];
+    word.get_mut(..bytes.len())?.copy_from_slice(bytes);
+    parse_word(u64::from_le_bytes(word), bytes.len())
+}
+
+// `parse_fixed_width` of the first `length` bytes of `word`, whatever the bytes after them, for
+// callers that can load the word straight from the input
+pub(crate) fn parse_word(word: u64, length: usize) -> Option<i32> {
+    if !(3..=5).contains(&length) {
+        return None;
+    }
+    let value_bytes = u64::MAX >> (64 - 8 * length);
+    let word = word & value_bytes;
+    // Bit offset of bit 4 of the point, looked for in the second to fourth bytes
+    let dot = (!word & 0x1010_1000).trailing_zeros();
+    let point = dot as usize >> 3;
+    let negative = word as u8 == b'-';
+    let integer_digits = point.wrapping_sub(negative as usize);
+    if point + 2 != length || (word >> (8 * point)) as u8 != b'.' || !(1..=2).contains(&integer_digits) {
+        return None;
+    }
+    let sign_byte = 0xFF * negative as u64;
+    let digit_bytes = value_bytes & !(0xFF << (8 * point)) & !sign_byte;
+    // Each digit byte minus '0' is below 10 exactly when adding 0x76 leaves bit 7 clear
+    let offsets = (word ^ 0x3030_3030_3030_3030) & digit_bytes;
+    if (offsets.wrapping_add(0x7676_7676_7676_7676) | offsets) & 0x8080_8080_8080_8080 & digit_bytes != 0 {
+        return None;
+    }
+    // Tens, units and tenths land in bytes 1, 2 and 4, a missing tens digit as zero, and the
+    // multiplication sums them as 100 * tens + 10 * units + tenths in bits 32 and up
+    let digits = ((word & !sign_byte) << (28 - dot)) & 0x0F_000F_0F00;
+    let magnitude = (digits.wrapping_mul(0x640A_0001) >> 32) & 0x3FF;
+    let sign = -(negative as i32);
+    Some((magnitude as i32 ^ sign) - sign)
+}
+
+fn parse_by_shape(value: &str) -> Option<i32> {
+    let (negative, digits) = match value.as_bytes() {
+        [b'-', digits @ ..] => (true, digits),
+        digits => (false, digits),
+    };
+    let result = match *digits {
+        [units, b'.', tenths] => to_digit(units)? * 10 + to_digit(tenths)?,
+        [tens, units, b'.', tenths] => to_digit(tens)? * 100 + to_digit(units)? * 10 + to_digit(tenths)?,
+        _ => return None,
+    };
+    Some(if negative { -result } else { result })
+}
+
+/// General form of `parse_i32` for values with up to `max_integer_digits` before the decimal
+/// point, at most 8 so that the result fits an `i32`
+pub fn parse_wide_i32(value: &str, max_integer_digits: usize) -> Option<i32> {
+    // Most values of wider datasets still fit the challenge's format, which takes the unrolled path
+    if max_integer_digits >= 2 {
+        if let Some(result) = parse_i32(value) {
+            return Some(result);
+        }
+    }
+    let (negative, digits) = match value.as_bytes() {
+        [b'-', digits @ ..] => (true, digits),
+        digits => (false, digits),
+    };
+    let [integer @ .., b'.', tenths] = digits else {
+        return None;
+    };
+    if integer.is_empty() || integer.len() > max_integer_digits.min(MAX_INTEGER_DIGITS) {
+        return None;
+    }
+    let mut result = 0;
+    for &digit in integer {
+        result = result * 10 + to_digit(digit)?;
+    }
+    result = result * 10 + to_digit(*tenths)?;
+    Some(if negative { -result } else { result })
+}
+
+/// Parses a value with anything from no decimal places up to `max_decimals` into tenths, rounding
+/// half away from zero, for inputs like `12` or `12.34`. Integer digits are limited as in
+/// `parse_wide_i32`.
+pub fn parse_decimal(value: &str, max_integer_digits: usize, max_decimals: usize) -> Option<i32> {
+    let (negative, digits) = match value.as_bytes() {
+        [b'-', digits @ ..] => (true, digits),
+        digits => (false, digits),
+    };
+    let (integer, fraction) = match memchr(b'.', digits) {
+        Some(point) if point + 1 < digits.len() => (&digits[..point], &digits[point + 1..]),
+        Some(_) => return None,
+        None => (digits, &[][..]),
+    };
+    if integer.is_empty() || integer.len() > max_integer_digits.min(MAX_INTEGER_DIGITS) || fraction.len() > max_decimals {
+        return None;
+    }
+    let mut result = 0;
+    for &digit in integer {
+        result = result * 10 + to_digit(digit)?;
+    }
+    let tenths = match fraction.first() {
+        Some(&digit) => to_digit(digit)?,
+        None => 0,
+    };
+    result = result * 10 + tenths;
+    // Only the hundredths decide the rounding, but every later digit must still be one
+    for (index, &digit) in fraction.iter().enumerate().skip(1) {
+        if to_digit(digit)? >= 5 && index == 1 {
+            result += 1;
+        }
+    }
+    Some(if negative { -result } else { result })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parse_i32() {
+        assert_eq!(parse_i32("-12.3"), Some(-123));
+        assert_eq!(parse_i32("12.3"), Some(123));
+        assert_eq!(parse_i32("-1.3"), Some(-13));
+        assert_eq!(parse_i32("2.3"), Some(23));
+        assert_eq!(parse_i32("-0.3"), Some(-3));
+        assert_eq!(parse_i32("0.3"), Some(3));
+    }
+
+    #[test]
+    fn test_parse_i32_rejects_malformed_values() {
+        for value in ["", "-", "1", ".5", "1.", "12", "1.23", "123.4", "--1.2", "a.b", "1,2", "-1é2"] {
+            assert_eq!(parse_i32(value), None, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn test_branch_light_parse_matches_shapes() {
+        // Every string of up to six of these bytes, valid or not
+        const ALPHABET: &[u8] = b"-.0159/a ";
+        let mut value = Vec::new();
+        for length in 0..=6u32 {
+            for mut index in 0..ALPHABET.len().pow(length) {
+                value.clear();
+                for _ in 0..length {
+                    value.push(ALPHABET[index % ALPHABET.len()]);
+                    index /= ALPHABET.len();
+                }
+                let value = std::str::from_utf8(&value).unwrap();
+                let expected = parse_by_shape(value);
+                if let Some(parsed) = parse_fixed_width(value) {
+                    assert_eq!(Some(parsed), expected, "{value:?}");
+                }
+                assert_eq!(parse_i32(value), expected, "{value:?}");
+            }
+        }
+        for value in (-999..=999).flat_map(|tenths: i32| [format!("{}.{}", tenths / 10, tenths.abs() % 10), format!("-{}.{}", tenths.abs() / 10, tenths.abs() % 10)]) {
+            assert_eq!(parse_fixed_width(&value), parse_by_shape(&value), "{value:?}");
+            assert!(parse_fixed_width(&value).is_some(), "{value:?}");
+        }
+        for value in ["1é", "1é2", "-é.1", "+1.2", "1/2", "12.3\r"] {
+            assert_eq!(parse_fixed_width(value), None, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_wide_i32() {
+        for value in ["-12.3", "12.3", "-1.3", "2.3", "-0.3", "0.3"] {
+            assert_eq!(parse_wide_i32(value, 2), parse_i32(value));
+        }
+        assert_eq!(parse_wide_i32("-123.4", 4), Some(-1234));
+        assert_eq!(parse_wide_i32("1023.7", 4), Some(10237));
+        assert_eq!(parse_wide_i32("12345.6", 4), None);
+        assert_eq!(parse_wide_i32("99999999.9", 20), Some(999_999_999));
+        assert_eq!(parse_wide_i32("12.3", 1), None);
+        assert_eq!(parse_wide_i32("999999999.9", 20), None);
+        for value in ["", "-", ".5", "1.", "-.5", "1.23", "1a.2"] {
+            assert_eq!(parse_wide_i32(value, 4), None, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_decimal() {
+        let parsed = ["12", "-12", "12.3", "12.34", "12.35", "-12.35", "0.05", "-0.04", "9.99", "1234.5"].map(|value| parse_decimal(value, 4, 2));
+        assert_eq!(parsed, [Some(120), Some(-120), Some(123), Some(123), Some(124), Some(-124), Some(1), Some(0), Some(100), Some(12345)]);
+        for value in ["", "-", ".5", "1.", "12.345", "12345", "1a", "1.2a", "1.a"] {
+            assert_eq!(parse_decimal(value, 4, 2), None, "{value:?}");
+        }
+        assert_eq!(parse_decimal("12.3", 2, 0), None);
+        assert_eq!(parse_decimal("12", 2, 0), Some(120));
+    }
+
+    #[test]
+    fn test_split_line() {
+        let format = LineFormat::default();
+        assert_eq!(split_line("Hamburg;12.0", format), Some(("Hamburg", "12.0")));
+        assert_eq!(split_line("a;b;-1.5", format), Some(("a;b", "-1.5")));
+        assert_eq!(split_line("Hamburg 12.0", format), None);
+        let format = LineFormat::new(',', FieldOrder::ValueFirst).unwrap();
+        assert_eq!(split_line("12.0,Hamburg", format), Some(("Hamburg", "12.0")));
+        assert_eq!(split_line("-1.5,a,b", format), Some(("a,b", "-1.5")));
+        assert_eq!(split_line("12.0;Hamburg", format), None);
+        assert_eq!(LineFormat::new('\n', FieldOrder::StationFirst), None);
+        assert_eq!(LineFormat::new('ü', FieldOrder::StationFirst), None);
+    }
+
+}
@@ -0,0 +1,279 @@
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+use crossbeam::queue::SegQueue;
+use memchr::{memchr_iter, memrchr};
+use rayon::prelude::*;
+use super::{merge_batches, merge_results, open_sequential, source, Aggregator, BatchRecord, Compression, Counted, Diagnostics, LineLengths, ParsedBatch, ProcessError, Processor, Progress, ReadAhead, Results, LINE_SAMPLE_SIZE};
+
+// First probe around a split point, comfortably longer than the longest valid line
+const PROBE_SIZE: u64 = 4 * 1024;
+
+impl<S: BuildHasher + Clone + Send + Sync, A: Aggregator> Processor<S, A> {
+    // Splits the file into one newline-aligned range per worker, each read through its own handle
+    pub(crate) fn aggregate_partitioned(&self, address: &str, progress: &Progress) -> Result<ParsedBatch<S, A>, ProcessError> {
+        let length = File::open(address)?.metadata()?.len();
+        let detected = match self.config.detect_line_lengths {
+            true => LineLengths::detect(&read_range(address, 0, length.min(LINE_SAMPLE_SIZE))?),
+            false => None,
+        };
+        let read_size = self.config.read_size(self.config.line_lengths(detected)) as u64;
+
+        let records = self.config.diagnostics.then(SegQueue::new);
+        let master_map = self.pool.install(|| {
+            let shards = align_shards(length, self.pool.num_threads(), |start, end| read_range(address, start, end))?;
+            shards.into_par_iter()
+                .map(|shard| self.aggregate_shard(address, shard, read_size, records.as_ref(), progress))
+                .reduce(|| Ok(self.empty_batch()), merge_results)
+        })?;
+        if let Some(records) = records {
+            eprint!("{}", Diagnostics::from_records(records));
+        }
+        Ok(master_map)
+    }
+
+    /// Aggregates only shard `shard` of `shards` newline-aligned byte ranges of the file, counted
+    /// from 0, so that separate machines can each take a share of one file and their saved
+    /// results can be merged. Each line falls in exactly one shard of the same count. Errors are
+    /// numbered from the start of the file.
+    pub fn process_shard(&self, address: &str, shard: usize, shards: usize) -> Result<Results<A>, ProcessError> {
+        if shard >= shards {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("No shard {shard} of {shards}")).into());
+        }
+        if self.config.manifest_path.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "A manifest covers a whole input").into());
+        }
+        let start = Instant::now();
+        let mut file = open_sequential(address)?;
+        if self.config.compression.of_file(&mut file)? != Compression::None {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "Compressed files cannot be split into shards").into());
+        }
+        let length = file.metadata()?.len();
+        let fetch = |start, end| read_range(address, start, end);
+        let range = align_split(length, shard, shards, fetch)?..align_split(length, shard + 1, shards, fetch)?;
+        self.process_range(file, range.clone(), || lines_before(address, range.start), start)
+    }
+
+    // Aggregates one newline-aligned byte range of an uncompressed file on the streaming
+    // pipeline. Lines are numbered after the `before` lines that precede the range, only counted
+    // when a line needs a number.
+    pub(crate) fn process_range(&self, mut file: File, range: Range<u64>, before: impl Fn() -> io::Result<u64>, start: Instant) -> Result<Results<A>, ProcessError> {
+        file.seek(SeekFrom::Start(range.start))?;
+        let progress = Progress::new(self.config.progress.as_ref(), Some(range.end - range.start));
+        let source = source::wrap(Counted::new(ReadAhead::new(file, range.start, self.config.readahead).take(range.end - range.start), &progress), &self.config)?;
+        let mut parsed = match self.aggregate(source, None) {
+            Ok(parsed) => parsed,
+            Err(error) => return Err(error.after_lines(before()?)),
+        };
+        if !parsed.invalid_lines.is_empty() {
+            let before = before()?;
+            parsed.invalid_lines.iter_mut().for_each(|(line, _)| *line += before);
+        }
+        self.finish(parsed, None, &progress, start)
+    }
+
+    fn aggregate_shard(&self, address: &str, shard: Range<u64>, read_size: u64, records: Option<&SegQueue<BatchRecord>>, progress: &Progress) -> Result<ParsedBatch<S, A>, ProcessError> {
+        let mut file = open_sequential(address)?;
+        file.seek(SeekFrom::Start(shard.start))?;
+        let mut reader = Counted::new(ReadAhead::new(file, shard.start, self.config.readahead).take(shard.end - shard.start), progress);
+        let mut parsed_shard = self.empty_batch();
+        let mut batch = Vec::new();
+        let mut position = shard.start;
+        loop {
+            if self.config.cancelled() {
+                parsed_shard.stats.cancelled = true;
+                return Ok(parsed_shard);
+            }
+            let read_start = Instant::now();
+            let bytes_read = reader.by_ref().take(read_size).read_to_end(&mut batch)?;
+            parsed_shard.stats.read_time += read_start.elapsed();
+            // Parse up to the last newline and carry the partial line into the next read
+            let end = match memrchr(b'\n', &batch) {
+                _ if bytes_read == 0 => batch.len(),
+                Some(newline) => newline + 1,
+                None => continue,
+            };
+            if end > 0 {
+                let parsed = match self.parse_into(&batch[..end], &mut parsed_shard.map) {
+                    Ok(parsed) => parsed,
+                    Err(error) => return Err(error.after_lines(lines_before(address, position)?)),
+                };
+                if let Some(records) = records {
+                    records.push(BatchRecord::new(end, parsed.lines, Duration::ZERO));
+                }
+                parsed_shard = merge_batches(parsed_shard, parsed);
+                batch.drain(..end);
+                position += end as u64;
+            }
+            if bytes_read == 0 {
+                return Ok(parsed_shard);
+            }
+        }
+    }
+}
+
+// Counts the lines that end before `offset`, to number a line found to be invalid part way into the file
+pub(crate) fn lines_before(address: &str, offset: u64) -> io::Result<u64> {
+    let mut reader = BufReader::new(File::open(address)?.take(offset));
+    let mut lines = 0;
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(lines);
+        }
+        lines += memchr_iter(b'\n', buffer).count() as u64;
+        let consumed = buffer.len();
+        reader.consume(consumed);
+    }
+}
+
+pub(crate) fn read_range(address: &str, start: u64, end: u64) -> io::Result<Vec<u8>> {
+    let mut file = File::open(address)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::with_capacity((end - start) as usize);
+    file.take(end - start).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+// Moves each even split point forward to just past the next newline. `fetch` returns the bytes of
+// a half-open range.
+pub(crate) fn align_shards<F>(length: u64, workers: usize, fetch: F) -> io::Result<Vec<Range<u64>>>
+where
+    F: Fn(u64, u64) -> io::Result<Vec<u8>> + Sync,
+{
+    let workers = workers.max(1);
+    let splits = (1..workers).into_par_iter()
+        .map(|worker| align_split(length, worker, workers, &fetch))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut shards = Vec::with_capacity(workers);
+    let mut start = 0;
+    for end in splits.into_iter().chain([length]) {
+        // Splits can collapse onto each other when lines are long relative to the shard size
+        if end > start {
+            shards.push(start..end);
+            start = end;
+        }
+    }
+    Ok(shards)
+}
+
+// Where shard `index` of `shards` starts: the even split point moved to just past the next
+// newline, growing the probe until one is found. Shard 0 starts at 0 and shard `shards` at the end.
+fn align_split<F>(length: u64, index: usize, shards: usize, fetch: F) -> io::Result<u64>
+where
+    F: Fn(u64, u64) -> io::Result<Vec<u8>>,
+{
+    if index == 0 {
+        return Ok(0);
+    }
+    if index >= shards {
+        return Ok(length);
+    }
+    next_line_start((length as u128 * index as u128 / shards as u128) as u64, length, fetch)
+}
+
+// Just past the first newline at or after `start`, or `length` when none follows
+pub(crate) fn next_line_start<F>(mut start: u64, length: u64, fetch: F) -> io::Result<u64>
+where
+    F: Fn(u64, u64) -> io::Result<Vec<u8>>,
+{
+    let mut probe = PROBE_SIZE;
+    while start < length {
+        let end = (start + probe).min(length);
+        let bytes = fetch(start, end)?;
+        if let Some(newline) = bytes.iter().position(|&b| b == b'\n') {
+            return Ok(start + newline as u64 + 1);
+        }
+        start = end;
+        probe *= 2;
+    }
+    Ok(length)
+}
+
+// Just past the last newline before `length`, or 0 when there is none
+pub(crate) fn last_line_end(address: &str, length: u64) -> io::Result<u64> {
+    let mut end = length;
+    let mut probe = PROBE_SIZE;
+    while end > 0 {
+        let start = end.saturating_sub(probe);
+        if let Some(newline) = memrchr(b'\n', &read_range(address, start, end)?) {
+            return Ok(start + newline as u64 + 1);
+        }
+        end = start;
+        probe *= 2;
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::Write;
+    use super::*;
+    use crate::Backend;
+
+    #[test]
+    fn test_align_shards() {
+        let data = b"Hamburg;12.0\nBulawayo;8.9\nPalembang;38.8\nSt. John's;15.2\n";
+        let fetch = |start: u64, end: u64| Ok(data[start as usize..end as usize].to_vec());
+        for workers in 1..=8 {
+            let shards = align_shards(data.len() as u64, workers, fetch).unwrap();
+            assert_eq!(shards.first().unwrap().start, 0);
+            assert_eq!(shards.last().unwrap().end, data.len() as u64);
+            for pair in shards.windows(2) {
+                assert_eq!(pair[0].end, pair[1].start);
+                assert_eq!(data[pair[1].start as usize - 1], b'\n');
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_shard() {
+        let path = std::env::temp_dir().join(format!("process_shard_{}.txt", std::process::id()));
+        let mut input = String::new();
+        for i in 0..2000 {
+            input += &format!("Station {};{}.{}\n", i % 23, i % 100 - 50, i % 10);
+        }
+        File::create(&path).unwrap().write_all(input.as_bytes()).unwrap();
+        let address = path.to_str().unwrap();
+        let processor = Processor::builder().build().unwrap();
+        let mut merged = processor.process_shard(address, 0, 5).unwrap();
+        for shard in 1..5 {
+            merged.merge(processor.process_shard(address, shard, 5).unwrap());
+        }
+        let whole = processor.process(address).unwrap();
+        let out_of_range = processor.process_shard(address, 5, 5);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(merged.to_string(), whole.to_string());
+        assert_eq!((merged.stats().lines, merged.stats().bytes_read), (2000, input.len() as u64));
+        assert!(out_of_range.is_err());
+    }
+
+    #[test]
+    fn test_partitioned_backend() {
+        let path = std::env::temp_dir().join(format!("partitioned_backend_{}.txt", std::process::id()));
+        let mut input = String::new();
+        for i in 0..5000 {
+            input += &format!("Station {};{}.{}\n", i % 37, i % 100 - 50, i % 10);
+        }
+        File::create(&path).unwrap().write_all(input.as_bytes()).unwrap();
+        let address = path.to_str().unwrap();
+        let pool = std::sync::Arc::new(rayon::ThreadPoolBuilder::new().num_threads(7).build().unwrap());
+        let partitioned = Processor::builder().backend(Backend::Partitioned).thread_pool(pool).avg_line_len(1).build().unwrap().process(address).unwrap();
+        let streamed = Processor::builder().build().unwrap().process(address).unwrap();
+        let last_line = input[..input.len() - 1].rfind('\n').unwrap() + 1;
+        input.insert_str(last_line, "bad\n");
+        File::create(&path).unwrap().write_all(input.as_bytes()).unwrap();
+        let result = Processor::builder().backend(Backend::Partitioned).batch_lines(3).build().unwrap().process(address);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ProcessError::MalformedLine { line_no: 5000, .. })), "{result:?}");
+        assert_eq!(partitioned.len(), 37);
+        for ((name, data), (expected_name, expected)) in partitioned.iter().zip(streamed.iter()) {
+            assert_eq!((name, data.to_string(), data.count()), (expected_name, expected.to_string(), expected.count()));
+        }
+    }
+
+}
@@ -0,0 +1,43 @@
+use super::{Backend, ProcessorBuilder, BATCH_SIZE};
+
+/// Curated settings for common machine classes. Options set after the preset override it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// One thread per CPU and small batches, keeping memory low and the machine responsive
+    Laptop,
+    /// Two threads per CPU, each reading its own range of the file so reading is not the bottleneck
+    Server,
+    /// Two threads per CPU parsing a memory map in place, for inputs that fit in the page cache.
+    /// Falls back to the partitioned backend with the `no-unsafe` feature.
+    MaxThroughput,
+}
+
+impl<S> ProcessorBuilder<S> {
+    pub fn preset(self, preset: Preset) -> Self {
+        let cpus = num_cpus::get();
+        match preset {
+            Preset::Laptop => self.threads(cpus).batch_lines(BATCH_SIZE / 4).backend(Backend::Stream),
+            Preset::Server => self.threads(cpus * 2).batch_lines(BATCH_SIZE).backend(Backend::Partitioned),
+            Preset::MaxThroughput if cfg!(feature = "no-unsafe") => self.threads(cpus * 2).batch_lines(BATCH_SIZE).backend(Backend::Partitioned),
+            Preset::MaxThroughput => self.threads(cpus * 2).batch_lines(BATCH_SIZE).backend(Backend::Mmap),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Processor;
+
+    #[test]
+    fn test_later_options_override_preset() {
+        let builder = Processor::builder().preset(Preset::Server).backend(Backend::Stream).threads(3);
+        assert_eq!(builder.config.backend, Backend::Stream);
+        assert_eq!(builder.config.batch_lines, BATCH_SIZE);
+        let processor = builder.build().unwrap();
+        assert_eq!(processor.pool.num_threads(), 3);
+        assert_eq!(Processor::builder().preset(Preset::Laptop).config.batch_lines, BATCH_SIZE / 4);
+    }
+
+}
@@ -0,0 +1,45 @@
+use std::io;
+
+/// Drops the CPU priority to the lowest nice level and, on Linux, moves I/O to the idle
+/// scheduling class. Threads started afterwards inherit both, so call this before building a
+/// processor. Unsupported with the `no-unsafe` feature.
+#[cfg(all(unix, not(feature = "no-unsafe")))]
+pub fn lower_priority() -> io::Result<()> {
+    const LOWEST_PRIORITY: libc::c_int = 19;
+
+    // SAFETY: plain syscall on the calling process with no pointers involved
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, LOWEST_PRIORITY) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    lower_io_priority()
+}
+
+#[cfg(not(unix))]
+pub fn lower_priority() -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "lowering priority is only supported on Unix"))
+}
+
+#[cfg(all(unix, feature = "no-unsafe"))]
+pub fn lower_priority() -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "lowering priority needs unsafe code"))
+}
+
+#[cfg(all(target_os = "linux", not(feature = "no-unsafe")))]
+fn lower_io_priority() -> io::Result<()> {
+    // Values from linux/ioprio.h, which libc does not expose
+    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+
+    // SAFETY: as above, the arguments are plain integers
+    let result = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT) };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "linux"), not(feature = "no-unsafe")))]
+fn lower_io_priority() -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::io::{self, BufRead, BufReader, Read};
+use hashbrown::HashSet;
+use super::LINE_DELIMITER;
+
+const HISTOGRAM_WIDTH: u64 = 40;
+const MAX_SHAPE_LENGTH: usize = 12;
+
+/// Shape of the input data, gathered in one sequential pass
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InputProfile {
+    pub lines: u64,
+    pub bytes: u64,
+    pub unique_stations: usize,
+    pub max_line_length: usize,
+    /// Lines per station name length in bytes
+    pub name_lengths: BTreeMap<usize, u64>,
+    /// Lines per value shape, with every digit replaced by `d`, e.g. `-dd.d`
+    pub value_shapes: BTreeMap<String, u64>,
+}
+
+/// Builder settings matching a profiled input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Suggestions {
+    pub avg_line_len: usize,
+    pub max_line_len: usize,
+    pub max_integer_digits: usize,
+    pub unique_stations: usize,
+}
+
+impl InputProfile {
+    /// Profiles at most `limit` bytes of the input, or all of it when no limit is given
+    pub fn from_reader<R: Read>(reader: R, limit: Option<u64>) -> io::Result<Self> {
+        let mut reader = BufReader::new(reader.take(limit.unwrap_or(u64::MAX)));
+        let mut profile = InputProfile::default();
+        let mut stations = HashSet::new();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let bytes_read = reader.read_until(b'\n', &mut line)?;
+            if bytes_read == 0 {
+                break;
+            }
+            profile.bytes += bytes_read as u64;
+            let line = line.strip_suffix(b"\n").unwrap_or(&line);
+            if line.is_empty() {
+                continue;
+            }
+            profile.lines += 1;
+            profile.max_line_length = profile.max_line_length.max(line.len());
+
+            let delimiter = line.iter().rposition(|&b| b == LINE_DELIMITER as u8).unwrap_or(line.len());
+            let (station, value) = (&line[..delimiter], line.get(delimiter + 1..).unwrap_or_default());
+            *profile.name_lengths.entry(station.len()).or_default() += 1;
+            if !stations.contains(station) {
+                stations.insert(station.to_vec());
+            }
+            *profile.value_shapes.entry(value_shape(value)).or_default() += 1;
+        }
+        profile.unique_stations = stations.len();
+        Ok(profile)
+    }
+
+    pub fn suggestions(&self) -> Suggestions {
+        let integer_digits = self.value_shapes.keys()
+            .filter_map(|shape| shape.trim_start_matches('-').split_once('.'))
+            .map(|(integer, _)| integer.len())
+            .max()
+            .unwrap_or(2);
+        Suggestions {
+            avg_line_len: (self.bytes.div_ceil(self.lines.max(1)) as usize).max(1),
+            max_line_len: self.max_line_length.max(1) + 1,
+            max_integer_digits: integer_digits.clamp(1, 8),
+            unique_stations: self.unique_stations,
+        }
+    }
+}
+
+fn value_shape(value: &[u8]) -> String {
+    if value.len() > MAX_SHAPE_LENGTH {
+        return "other".to_string();
+    }
+    value.iter().map(|&b| match b {
+        b'0'..=b'9' => 'd',
+        b if b.is_ascii_graphic() => b as char,
+        _ => '?',
+    }).collect()
+}
+
+impl Display for InputProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Lines: {}, bytes: {}, unique stations: {}, longest line: {}",
+            self.lines, self.bytes, self.unique_stations, self.max_line_length)?;
+        let largest = self.name_lengths.values().copied().max().unwrap_or(1);
+        writeln!(f, "Station name lengths (bytes):")?;
+        for (length, count) in &self.name_lengths {
+            let bar = "#".repeat((count * HISTOGRAM_WIDTH).div_ceil(largest) as usize);
+            writeln!(f, "{:>5} {:>12} {}", length, count, bar)?;
+        }
+        writeln!(f, "Value shapes:")?;
+        for (shape, count) in &self.value_shapes {
+            writeln!(f, "{:>8} {:>12}", shape, count)?;
+        }
+        let suggestions = self.suggestions();
+        writeln!(f, "Suggested settings: --avg-line-len {} --max-line-len {} --max-integer-digits {}",
+            suggestions.avg_line_len, suggestions.max_line_len, suggestions.max_integer_digits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let input = "Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\nEverest;-123.4\n\n";
+        let profile = InputProfile::from_reader(input.as_bytes(), None).unwrap();
+        assert_eq!(profile.lines, 4);
+        assert_eq!(profile.unique_stations, 3);
+        assert_eq!(profile.name_lengths[&7], 3);
+        assert_eq!(profile.value_shapes["dd.d"], 1);
+        assert_eq!(profile.value_shapes["-ddd.d"], 1);
+        let suggestions = profile.suggestions();
+        assert_eq!(suggestions.max_integer_digits, 3);
+        assert_eq!(suggestions.max_line_len, 15);
+    }
+
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use hashbrown::HashMap;
+use super::{Data, ProcessError, Results};
+
+/// Aggregates a measurements file on one thread, a line at a time, with none of the optimized
+/// pipeline's batching, custom parsing or unsafe code. Slow but simple enough to check by
+/// reading, it is the baseline the pipeline is tested against. Accepts exactly the challenge
+/// format, along with `\r\n` endings and blank lines, stopping at the first malformed line as the
+/// default `LinePolicy::Strict` does.
+pub fn process_file_reference(address: &str) -> Result<Results, ProcessError> {
+    process_reader_reference(File::open(address)?)
+}
+
+/// As `process_file_reference`, for any reader
+pub fn process_reader_reference<R: Read>(mut reader: R) -> Result<Results, ProcessError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let input = String::from_utf8(bytes).map_err(|error| {
+        let valid = &error.as_bytes()[..error.utf8_error().valid_up_to()];
+        let line_start = valid.iter().rposition(|&byte| byte == b'\n').map_or(0, |newline| newline + 1);
+        ProcessError::InvalidUtf8 { line_no: valid.iter().filter(|&&byte| byte == b'\n').count() as u64 + 1, offset: valid.len() - line_start }
+    })?;
+
+    let mut stations = BTreeMap::<String, Data>::new();
+    let lines = input.strip_suffix('\n').unwrap_or(&input);
+    for (index, line) in lines.split('\n').enumerate().filter(|_| !input.is_empty()) {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        let malformed = || ProcessError::MalformedLine { line_no: index as u64 + 1, contents: line.to_string() };
+        let (station, value) = line.rsplit_once(';').ok_or_else(malformed)?;
+        let value = parse_tenths(value).ok_or_else(malformed)?;
+        match stations.get_mut(station) {
+            Some(data) => {
+                data.min = data.min.min(value);
+                data.max = data.max.max(value);
+                data.sum += value as i64;
+                data.count += 1;
+            }
+            None => {
+                stations.insert(station.to_string(), Data { sum: value as i64, count: 1, min: value, max: value });
+            }
+        }
+    }
+    Ok(Results::from_map(stations.into_iter().collect::<HashMap<_, _>>()))
+}
+
+// `-?d?d.d`, in tenths
+fn parse_tenths(value: &str) -> Option<i32> {
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value),
+    };
+    let (integer, fraction) = value.split_once('.')?;
+    let digits = |part: &str, lengths: std::ops::RangeInclusive<usize>| lengths.contains(&part.len()) && part.bytes().all(|byte| byte.is_ascii_digit());
+    if !digits(integer, 1..=2) || !digits(fraction, 1..=1) {
+        return None;
+    }
+    let tenths = integer.parse::<i32>().ok()? * 10 + fraction.parse::<i32>().ok()?;
+    Some(if negative { -tenths } else { tenths })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use proptest::prelude::*;
+    use crate::Processor;
+
+    // Names of 1 to 100 bytes of any characters but the separators, most of them multi-byte
+    fn station() -> impl Strategy<Value = String> {
+        let character = any::<char>().prop_filter("a separator", |c| !matches!(c, ';' | '\n' | '\r'));
+        proptest::collection::vec(character, 1..=100).prop_map(|characters| {
+            let mut length = 0;
+            characters.into_iter().take_while(|c| {
+                length += c.len_utf8();
+                length <= 100
+            }).collect()
+        })
+    }
+
+    #[test]
+    fn test_parse_tenths() {
+        let parsed = ["0.0", "-0.0", "9.9", "-12.3", "99.9", "-99.9"].map(parse_tenths);
+        assert_eq!(parsed, [Some(0), Some(0), Some(99), Some(-123), Some(999), Some(-999)]);
+        for invalid in ["", "1", "1.", ".1", "100.0", "1.23", "+1.0", "--1.0", "1.0 ", "1,0", "١.٠"] {
+            assert_eq!(parse_tenths(invalid), None, "{invalid:?}");
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        // Batches of as little as one line, and reads that split names mid-character
+        #[test]
+        fn test_random_inputs_match_reference(
+            names in proptest::collection::vec(station(), 1..20),
+            rows in proptest::collection::vec((any::<prop::sample::Index>(), -999i32..=999), 0..2_000),
+            trailing_newline in any::<bool>(),
+            batch_lines in 1usize..50,
+            buffer_capacity in 1usize..256,
+            threads in 1usize..4,
+        ) {
+            let mut input = rows.iter().map(|(name, value)| format!("{};{:.1}", names[name.index(names.len())], *value as f64 / 10.0)).collect::<Vec<_>>().join("\n");
+            if trailing_newline {
+                input.push('\n');
+            }
+            let outcome = |results: Results| results.iter().map(|(station, data)| (station.to_string(), data.min, data.max, data.sum, data.count)).collect::<Vec<_>>();
+            let expected = outcome(process_reader_reference(input.as_bytes()).unwrap());
+            let processor = Processor::builder().batch_lines(batch_lines).avg_line_len(1).buffer_capacity(buffer_capacity).threads(threads).build().unwrap();
+            prop_assert_eq!(outcome(processor.process_reader(input.as_bytes()).unwrap()), expected);
+        }
+    }
+
+}
@@ -0,0 +1,283 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+use std::hash::BuildHasher;
+use std::io::{self, Write};
+use std::iter::{self, Peekable};
+use std::{slice, vec};
+use hashbrown::HashMap;
+use super::output::ordered;
+use super::{write_results, Aggregator, Columns, Data, Format, Limit, OutputOptions, ProcessStats, SortBy};
+
+/// Aggregated statistics for every station, sorted by station name
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Results<A = Data> {
+    stations: Vec<(String, A)>,
+    invalid_lines: Vec<(u64, String)>,
+    stats: ProcessStats,
+}
+impl<A> Default for Results<A> {
+    fn default() -> Self {
+        Results { stations: Vec::new(), invalid_lines: Vec::new(), stats: ProcessStats::default() }
+    }
+}
+impl<A: Aggregator> Results<A> {
+    pub(crate) fn from_map<S: BuildHasher>(map: HashMap<String, A, S>) -> Self {
+        let mut stations = map.into_iter().collect::<Vec<_>>();
+        stations.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        Results { stations, invalid_lines: Vec::new(), stats: ProcessStats::default() }
+    }
+
+    // From stations already in name order, each named once
+    pub(crate) fn from_sorted(stations: Vec<(String, A)>) -> Self {
+        Results { stations, invalid_lines: Vec::new(), stats: ProcessStats::default() }
+    }
+
+    pub(crate) fn with_stats(mut self, stats: ProcessStats) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    pub(crate) fn with_invalid_lines(mut self, invalid_lines: Vec<(u64, String)>) -> Self {
+        self.invalid_lines = invalid_lines;
+        self
+    }
+
+    pub fn get(&self, station: &str) -> Option<&A> {
+        let index = self.stations.binary_search_by(|(name, _)| name.as_str().cmp(station)).ok()?;
+        Some(&self.stations[index].1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &A)> {
+        self.stations.iter().map(|(station, data)| (station.as_str(), data))
+    }
+
+    pub fn len(&self) -> usize {
+        self.stations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stations.is_empty()
+    }
+
+    /// Malformed lines left out under `LinePolicy::Collect`, with their line numbers from 1
+    pub fn invalid_lines(&self) -> &[(u64, String)] {
+        &self.invalid_lines
+    }
+
+    /// Where the time of the run went. Zero for results not produced by a `Processor`.
+    pub fn stats(&self) -> &ProcessStats {
+        &self.stats
+    }
+
+    /// Folds in the results of another part of the same input, such as another file or another
+    /// machine's share of one. Stations in both are merged with `other` coming after `self`, and
+    /// the invalid lines and stats of both are kept.
+    ///
+    /// Results saved with `write_state`, serialized with the `serde` feature or written in
+    /// `Format::Parquet` read back with the exact sums, counts and extremes they were aggregated
+    /// with, so merging them gives the same output as aggregating the parts together.
+    pub fn merge(&mut self, other: Results<A>) {
+        let mut ours = std::mem::take(&mut self.stations).into_iter().peekable();
+        let mut theirs = other.stations.into_iter().peekable();
+        let mut stations = Vec::with_capacity(ours.len().max(theirs.len()));
+        while let Some(next) = next_station(&mut ours, &mut theirs) {
+            stations.push(next);
+        }
+        self.stations = stations;
+        self.invalid_lines.extend(other.invalid_lines);
+        self.stats = self.stats.combine(other.stats);
+    }
+
+    /// Each station's `Aggregator::finalize` output, in station order
+    pub fn finalized(&self) -> impl Iterator<Item = (&str, A::Output)> + '_ {
+        self.iter().map(|(station, aggregator)| (station, aggregator.finalize()))
+    }
+}
+// The next station of two sorted lists, merging the two entries of a station both lists hold
+fn next_station<A: Aggregator>(ours: &mut Peekable<vec::IntoIter<(String, A)>>, theirs: &mut Peekable<vec::IntoIter<(String, A)>>) -> Option<(String, A)> {
+    let order = match (ours.peek(), theirs.peek()) {
+        (Some((a, _)), Some((b, _))) => a.cmp(b),
+        (Some(_), None) => Ordering::Less,
+        (None, _) => Ordering::Greater,
+    };
+    match order {
+        Ordering::Less => ours.next(),
+        Ordering::Greater => theirs.next(),
+        Ordering::Equal => {
+            let (station, mut aggregator) = ours.next()?;
+            aggregator.merge(theirs.next()?.1);
+            Some((station, aggregator))
+        }
+    }
+}
+
+impl<A> IntoIterator for Results<A> {
+    type Item = (String, A);
+    type IntoIter = vec::IntoIter<(String, A)>;
+
+    /// The stations and their aggregators, in station order
+    fn into_iter(self) -> Self::IntoIter {
+        self.stations.into_iter()
+    }
+}
+
+impl<'a, A> IntoIterator for &'a Results<A> {
+    type Item = (&'a str, &'a A);
+    type IntoIter = iter::Map<slice::Iter<'a, (String, A)>, fn(&'a (String, A)) -> (&'a str, &'a A)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.stations.iter().map(|(station, aggregator)| (station.as_str(), aggregator))
+    }
+}
+
+/// The challenge output, `{name=min/mean/max, ...}` in station order
+impl<A: Columns> Display for Results<A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (index, (station, columns)) in self.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{station}={}", columns.data())?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl<A: Columns> Results<A> {
+    /// Writes a `station,min,mean,max,count` header and one row per station, for spreadsheets
+    /// and dataframes
+    pub fn write_csv<W: Write>(&self, writer: W) -> io::Result<()> {
+        write_results(self, OutputOptions { format: Format::Csv, ..Default::default() }, writer)
+    }
+
+    /// The `n` stations with the highest values of `column`, highest first. Ties keep the order
+    /// of their names.
+    pub fn top_n(&self, n: usize, column: SortBy) -> Vec<(&str, &A)> {
+        ordered(self, column, Some(Limit::Top(n)))
+    }
+
+    /// The `n` stations with the lowest values of `column`, lowest first
+    pub fn bottom_n(&self, n: usize, column: SortBy) -> Vec<(&str, &A)> {
+        ordered(self, column, Some(Limit::Bottom(n)))
+    }
+
+    /// The positions among `sources`, the results of the parts these results combine as given by
+    /// `Processor::process_files_by_source`, of the first part holding the station's min and the
+    /// first holding its max
+    pub fn extreme_sources(&self, station: &str, sources: &[Results]) -> Option<(usize, usize)> {
+        let data = self.get(station)?.data();
+        let holding = |extreme: fn(&Data) -> i32| sources.iter().position(|source| source.get(station).is_some_and(|part| extreme(part) == extreme(data)));
+        Some((holding(|data| data.min)?, holding(|data| data.max)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_lookup_and_order() {
+        let mut map = HashMap::<String, Data>::new();
+        map.insert("Zürich".to_string(), Data { sum: -5, count: 1, min: -5, max: -5 });
+        map.insert("Abha".to_string(), Data { sum: 180, count: 2, min: -12, max: 192 });
+        let results = Results::from_map(map);
+        assert_eq!(results.iter().map(|(station, _)| station).collect::<Vec<_>>(), ["Abha", "Zürich"]);
+        assert_eq!(results.get("Abha").map(Data::count), Some(2));
+        assert_eq!(results.get("Abha").map(Data::mean), Some(9.0));
+        assert!(results.get("Hamburg").is_none());
+    }
+
+    #[test]
+    fn test_top_and_bottom_n() {
+        let results = crate::Processor::builder().build().unwrap()
+            .process_reader(&b"a;5.0\nb;-2.0\nc;5.0\nd;9.0\nd;-9.0\ne;1.0\n"[..]).unwrap();
+        let names = |stations: Vec<(&str, &Data)>| stations.into_iter().map(|(station, _)| station.to_string()).collect::<Vec<_>>();
+        assert_eq!(names(results.top_n(3, SortBy::Max)), ["d", "a", "c"]);
+        assert_eq!(names(results.top_n(2, SortBy::Mean)), ["a", "c"]);
+        assert_eq!(names(results.bottom_n(2, SortBy::Min)), ["d", "b"]);
+        assert_eq!(names(results.top_n(10, SortBy::Count)).len(), 5);
+        let mut output = Vec::new();
+        write_results(&results, OutputOptions { limit: Some(Limit::Bottom(2)), sort_by: SortBy::Mean, ..Default::default() }, &mut output).unwrap();
+        assert_eq!(output, b"{b=-2.0/-2.0/-2.0, d=-9.0/0.0/9.0}\n");
+    }
+
+    #[test]
+    fn test_merge_and_display() {
+        let processor = crate::Processor::builder().build().unwrap();
+        let mut results = processor.process_reader(&b"b;1.0\nd;4.0\n"[..]).unwrap();
+        results.merge(processor.process_reader(&b"a;2.0\nd;-1.0\ne;0.5\n"[..]).unwrap());
+        let whole = processor.process_reader(&b"b;1.0\nd;4.0\na;2.0\nd;-1.0\ne;0.5\n"[..]).unwrap();
+        assert_eq!(results.to_string(), whole.to_string());
+        assert_eq!(results.to_string(), "{a=2.0/2.0/2.0, b=1.0/1.0/1.0, d=-1.0/1.5/4.0, e=0.5/0.5/0.5}");
+        assert_eq!(results.stats().lines, 5);
+        assert_eq!((&results).into_iter().count(), 4);
+        let owned = results.into_iter().map(|(station, data)| (station, data.count())).collect::<Vec<_>>();
+        assert_eq!(owned, [("a".to_string(), 1), ("b".to_string(), 1), ("d".to_string(), 2), ("e".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_extreme_sources() {
+        let paths = ["a", "b", "c"].map(|part| std::env::temp_dir().join(format!("extreme_sources_{part}_{}.txt", std::process::id())));
+        std::fs::write(&paths[0], "Hamburg;12.0\nBulawayo;8.9\n").unwrap();
+        std::fs::write(&paths[1], "Hamburg;-3.4\nHamburg;99.9\n").unwrap();
+        std::fs::write(&paths[2], "Bulawayo;8.9\nBulawayo;-12.0\nbad\n").unwrap();
+        let processor = crate::Processor::builder().line_policy(crate::LinePolicy::Skip).build().unwrap();
+        let (results, sources) = processor.process_files_by_source(&paths).unwrap();
+        assert_eq!(results.to_string(), processor.process_files(&paths).unwrap().to_string());
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert_eq!(sources.iter().map(|source| source.to_string()).collect::<Vec<_>>(),
+            ["{Bulawayo=8.9/8.9/8.9, Hamburg=12.0/12.0/12.0}", "{Hamburg=-3.4/48.3/99.9}", "{Bulawayo=-12.0/-1.5/8.9}"]);
+        assert_eq!(sources.iter().map(|source| source.stats().lines).collect::<Vec<_>>(), [2, 2, 3]);
+        assert_eq!(results.extreme_sources("Hamburg", &sources), Some((1, 1)));
+        // A tie goes to the first part
+        assert_eq!(results.extreme_sources("Bulawayo", &sources), Some((2, 0)));
+        assert_eq!(results.extreme_sources("Paris", &sources), None);
+        assert_eq!(results.extreme_sources("Hamburg", &sources[..1]), None);
+    }
+
+    // The results written and read back in the named format
+    fn round_trip(format: &str, results: &Results) -> Results {
+        match format {
+            #[cfg(feature = "serde")]
+            "json" => serde_json::from_str(&serde_json::to_string(results).unwrap()).unwrap(),
+            #[cfg(feature = "parquet")]
+            "parquet" => {
+                let mut output = Vec::new();
+                crate::parquet::write_results(results, &mut output).unwrap();
+                Results::read_parquet(bytes::Bytes::from(output)).unwrap()
+            }
+            _ => {
+                let mut state = Vec::new();
+                results.write_state(&mut state).unwrap();
+                Results::read_state(&state[..]).unwrap()
+            }
+        }
+    }
+
+    #[test]
+    fn test_exports_round_trip() {
+        // Means on a rounding tie, which a round trip through the printed values would move
+        let parts: [&[u8]; 2] = [b"a;0.1\nb;-0.3\nc;99.9\na;0.2\n", b"a;0.2\nb;-0.2\nd;-99.9\nc;-0.1\n"];
+        let whole = [parts[0], parts[1]].concat();
+        let processor = crate::Processor::builder().build().unwrap();
+        let expected = processor.process_reader(&whole[..]).unwrap();
+        let aggregates = |results: &Results| results.iter().map(|(station, data)| (station.to_string(), data.sum, data.count, data.min, data.max)).collect::<Vec<_>>();
+
+        let mut exports = vec!["state"];
+        exports.extend(cfg!(feature = "serde").then_some("json"));
+        exports.extend(cfg!(feature = "parquet").then_some("parquet"));
+        for name in exports {
+            let mut merged = round_trip(name, &processor.process_reader(parts[0]).unwrap());
+            merged.merge(round_trip(name, &processor.process_reader(parts[1]).unwrap()));
+            assert_eq!(aggregates(&merged), aggregates(&expected), "{name}");
+            assert_eq!(merged.to_string(), expected.to_string(), "{name}");
+        }
+    }
+
+}
@@ -0,0 +1,307 @@
+use std::ops::Range;
+use super::parse::{parse_word, FieldOrder};
+
+// Bytes whose newlines and delimiters are found together
+const CHUNK: usize = 64;
+
+/// One line of a batch, without its newline, and the first and last delimiters in it, all as
+/// offsets into the batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Line {
+    pub range: Range<usize>,
+    pub first_delimiter: Option<usize>,
+    pub last_delimiter: Option<usize>,
+    /// The station's range and the value, when values are parsed in the walk and this line's is
+    /// in the challenge format
+    pub parsed: Option<(Range<usize>, i32)>,
+}
+
+// Bitmasks of the newlines and delimiters of a chunk, bit `i` standing for byte `i`
+type Masks = fn(&[u8; CHUNK], u8) -> (u64, u64);
+
+// Walks the lines of a batch in one pass, finding each line's end and its delimiters from
+// bitmasks built a chunk at a time with the widest vectors the CPU has, so neither is searched for
+// byte by byte. Like splitting on newlines, a batch of `n` newlines gives `n + 1` lines, the last
+// one empty when the batch ends with a newline.
+//
+// Asked to, it also parses each value in the challenge format as its line ends, loading the value
+// straight from the batch while the line is still in cache, so the caller only slices out the
+// station. Other values are left to the caller.
+pub(crate) struct Lines<'a> {
+    bytes: &'a [u8],
+    delimiter: u8,
+    masks: Masks,
+    values: Option<FieldOrder>,
+    // Start of the chunk being walked, and its newlines and delimiters not walked past yet
+    chunk: usize,
+    newlines: u64,
+    delimiters: u64,
+    line_start: usize,
+    first_delimiter: Option<usize>,
+    last_delimiter: Option<usize>,
+    finished: bool,
+}
+impl<'a> Lines<'a> {
+    pub fn new(bytes: &'a [u8], delimiter: u8) -> Self {
+        let mut lines = Lines {
+            bytes,
+            delimiter,
+            masks: detect(),
+            values: None,
+            chunk: 0,
+            newlines: 0,
+            delimiters: 0,
+            line_start: 0,
+            first_delimiter: None,
+            last_delimiter: None,
+            finished: false,
+        };
+        lines.load();
+        lines
+    }
+
+    // Parses the values of lines in this field order as it walks them
+    pub fn parsing_values(self, order: FieldOrder) -> Self {
+        Lines { values: Some(order), ..self }
+    }
+
+    fn load(&mut self) {
+        let remaining = self.bytes.len().saturating_sub(self.chunk);
+        (self.newlines, self.delimiters) = match self.bytes.get(self.chunk..self.chunk + CHUNK).and_then(|chunk| <&[u8; CHUNK]>::try_from(chunk).ok()) {
+            Some(chunk) => (self.masks)(chunk, self.delimiter),
+            None => {
+                // The last, partial chunk is padded, and the padding masked out
+                let mut padded = [0; CHUNK];
+                padded[..remaining].copy_from_slice(&self.bytes[self.chunk..]);
+                let (newlines, delimiters) = (self.masks)(&padded, self.delimiter);
+                let valid = (1u64 << remaining) - 1;
+                (newlines & valid, delimiters & valid)
+            }
+        };
+    }
+
+    fn take_line(&mut self, end: usize) -> Line {
+        let parsed = self.values.and_then(|order| self.parse(order, end));
+        let line = Line { range: self.line_start..end, first_delimiter: self.first_delimiter.take(), last_delimiter: self.last_delimiter.take(), parsed };
+        self.line_start = end + 1;
+        line
+    }
+
+    // The station and value of the line ending at `end`, split at the delimiter next to the value
+    fn parse(&self, order: FieldOrder, end: usize) -> Option<(Range<usize>, i32)> {
+        // Tolerates the `\r\n` endings of Windows exports
+        let end = if end > self.line_start && self.bytes[end - 1] == b'\r' { end - 1 } else { end };
+        match order {
+            FieldOrder::StationFirst => {
+                let delimiter = self.last_delimiter?;
+                Some((self.line_start..delimiter, self.value(delimiter + 1, end)?))
+            }
+            FieldOrder::ValueFirst => {
+                let delimiter = self.first_delimiter?;
+                Some((delimiter + 1..end, self.value(self.line_start, delimiter)?))
+            }
+        }
+    }
+
+    // A value of three to five bytes, loaded as one word wherever eight bytes remain
+    fn value(&self, start: usize, end: usize) -> Option<i32> {
+        let length = end.checked_sub(start)?;
+        let word = match self.bytes.get(start..start + 8).and_then(|word| <[u8; 8]>::try_from(word).ok()) {
+            Some(word) => word,
+            None => {
+                let mut word = [0; 8];
+                word.get_mut(..length)?.copy_from_slice(&self.bytes[start..end]);
+                word
+            }
+        };
+        parse_word(u64::from_le_bytes(word), length)
+    }
+}
+impl Iterator for Lines<'_> {
+    type Item = Line;
+
+    fn next(&mut self) -> Option<Line> {
+        loop {
+            let structural = self.newlines | self.delimiters;
+            if structural != 0 {
+                let bit = 1 << structural.trailing_zeros();
+                let position = self.chunk + structural.trailing_zeros() as usize;
+                if self.delimiters & bit != 0 {
+                    self.delimiters &= !bit;
+                    self.first_delimiter.get_or_insert(position);
+                    self.last_delimiter = Some(position);
+                    continue;
+                }
+                self.newlines &= !bit;
+                return Some(self.take_line(position));
+            }
+            if self.finished {
+                return None;
+            }
+            self.chunk += CHUNK;
+            if self.chunk >= self.bytes.len() {
+                self.finished = true;
+                return Some(self.take_line(self.bytes.len()));
+            }
+            self.load();
+        }
+    }
+}
+
+// The fastest way to build the masks on this CPU
+#[cfg(all(target_arch = "x86_64", not(feature = "no-unsafe")))]
+fn detect() -> Masks {
+    match std::arch::is_x86_feature_detected!("avx2") {
+        true => simd::masks_avx2,
+        false => simd::masks_sse2,
+    }
+}
+
+#[cfg(not(all(target_arch = "x86_64", not(feature = "no-unsafe"))))]
+fn detect() -> Masks {
+    masks_swar
+}
+
+// Eight bytes at a time in plain integer arithmetic, for other CPUs and builds without unsafe code
+#[cfg_attr(all(target_arch = "x86_64", not(feature = "no-unsafe"), not(test)), allow(dead_code))]
+fn masks_swar(chunk: &[u8; CHUNK], delimiter: u8) -> (u64, u64) {
+    let (mut newlines, mut delimiters) = (0, 0);
+    for (index, word) in chunk.chunks_exact(8).enumerate() {
+        let word = u64::from_le_bytes([word[0], word[1], word[2], word[3], word[4], word[5], word[6], word[7]]);
+        newlines |= byte_mask(word, b'\n') << (8 * index);
+        delimiters |= byte_mask(word, delimiter) << (8 * index);
+    }
+    (newlines, delimiters)
+}
+
+// One bit per byte of `word` equal to `byte`, with no false positives
+fn byte_mask(word: u64, byte: u8) -> u64 {
+    const LOW_SEVEN: u64 = 0x7F7F_7F7F_7F7F_7F7F;
+    let difference = word ^ (0x0101_0101_0101_0101 * byte as u64);
+    // Bit 7 of each byte is set where the byte of `difference` is zero
+    let zeros = !(((difference & LOW_SEVEN) + LOW_SEVEN) | difference | LOW_SEVEN);
+    // Gathers bit 7 of byte `i` into bit `56 + i`, the shifted copies never overlapping
+    (zeros >> 7).wrapping_mul(0x0102_0408_1020_4080) >> 56
+}
+
+#[cfg(all(target_arch = "x86_64", not(feature = "no-unsafe")))]
+mod simd {
+    use std::arch::x86_64::*;
+    use super::CHUNK;
+
+    // Compares 16 bytes at a time, which every x86_64 CPU can
+    pub(super) fn masks_sse2(chunk: &[u8; CHUNK], delimiter: u8) -> (u64, u64) {
+        // SAFETY: SSE2 is part of x86_64
+        unsafe { masks_sse2_enabled(chunk, delimiter) }
+    }
+
+    #[target_feature(enable = "sse2")]
+    fn masks_sse2_enabled(chunk: &[u8; CHUNK], delimiter: u8) -> (u64, u64) {
+        let (newline, delimiter) = (_mm_set1_epi8(b'\n' as i8), _mm_set1_epi8(delimiter as i8));
+        let (mut newlines, mut delimiters) = (0, 0);
+        for (index, part) in chunk.chunks_exact(16).enumerate() {
+            // SAFETY: `part` holds 16 readable bytes, and unaligned loads need no alignment
+            let bytes = unsafe { _mm_loadu_si128(part.as_ptr().cast()) };
+            newlines |= (_mm_movemask_epi8(_mm_cmpeq_epi8(bytes, newline)) as u16 as u64) << (16 * index);
+            delimiters |= (_mm_movemask_epi8(_mm_cmpeq_epi8(bytes, delimiter)) as u16 as u64) << (16 * index);
+        }
+        (newlines, delimiters)
+    }
+
+    // Only handed out once AVX2 was detected
+    pub(super) fn masks_avx2(chunk: &[u8; CHUNK], delimiter: u8) -> (u64, u64) {
+        // SAFETY: the CPU supports AVX2
+        unsafe { masks_avx2_enabled(chunk, delimiter) }
+    }
+
+    #[target_feature(enable = "avx2")]
+    fn masks_avx2_enabled(chunk: &[u8; CHUNK], delimiter: u8) -> (u64, u64) {
+        let (newline, delimiter) = (_mm256_set1_epi8(b'\n' as i8), _mm256_set1_epi8(delimiter as i8));
+        // SAFETY: the chunk holds 64 readable bytes, and unaligned loads need no alignment
+        let (low, high) = unsafe { (_mm256_loadu_si256(chunk.as_ptr().cast()), _mm256_loadu_si256(chunk.as_ptr().add(32).cast())) };
+        let mask = |needle| {
+            let low = _mm256_movemask_epi8(_mm256_cmpeq_epi8(low, needle)) as u32 as u64;
+            let high = _mm256_movemask_epi8(_mm256_cmpeq_epi8(high, needle)) as u32 as u64;
+            low | high << 32
+        };
+        (mask(newline), mask(delimiter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse::{parse_i32, split_line, LineFormat};
+
+    #[test]
+    fn test_lines() {
+        let mut batch = (0..500).map(|i| format!("Station;{i};{}.{}\n", i % 100, i % 10)).collect::<String>();
+        batch += "\n\nno delimiter\n;\nlast;1.0";
+        let bytes = batch.as_bytes();
+        let mut expected = Vec::new();
+        let mut start = 0;
+        for line in batch.split('\n') {
+            let range = start..start + line.len();
+            let first_delimiter = line.find(';').map(|offset| start + offset);
+            let last_delimiter = line.rfind(';').map(|offset| start + offset);
+            expected.push(Line { range, first_delimiter, last_delimiter, parsed: None });
+            start += line.len() + 1;
+        }
+        let implementations: Vec<Masks> = vec![masks_swar, detect()];
+        #[cfg(all(target_arch = "x86_64", not(feature = "no-unsafe")))]
+        let implementations = [implementations, vec![simd::masks_sse2]].concat();
+        for masks in implementations {
+            for length in [0, 1, 63, 64, 65, bytes.len()] {
+                let mut lines = Lines { masks, ..Lines::new(&bytes[..length], b';') };
+                lines.load();
+                assert_eq!(lines.count(), batch[..length].split('\n').count(), "{length}");
+            }
+            let mut lines = Lines { masks, ..Lines::new(bytes, b';') };
+            lines.load();
+            assert_eq!(lines.collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn test_parsed_values() {
+        // Every shape of the challenge and some near misses, with station names holding the
+        // delimiter, Windows endings, and values in the last eight bytes of the batch
+        let values = ["0.0", "-0.1", "9.9", "12.3", "-99.9", "100.0", "1.23", "1", ".5", "-", "1.x", "", "1.0\r"];
+        let names = ["a", "St. John's", "x;y", ""];
+        for order in [FieldOrder::StationFirst, FieldOrder::ValueFirst] {
+            let format = LineFormat::new(';', order).unwrap();
+            let mut batch = String::new();
+            for (index, value) in values.iter().enumerate() {
+                let name = names[index % names.len()];
+                batch += &match order {
+                    FieldOrder::StationFirst => format!("{name};{value}\n{name};{value}\r\n"),
+                    FieldOrder::ValueFirst => format!("{value};{name}\n{value};{name}\r\n"),
+                };
+            }
+            batch += match order {
+                FieldOrder::StationFirst => "end;-1.5",
+                FieldOrder::ValueFirst => "-1.5;end",
+            };
+            for end in [batch.len(), batch.len() - 3] {
+                let batch = &batch[..end];
+                for line in Lines::new(batch.as_bytes(), b';').parsing_values(order) {
+                    let text = batch[line.range.clone()].strip_suffix('\r').unwrap_or(&batch[line.range.clone()]);
+                    let expected = split_line(text, format).and_then(|(station, value)| Some((station, parse_i32(value)?)));
+                    let parsed = line.parsed.map(|(station, value)| (&batch[station], value));
+                    assert_eq!(parsed, expected, "{text:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_byte_mask() {
+        let word = u64::from_le_bytes(*b";a;\n\x00;\xff;");
+        assert_eq!(byte_mask(word, b';'), 0b1010_0101);
+        assert_eq!(byte_mask(word, b'\n'), 0b0000_1000);
+        assert_eq!(byte_mask(word, 0), 0b0001_0000);
+        assert_eq!(byte_mask(word, 0xFF), 0b0100_0000);
+    }
+
+}
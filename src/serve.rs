@@ -0,0 +1,448 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::hash::BuildHasher;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+use super::output::{write_json_string, write_json_values};
+use super::{write_results, Columns, Data, Format, Limit, OutputOptions, Processor, Results, SortBy, StationHasher};
+
+// Stations listed by `/top` when the query gives no `n`
+const DEFAULT_TOP: usize = 10;
+// Longest path or URL accepted in the body of a job submission
+const MAX_ADDRESS_LEN: u64 = 4096;
+// Finished jobs kept by default
+const MAX_FINISHED_JOBS: usize = 100;
+
+/// Where a submitted job is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobStatus {
+    /// Waiting for one of the running jobs to finish
+    Queued,
+    Running,
+    Done,
+    /// Aggregating failed, with the error
+    Failed(String),
+}
+impl JobStatus {
+    fn name(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Done => "done",
+            JobStatus::Failed(_) => "failed",
+        }
+    }
+}
+
+/// Which inputs jobs may read and how many finished jobs are kept. By default jobs may read
+/// nothing at all, so paths and each URL scheme have to be allowed.
+#[derive(Debug, Clone)]
+pub struct JobRules {
+    /// Directory that submitted paths must lie under once symbolic links are resolved, relative
+    /// paths being taken from it. `None` refuses every path.
+    pub root: Option<PathBuf>,
+    /// URL schemes that may be submitted, such as `https` or `s3`. Allowing `file` lets jobs read
+    /// any local file, whatever the root.
+    pub schemes: Vec<String>,
+    /// Finished jobs kept, with their results, before the one that finished first is dropped.
+    /// At least one is kept.
+    pub max_finished: usize,
+}
+impl Default for JobRules {
+    fn default() -> Self {
+        JobRules { root: None, schemes: Vec::new(), max_finished: MAX_FINISHED_JOBS }
+    }
+}
+
+/// Files and URLs submitted for aggregation over time, each aggregated by one shared processor.
+/// At most `limit` jobs run at once and the rest start in the order they were submitted. Only
+/// inputs allowed by the rules are taken, and finished jobs are dropped oldest first past the
+/// rules' limit.
+pub struct Jobs<S = StationHasher, A = Data> {
+    processor: Processor<S, A>,
+    limit: usize,
+    rules: JobRules,
+    state: Mutex<JobTable<A>>,
+    finished: Condvar,
+}
+
+struct JobTable<A> {
+    // Jobs by id, from 1, without those dropped
+    jobs: BTreeMap<u64, Job<A>>,
+    // Id of the first job not yet started, and of the last submitted
+    next: u64,
+    last: u64,
+    running: usize,
+    // Ids of the finished jobs kept, in the order they finished
+    done: VecDeque<u64>,
+}
+
+struct Job<A> {
+    address: String,
+    status: JobStatus,
+    results: Option<Results<A>>,
+}
+
+impl<S: BuildHasher + Clone + Send + Sync + 'static, A: Columns + 'static> Jobs<S, A> {
+    /// Runs jobs with the processor's settings and thread pool, at most `limit` of them at a
+    /// time, though always at least one
+    pub fn new(processor: Processor<S, A>, limit: usize, rules: JobRules) -> Arc<Self> {
+        let state = Mutex::new(JobTable { jobs: BTreeMap::new(), next: 1, last: 0, running: 0, done: VecDeque::new() });
+        Arc::new(Jobs { processor, limit: limit.max(1), rules, state, finished: Condvar::new() })
+    }
+
+    /// Queues a file path or URL, as accepted by `Processor::process`, and returns the job's id,
+    /// counting from 1. Inputs the rules do not allow are refused with `PermissionDenied`.
+    pub fn submit(self: &Arc<Self>, address: &str) -> io::Result<u64> {
+        let address = self.allowed(address)?;
+        let mut state = self.lock();
+        state.last += 1;
+        let id = state.last;
+        state.jobs.insert(id, Job { address, status: JobStatus::Queued, results: None });
+        if state.running < self.limit {
+            state.running += 1;
+            let jobs = Arc::clone(self);
+            thread::spawn(move || jobs.work());
+        }
+        Ok(id)
+    }
+
+    /// The status of a job, or `None` when no job has that id or it has been dropped
+    pub fn status(&self, id: u64) -> Option<JobStatus> {
+        Some(self.lock().jobs.get(&id)?.status.clone())
+    }
+
+    /// Blocks until the job is done or has failed, and returns its status
+    pub fn wait(&self, id: u64) -> Option<JobStatus> {
+        let mut state = self.lock();
+        loop {
+            match &state.jobs.get(&id)?.status {
+                JobStatus::Queued | JobStatus::Running => {}
+                status => return Some(status.clone()),
+            }
+            state = self.finished.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    // The address a job reads for a submitted one: a URL of an allowed scheme, or the resolved
+    // path of an existing file under the root
+    fn allowed(&self, address: &str) -> io::Result<String> {
+        let denied = |message: String| io::Error::new(io::ErrorKind::PermissionDenied, message);
+        if let Some((scheme, _)) = address.split_once("://") {
+            return match self.rules.schemes.iter().any(|allowed| allowed == scheme) {
+                true => Ok(address.to_string()),
+                false => Err(denied(format!("Jobs may not read {scheme} URLs"))),
+            };
+        }
+        let Some(root) = &self.rules.root else {
+            return Err(denied("Jobs may not read files".to_string()));
+        };
+        // A path that cannot be resolved is refused as one outside the root is, so that
+        // submissions do not tell which files exist elsewhere
+        let outside = || denied(format!("Jobs may only read existing files under {}", root.display()));
+        let root = root.canonicalize().map_err(|_| outside())?;
+        let path = root.join(address).canonicalize().map_err(|_| outside())?;
+        match (path.starts_with(&root), path.to_str()) {
+            (true, Some(path)) => Ok(path.to_string()),
+            _ => Err(outside()),
+        }
+    }
+
+    // Runs queued jobs one after another until none are left, then gives up its slot
+    fn work(&self) {
+        loop {
+            let (id, address) = {
+                let mut state = self.lock();
+                let id = state.next;
+                let Some(job) = state.jobs.get_mut(&id) else {
+                    state.running -= 1;
+                    return;
+                };
+                job.status = JobStatus::Running;
+                let address = job.address.clone();
+                state.next += 1;
+                (id, address)
+            };
+            let outcome = self.processor.process(&address);
+            let mut state = self.lock();
+            if let Some(job) = state.jobs.get_mut(&id) {
+                match outcome {
+                    Ok(results) => (job.status, job.results) = (JobStatus::Done, Some(results)),
+                    Err(error) => job.status = JobStatus::Failed(error.to_string()),
+                }
+            }
+            state.done.push_back(id);
+            while state.done.len() > self.rules.max_finished.max(1) {
+                if let Some(dropped) = state.done.pop_front() {
+                    state.jobs.remove(&dropped);
+                }
+            }
+            self.finished.notify_all();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, JobTable<A>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // The status and JSON body answering a GET of `/jobs` or a URL under it
+    fn respond(&self, url: &str) -> io::Result<(u16, Vec<u8>)> {
+        let state = self.lock();
+        let mut body = Vec::new();
+        let rest = url.strip_prefix("/jobs").unwrap_or(url);
+        if rest.split('?').next().unwrap_or_default().trim_end_matches('/').is_empty() {
+            write!(body, "[")?;
+            for (index, (&id, job)) in state.jobs.iter().enumerate() {
+                if index > 0 {
+                    write!(body, ",")?;
+                }
+                write_job(&mut body, id, job)?;
+            }
+            writeln!(body, "]")?;
+            return Ok((200, body));
+        }
+        let rest = rest.trim_start_matches('/');
+        let (id, endpoint) = rest.find(['/', '?']).map_or((rest, ""), |split| rest.split_at(split));
+        let Some((id, job)) = id.parse().ok().and_then(|id| Some((id, state.jobs.get(&id)?))) else {
+            return error(404, &format!("No job with id {id}"));
+        };
+        match (&job.results, endpoint.split('?').next().unwrap_or_default().trim_end_matches('/')) {
+            (_, "") => {
+                write_job(&mut body, id, job)?;
+                writeln!(body)?;
+                Ok((200, body))
+            }
+            // The results of a finished job are queried as those of the server are
+            (Some(results), _) => respond(results, endpoint),
+            (None, _) => error(409, &format!("Job {id} has no results, being {}", job.status.name())),
+        }
+    }
+}
+
+fn write_job<A, W: Write>(writer: &mut W, id: u64, job: &Job<A>) -> io::Result<()> {
+    write!(writer, "{{\"id\":{id},\"address\":")?;
+    write_json_string(writer, &job.address)?;
+    write!(writer, ",\"status\":\"{}\"", job.status.name())?;
+    if let JobStatus::Failed(message) = &job.status {
+        write!(writer, ",\"error\":")?;
+        write_json_string(writer, message)?;
+    }
+    write!(writer, "}}")
+}
+
+/// Answers JSON queries about the results over HTTP at `address`, such as `127.0.0.1:8080`,
+/// until the process ends:
+///
+/// - `/stations`, every station as in `Format::Json`
+/// - `/stations/{name}`, one station's values, with the name percent-encoded
+/// - `/top?n=10&by=mean`, the stations with the highest values of a column, highest first
+pub fn serve<A: Columns>(results: &Results<A>, address: &str) -> io::Result<()> {
+    listen(address, |request| match request.method() {
+        Method::Get | Method::Head => respond(results, request.url()),
+        _ => error(405, "Only GET requests are served"),
+    })
+}
+
+/// Serves the results as `serve` does, and takes jobs aggregating other files for the same
+/// clients:
+///
+/// - `POST /jobs` with a file path or URL as the body queues a job, answering with its id, or
+///   403 when the input is not allowed
+/// - `/jobs`, every job with its status, and `/jobs/{id}` one of them
+/// - `/jobs/{id}/stations`, `/jobs/{id}/stations/{name}` and `/jobs/{id}/top` query the results
+///   of a finished job
+///
+/// Clients choose what the server reads, so the `JobRules` of `jobs` are the whole of what keeps
+/// them from the server's files and network: paths must lie under the rules' root and URLs must
+/// be of an allowed scheme. Only `max_finished` finished jobs are kept, the one that finished
+/// first being dropped, and with it its results, to make room for the next.
+pub fn serve_jobs<S: BuildHasher + Clone + Send + Sync + 'static, A: Columns + 'static>(results: &Results<A>, jobs: &Arc<Jobs<S, A>>, address: &str) -> io::Result<()> {
+    listen(address, |request| {
+        let url = request.url().to_string();
+        match (request.method(), url.split('?').next().unwrap_or_default().trim_end_matches('/')) {
+            (Method::Post, "/jobs") => submit(jobs, request),
+            (Method::Get | Method::Head, path) if path == "/jobs" || path.starts_with("/jobs/") => jobs.respond(&url),
+            (Method::Get | Method::Head, _) => respond(results, &url),
+            _ => error(405, "Only GET requests and POSTs to /jobs are served"),
+        }
+    })
+}
+
+// Queues the path or URL in the body of the request
+fn submit<S: BuildHasher + Clone + Send + Sync + 'static, A: Columns + 'static>(jobs: &Arc<Jobs<S, A>>, request: &mut Request) -> io::Result<(u16, Vec<u8>)> {
+    let mut address = String::new();
+    if request.as_reader().take(MAX_ADDRESS_LEN + 1).read_to_string(&mut address).is_err() {
+        return error(400, "The body is not UTF-8");
+    }
+    let address = address.trim();
+    match address {
+        "" => return error(400, "The body must be the path or URL of a measurements file"),
+        // Standard input is the server's own
+        "-" => return error(400, "Jobs cannot read standard input"),
+        _ if address.len() as u64 > MAX_ADDRESS_LEN => return error(400, "The path or URL is too long"),
+        _ => {}
+    }
+    let id = match jobs.submit(address) {
+        Ok(id) => id,
+        Err(refused) => return error(403, &refused.to_string()),
+    };
+    let mut body = Vec::new();
+    writeln!(body, "{{\"id\":{id}}}")?;
+    Ok((202, body))
+}
+
+// Answers each request with the status and JSON body `handle` returns, until the process ends
+fn listen(address: &str, mut handle: impl FnMut(&mut Request) -> io::Result<(u16, Vec<u8>)>) -> io::Result<()> {
+    let server = Server::http(address).map_err(io::Error::other)?;
+    let content_type = Header::from_bytes("Content-Type", "application/json")
+        .map_err(|_| io::Error::other("Invalid Content-Type header"))?;
+    for mut request in server.incoming_requests() {
+        let (status, body) = handle(&mut request)?;
+        let response = Response::from_data(body).with_status_code(status).with_header(content_type.clone());
+        // A client hanging up early is its own problem, not the server's
+        if let Err(error) = request.respond(response) {
+            eprintln!("Could not answer a request: {error}");
+        }
+    }
+    Ok(())
+}
+
+// The status and JSON body answering a GET of `url`
+fn respond<A: Columns>(results: &Results<A>, url: &str) -> io::Result<(u16, Vec<u8>)> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let mut body = Vec::new();
+    match path.trim_end_matches('/') {
+        "/stations" => write_results(results, OutputOptions { format: Format::Json, ..Default::default() }, &mut body)?,
+        "/top" => {
+            let mut n = DEFAULT_TOP;
+            let mut sort_by = SortBy::Mean;
+            for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+                match (key, value.parse(), column(value)) {
+                    ("n", Ok(value), _) => n = value,
+                    ("by", _, Some(column)) => sort_by = column,
+                    ("n" | "by", _, _) => return error(400, &format!("Invalid {key}: {value}")),
+                    _ => {}
+                }
+            }
+            let options = OutputOptions { format: Format::Json, sort_by, limit: Some(Limit::Top(n)), ..Default::default() };
+            write_results(results, options, &mut body)?;
+        }
+        path => {
+            let Some(name) = path.strip_prefix("/stations/") else {
+                return error(404, &format!("No such endpoint: {path}"));
+            };
+            let Some(name) = percent_decode(name) else {
+                return error(400, "Station name is not valid percent-encoded UTF-8");
+            };
+            let Some(columns) = results.get(&name) else {
+                return error(404, &format!("No station named {name}"));
+            };
+            write_json_values::<A, _>(&mut body, columns.data(), &columns.extra())?;
+            writeln!(body)?;
+        }
+    }
+    Ok((200, body))
+}
+
+fn error(status: u16, message: &str) -> io::Result<(u16, Vec<u8>)> {
+    let mut body = b"{\"error\":".to_vec();
+    write_json_string(&mut body, message)?;
+    writeln!(body, "}}")?;
+    Ok((status, body))
+}
+
+fn column(name: &str) -> Option<SortBy> {
+    match name {
+        "station" => Some(SortBy::Station),
+        "min" => Some(SortBy::Min),
+        "mean" => Some(SortBy::Mean),
+        "max" => Some(SortBy::Max),
+        "count" => Some(SortBy::Count),
+        _ => None,
+    }
+}
+
+fn percent_decode(encoded: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_respond() {
+        let results = crate::Processor::builder().build().unwrap()
+            .process_reader("Hamburg;12.0\nSt. John's;-3.4\nZürich;5.0\nHamburg;8.0\n".as_bytes()).unwrap();
+        let get = |url: &str| {
+            let (status, body) = respond(&results, url).unwrap();
+            (status, String::from_utf8(body).unwrap())
+        };
+        assert_eq!(get("/stations/Hamburg"), (200, "{\"min\":8.0,\"mean\":10.0,\"max\":12.0,\"count\":2}\n".to_string()));
+        assert_eq!(get("/stations/Z%C3%BCrich").1, "{\"min\":5.0,\"mean\":5.0,\"max\":5.0,\"count\":1}\n");
+        assert_eq!(get("/stations/St.%20John's").0, 200);
+        assert_eq!(get("/stations/Paris").0, 404);
+        assert_eq!(get("/stations/%ZZ").0, 400);
+        assert!(get("/stations").1.starts_with("{\"Hamburg\":"));
+        let top = get("/top?n=2").1;
+        assert!(top.starts_with("{\"Hamburg\":") && top.contains("Zürich") && !top.contains("John"), "{top}");
+        assert!(get("/top?by=station&n=1").1.starts_with("{\"Zürich\":"));
+        assert_eq!(get("/top?n=ten").0, 400);
+        assert_eq!(get("/").0, 404);
+    }
+
+    #[test]
+    fn test_jobs() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("measurements.txt");
+        std::fs::write(&path, "Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n").unwrap();
+        let rules = JobRules { root: Some(root.path().to_path_buf()), schemes: vec!["file".to_string()], max_finished: 2 };
+        let jobs = Jobs::new(crate::Processor::builder().build().unwrap(), 1, rules);
+        let refused = |address: &str| jobs.submit(address).unwrap_err().kind();
+        assert_eq!(refused("https://example.com/measurements.txt"), io::ErrorKind::PermissionDenied);
+        assert_eq!(refused("missing.txt"), io::ErrorKind::PermissionDenied);
+        assert_eq!(refused("../measurements.txt"), io::ErrorKind::PermissionDenied);
+        assert_eq!(refused("/etc/hostname"), io::ErrorKind::PermissionDenied);
+        // The first of three finished jobs is dropped
+        jobs.submit("measurements.txt").unwrap();
+        let missing = jobs.submit("file:///no/such/measurements.txt").unwrap();
+        let file = jobs.submit(path.to_str().unwrap()).unwrap();
+        assert_eq!((missing, file), (2, 3));
+        assert_eq!(jobs.wait(file), Some(JobStatus::Done));
+        assert!(matches!(jobs.status(missing), Some(JobStatus::Failed(_))));
+        assert_eq!(jobs.status(1), None);
+        assert_eq!(jobs.status(4), None);
+        assert_eq!(jobs.status(0), None);
+
+        let get = |url: &str| {
+            let (status, body) = jobs.respond(url).unwrap();
+            (status, String::from_utf8(body).unwrap())
+        };
+        let listed = get("/jobs").1;
+        assert!(listed.starts_with("[{\"id\":2,\"address\":\"file:///no/such/measurements.txt\",\"status\":\"failed\",\"error\":"), "{listed}");
+        assert!(listed.contains("{\"id\":3,") && !listed.contains("\"id\":1,"), "{listed}");
+        assert!(get("/jobs/3").1.ends_with("\"status\":\"done\"}\n"));
+        assert_eq!(get("/jobs/3/stations"), (200, "{\"Bulawayo\":{\"min\":8.9,\"mean\":8.9,\"max\":8.9,\"count\":1},\"Hamburg\":{\"min\":-3.4,\"mean\":4.3,\"max\":12.0,\"count\":2}}\n".to_string()));
+        assert_eq!(get("/jobs/3/stations/Hamburg").1, "{\"min\":-3.4,\"mean\":4.3,\"max\":12.0,\"count\":2}\n");
+        assert!(get("/jobs/3/top?n=1").1.starts_with("{\"Bulawayo\":"));
+        assert_eq!(get("/jobs/2/stations").0, 409);
+        assert_eq!(get("/jobs/1").0, 404);
+        assert_eq!(get("/jobs/one").0, 404);
+    }
+
+}
@@ -0,0 +1,409 @@
+use std::fmt::{self, Debug};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use memchr::memchr_iter;
+use super::generate::SplitMix64;
+use super::Config;
+
+const BYTES_PER_MEGABYTE: f64 = 1_000_000.0;
+// Bucket holds a tenth of a second of reads so pacing stays smooth at large buffer sizes
+const BURST_SECONDS: f64 = 0.1;
+const SAMPLE_SEED: u64 = 0x5eed;
+
+/// What a zero-byte read from the input means
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EofPolicy {
+    /// The input is finished
+    #[default]
+    Stop,
+    /// More input may follow, so wait this long and read again. Without an end sentinel the run
+    /// only ends when the process is stopped.
+    WaitAndRetry(Duration),
+}
+
+// Called with the input bytes read so far and the total, when known
+#[derive(Clone)]
+pub(crate) struct ProgressHook(pub Arc<dyn Fn(u64, Option<u64>) + Send + Sync>);
+impl Debug for ProgressHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ProgressHook")
+    }
+}
+
+// Input bytes consumed by every reader of one run, shared so that parallel readers report a
+// single running total
+pub(crate) struct Progress<'a> {
+    hook: Option<&'a ProgressHook>,
+    read: AtomicU64,
+    total: Option<u64>,
+}
+impl<'a> Progress<'a> {
+    pub fn new(hook: Option<&'a ProgressHook>, total: Option<u64>) -> Self {
+        Progress { hook, read: AtomicU64::new(0), total }
+    }
+    pub fn advance(&self, bytes: usize) {
+        let read = self.read.fetch_add(bytes as u64, Ordering::Relaxed) + bytes as u64;
+        if let Some(hook) = self.hook {
+            (hook.0)(read, self.total);
+        }
+    }
+    pub fn bytes_read(&self) -> u64 {
+        self.read.load(Ordering::Relaxed)
+    }
+}
+
+// Reports every read from the inner reader as progress
+pub(crate) struct Counted<'a, R> {
+    inner: R,
+    progress: &'a Progress<'a>,
+}
+impl<'a, R> Counted<'a, R> {
+    pub fn new(inner: R, progress: &'a Progress<'a>) -> Self {
+        Counted { inner, progress }
+    }
+}
+impl<R: Read> Read for Counted<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.progress.advance(bytes_read);
+        Ok(bytes_read)
+    }
+}
+
+// Applies the configured end-of-input handling and read limits to an input. The throttle goes
+// nearest the input, so it paces the bytes actually read rather than those left after sampling.
+pub(crate) fn wrap<'a, R: Read + Send + 'a>(reader: R, config: &Config) -> io::Result<Box<dyn Read + Send + 'a>> {
+    let reader: Box<dyn Read + Send + 'a> = match config.max_read_mbps {
+        Some(mbps) => Box::new(Throttled::new(reader, mbps)?),
+        None => Box::new(reader),
+    };
+    let reader: Box<dyn Read + Send + 'a> = match config.eof_policy {
+        EofPolicy::Stop => reader,
+        EofPolicy::WaitAndRetry(interval) => Box::new(Waiting { inner: reader, interval }),
+    };
+    let reader: Box<dyn Read + Send + 'a> = match &config.end_sentinel {
+        Some(sentinel) => Box::new(Sentinel::new(reader, sentinel)),
+        None => reader,
+    };
+    let reader: Box<dyn Read + Send + 'a> = match config.max_lines {
+        Some(lines) => Box::new(LineLimit { inner: reader, remaining: lines }),
+        None => reader,
+    };
+    Ok(match config.sample {
+        Some(fraction) => Box::new(Sampled::new(reader, fraction)),
+        None => reader,
+    })
+}
+
+// Treats empty reads as a pause in the input rather than its end
+struct Waiting<R> {
+    inner: R,
+    interval: Duration,
+}
+impl<R: Read> Read for Waiting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.inner.read(buf)? {
+                0 => thread::sleep(self.interval),
+                bytes_read => return Ok(bytes_read),
+            }
+        }
+    }
+}
+
+// Reads a file that keeps being appended to, waiting at its end for more
+pub(crate) struct Tail {
+    file: File,
+    position: u64,
+    interval: Duration,
+}
+impl Tail {
+    pub fn new(file: File, interval: Duration) -> Self {
+        Tail { file, position: 0, interval }
+    }
+}
+impl Read for Tail {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.file.read(buf)? {
+                // A rotated or truncated file would otherwise be waited on forever
+                0 if self.file.metadata()?.len() < self.position => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "The file shrank while being followed"));
+                }
+                0 => thread::sleep(self.interval),
+                bytes_read => {
+                    self.position += bytes_read as u64;
+                    return Ok(bytes_read);
+                }
+            }
+        }
+    }
+}
+
+// Ends the input at the first line equal to the sentinel, which is itself dropped
+struct Sentinel<R> {
+    inner: BufReader<R>,
+    sentinel: Vec<u8>,
+    pending: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+impl<R: Read> Sentinel<R> {
+    fn new(inner: R, sentinel: &str) -> Self {
+        Sentinel { inner: BufReader::new(inner), sentinel: sentinel.as_bytes().to_vec(), pending: Vec::new(), position: 0, finished: false }
+    }
+    // Buffers whole lines until there is at least `wanted` bytes, the sentinel or the end of input
+    fn fill(&mut self, wanted: usize) -> io::Result<()> {
+        self.pending.clear();
+        self.position = 0;
+        while self.pending.len() < wanted && !self.finished {
+            let line_start = self.pending.len();
+            if self.inner.read_until(b'\n', &mut self.pending)? == 0 {
+                self.finished = true;
+                break;
+            }
+            let line = &self.pending[line_start..];
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+            if line.strip_suffix(b"\r").unwrap_or(line) == self.sentinel {
+                self.pending.truncate(line_start);
+                self.finished = true;
+            }
+        }
+        Ok(())
+    }
+}
+impl<R: Read> Read for Sentinel<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.pending.len() {
+            self.fill(buf.len())?;
+        }
+        let bytes_read = buf.len().min(self.pending.len() - self.position);
+        buf[..bytes_read].copy_from_slice(&self.pending[self.position..self.position + bytes_read]);
+        self.position += bytes_read;
+        Ok(bytes_read)
+    }
+}
+
+// Ends the input after this many lines
+struct LineLimit<R> {
+    inner: R,
+    remaining: u64,
+}
+impl<R: Read> Read for LineLimit<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+        let bytes_read = self.inner.read(buf)?;
+        for newline in memchr_iter(b'\n', &buf[..bytes_read]) {
+            self.remaining -= 1;
+            if self.remaining == 0 {
+                return Ok(newline + 1);
+            }
+        }
+        Ok(bytes_read)
+    }
+}
+
+// Keeps each line with the given probability. The seed is fixed, so the same input always
+// gives the same sample.
+struct Sampled<R> {
+    inner: BufReader<R>,
+    fraction: f64,
+    random: SplitMix64,
+    pending: Vec<u8>,
+    position: usize,
+}
+impl<R: Read> Sampled<R> {
+    fn new(inner: R, fraction: f64) -> Self {
+        Sampled { inner: BufReader::new(inner), fraction, random: SplitMix64(SAMPLE_SEED), pending: Vec::new(), position: 0 }
+    }
+    // Buffers kept lines until there is at least `wanted` bytes or the end of input
+    fn fill(&mut self, wanted: usize) -> io::Result<()> {
+        self.pending.clear();
+        self.position = 0;
+        while self.pending.len() < wanted {
+            let line_start = self.pending.len();
+            if self.inner.read_until(b'\n', &mut self.pending)? == 0 {
+                break;
+            }
+            if self.random.unit() > self.fraction {
+                self.pending.truncate(line_start);
+            }
+        }
+        Ok(())
+    }
+}
+impl<R: Read> Read for Sampled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.pending.len() {
+            self.fill(buf.len())?;
+        }
+        let bytes_read = buf.len().min(self.pending.len() - self.position);
+        buf[..bytes_read].copy_from_slice(&self.pending[self.position..self.position + bytes_read]);
+        self.position += bytes_read;
+        Ok(bytes_read)
+    }
+}
+
+/// Token-bucket pacing over any reader, limiting throughput to a fixed number of megabytes
+/// per second.
+pub struct Throttled<R> {
+    inner: R,
+    bytes_per_second: f64,
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+impl<R: Read> Throttled<R> {
+    pub fn new(inner: R, max_read_mbps: f64) -> io::Result<Self> {
+        if !(max_read_mbps.is_finite() && max_read_mbps > 0.0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Read limit must be a positive number"));
+        }
+        let bytes_per_second = max_read_mbps * BYTES_PER_MEGABYTE;
+        let capacity = (bytes_per_second * BURST_SECONDS).max(1.0);
+        Ok(Throttled { inner, bytes_per_second, capacity, tokens: capacity, last_refill: Instant::now() })
+    }
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.bytes_per_second).min(self.capacity);
+        self.last_refill = now;
+    }
+}
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.refill();
+        if self.tokens < 1.0 {
+            thread::sleep(Duration::from_secs_f64((1.0 - self.tokens) / self.bytes_per_second));
+            self.refill();
+        }
+        let allowed = (self.tokens as usize).clamp(1, buf.len());
+        let bytes_read = self.inner.read(&mut buf[..allowed])?;
+        self.tokens -= bytes_read as f64;
+        Ok(bytes_read)
+    }
+}
+
+/// Test-only reader that returns short reads and `Interrupted` errors in a fixed pattern
+#[cfg(test)]
+pub(crate) struct FaultInjector<R> {
+    inner: R,
+    calls: usize,
+}
+#[cfg(test)]
+impl<R: Read> FaultInjector<R> {
+    pub fn new(inner: R) -> Self {
+        FaultInjector { inner, calls: 0 }
+    }
+}
+#[cfg(test)]
+impl<R: Read> Read for FaultInjector<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        const READ_SIZES: [usize; 8] = [1, 2, 3, 5, 8, 13, 4096, 65537];
+        self.calls += 1;
+        if self.calls.is_multiple_of(5) {
+            return Err(io::Error::from(io::ErrorKind::Interrupted));
+        }
+        let size = READ_SIZES[self.calls % READ_SIZES.len()].min(buf.len());
+        self.inner.read(&mut buf[..size])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_throttled_paces_reads() {
+        let data = vec![b'x'; 200_000];
+        let start = Instant::now();
+        let mut output = Vec::new();
+        // 1 MB/s with a 100KB bucket, so the second 100KB has to wait for refills
+        Throttled::new(data.as_slice(), 1.0).unwrap().read_to_end(&mut output).unwrap();
+        assert_eq!(output, data);
+        assert!(start.elapsed() >= Duration::from_millis(80));
+        assert!(Throttled::new(data.as_slice(), 0.0).is_err());
+        assert!(Throttled::new(data.as_slice(), f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_sentinel_ends_input() {
+        let config = Config { end_sentinel: Some("END".to_string()), ..Config::default() };
+        let mut output = String::new();
+        wrap(&b"Hamburg;12.0\nBulawayo;8.9\nEND\nHamburg;-3.4\n"[..], &config).unwrap().read_to_string(&mut output).unwrap();
+        assert_eq!(output, "Hamburg;12.0\nBulawayo;8.9\n");
+    }
+
+    #[test]
+    fn test_line_limit_and_sample() {
+        let input = (0..1_000).map(|line| format!("a;{}.0\n", line % 100)).collect::<String>();
+        let read = |config: Config| {
+            let mut output = String::new();
+            wrap(input.as_bytes(), &config).unwrap().read_to_string(&mut output).unwrap();
+            output
+        };
+        assert_eq!(read(Config { max_lines: Some(2), ..Config::default() }), "a;0.0\na;1.0\n");
+        let sample = read(Config { sample: Some(0.1), ..Config::default() });
+        assert!((50..150).contains(&sample.lines().count()), "{}", sample.lines().count());
+        assert!(sample.lines().all(|line| input.contains(&format!("{line}\n"))));
+        assert_eq!(read(Config { sample: Some(0.1), ..Config::default() }), sample);
+        assert_eq!(read(Config { sample: Some(1.0), ..Config::default() }), input);
+    }
+
+    #[test]
+    fn test_throttle_paces_sampled_input() {
+        // 180KB read at 1 MB/s has to wait for refills, though only a tenth of it is kept
+        let input = (0..30_000).map(|line| format!("a;{}.0\n", line % 10)).collect::<String>();
+        let config = Config { sample: Some(0.1), max_read_mbps: Some(1.0), ..Config::default() };
+        let start = Instant::now();
+        let mut output = String::new();
+        wrap(input.as_bytes(), &config).unwrap().read_to_string(&mut output).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(80));
+        let mut sample = String::new();
+        wrap(input.as_bytes(), &Config { max_read_mbps: None, ..config }).unwrap().read_to_string(&mut sample).unwrap();
+        assert_eq!(output, sample);
+    }
+
+    #[test]
+    fn test_waiting_retries_empty_reads() {
+        // Stands in for a pipe whose writer has paused: empty reads before each chunk of data
+        struct Paused<'a> { chunks: Vec<&'a [u8]>, empty_reads: usize }
+        impl Read for Paused<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.empty_reads % 3 != 2 {
+                    self.empty_reads += 1;
+                    return Ok(0);
+                }
+                self.empty_reads += 1;
+                let Some(chunk) = self.chunks.pop() else { return Ok(0) };
+                buf[..chunk.len()].copy_from_slice(chunk);
+                Ok(chunk.len())
+            }
+        }
+        let input = Paused { chunks: vec![b"END\n", b"Bulawayo;8.9\n", b"Hamburg;12.0\n"], empty_reads: 0 };
+        let config = Config {
+            eof_policy: EofPolicy::WaitAndRetry(Duration::from_millis(1)),
+            end_sentinel: Some("END".to_string()),
+            ..Config::default()
+        };
+        let mut output = String::new();
+        wrap(input, &config).unwrap().read_to_string(&mut output).unwrap();
+        assert_eq!(output, "Hamburg;12.0\nBulawayo;8.9\n");
+    }
+
+}
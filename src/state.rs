@@ -0,0 +1,124 @@
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use super::{Data, ProcessStats, Results};
+
+// Start of every state file, with the version of the layout that follows
+const MAGIC: &[u8; 8] = b"BRCSTAT1";
+// Longest station name accepted back, well beyond the challenge's 100 bytes
+const MAX_NAME_LENGTH: usize = 1 << 16;
+
+impl Results<Data> {
+    /// Writes the per-station accumulators in a compact binary layout, so a partial run can be
+    /// saved and later read back with `read_state` and combined with `Results::merge`. The byte
+    /// and line counts are kept, timings and invalid lines are not.
+    pub fn write_state<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(MAGIC)?;
+        let stats = self.stats();
+        for number in [stats.bytes_read, stats.lines, stats.batches, stats.sampled as u64, self.len() as u64] {
+            writer.write_all(&number.to_le_bytes())?;
+        }
+        for (station, data) in self.iter() {
+            writer.write_all(&(station.len() as u32).to_le_bytes())?;
+            writer.write_all(station.as_bytes())?;
+            writer.write_all(&data.sum.to_le_bytes())?;
+            writer.write_all(&data.count.to_le_bytes())?;
+            writer.write_all(&data.min.to_le_bytes())?;
+            writer.write_all(&data.max.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    /// Reads back results saved by `write_state`
+    pub fn read_state<R: Read>(reader: R) -> io::Result<Self> {
+        let mut reader = BufReader::new(reader);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("Not a saved aggregation state"));
+        }
+        let [bytes_read, lines, batches, sampled, stations] = [(); 5].map(|_| read_u64(&mut reader));
+        let stats = ProcessStats { bytes_read: bytes_read?, lines: lines?, batches: batches?, sampled: sampled? != 0, ..Default::default() };
+
+        let mut entries = Vec::new();
+        for _ in 0..stations? {
+            let length = u32::from_le_bytes(read_array(&mut reader)?) as usize;
+            if length > MAX_NAME_LENGTH {
+                return Err(invalid("Station name too long"));
+            }
+            let mut name = vec![0; length];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| invalid("Station name is not UTF-8"))?;
+            let data = Data {
+                sum: i64::from_le_bytes(read_array(&mut reader)?),
+                count: u32::from_le_bytes(read_array(&mut reader)?),
+                min: i32::from_le_bytes(read_array(&mut reader)?),
+                max: i32::from_le_bytes(read_array(&mut reader)?),
+            };
+            if data.count == 0 || data.min > data.max {
+                return Err(invalid("Inconsistent station values"));
+            }
+            if entries.last().is_some_and(|(last, _): &(String, Data)| *last >= name) {
+                return Err(invalid("Stations out of order"));
+            }
+            entries.push((name, data));
+        }
+        if reader.read(&mut [0])? != 0 {
+            return Err(invalid("Trailing bytes after the last station"));
+        }
+        Ok(Results::from_sorted(entries).with_stats(stats))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    read_array(reader).map(u64::from_le_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_state_round_trip() {
+        let processor = crate::Processor::builder().build().unwrap();
+        let first = processor.process_reader(&b"Hamburg;12.0\nAbha;-3.4\nHamburg;-0.5\n"[..]).unwrap();
+        let mut saved = Vec::new();
+        first.write_state(&mut saved).unwrap();
+        let mut restored = Results::read_state(saved.as_slice()).unwrap();
+        assert_eq!(restored.to_string(), first.to_string());
+        assert_eq!((restored.stats().lines, restored.stats().bytes_read), (3, 36));
+
+        restored.merge(processor.process_reader("Abha;7.1\nZürich;0.0\n".as_bytes()).unwrap());
+        let whole = processor.process_reader("Hamburg;12.0\nAbha;-3.4\nHamburg;-0.5\nAbha;7.1\nZürich;0.0\n".as_bytes()).unwrap();
+        assert_eq!(restored.to_string(), whole.to_string());
+        assert_eq!(restored.stats().lines, 5);
+
+        assert!(Results::read_state(&saved[..saved.len() - 1]).is_err());
+        assert!(Results::read_state(&b"Hamburg;12.0\n"[..]).is_err());
+        saved.push(0);
+        assert!(Results::read_state(saved.as_slice()).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_state_json() {
+        let processor = crate::Processor::builder().aggregator::<crate::Moments>().build().unwrap();
+        let results = processor.process_reader(&b"Hamburg;12.0\nAbha;-3.4\nHamburg;-0.5\n"[..]).unwrap();
+        let json = serde_json::to_string(&results).unwrap();
+        let mut restored: Results<crate::Moments> = serde_json::from_str(&json).unwrap();
+        restored.merge(processor.process_reader(&b"Abha;7.1\n"[..]).unwrap());
+        assert_eq!(restored.get("Abha").map(|moments| moments.data().count()), Some(2));
+        assert_eq!(restored.stats().lines, 4);
+    }
+
+}
@@ -0,0 +1,123 @@
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+use super::{Backend, ProcessError, ProcessorBuilder};
+
+/// A whole pipeline a file can be aggregated with, for timing them against each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// One reader filling batch buffers for every worker, however small the file
+    Buffered,
+    /// Workers parsing disjoint ranges of a memory map in place. Unavailable with the `no-unsafe`
+    /// feature.
+    Mmap,
+    /// Each worker reading its own newline-aligned range of the file
+    Partitioned,
+    /// One reader and a single worker
+    SingleThreaded,
+}
+impl Strategy {
+    /// Every strategy this build can run
+    pub fn available() -> Vec<Strategy> {
+        [Strategy::Buffered, Strategy::Mmap, Strategy::Partitioned, Strategy::SingleThreaded].into_iter()
+            .filter(|&strategy| strategy != Strategy::Mmap || !cfg!(feature = "no-unsafe"))
+            .collect()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::Buffered => "buffered",
+            Strategy::Mmap => "mmap",
+            Strategy::Partitioned => "partitioned",
+            Strategy::SingleThreaded => "single-threaded",
+        }
+    }
+}
+
+impl<S> ProcessorBuilder<S> {
+    /// Sets the backend and threads of the strategy, overriding any set before
+    pub fn strategy(self, strategy: Strategy) -> Self {
+        match strategy {
+            Strategy::Buffered => self.backend(Backend::Stream).inline_threshold(0),
+            Strategy::Mmap => self.backend(Backend::Mmap),
+            Strategy::Partitioned => self.backend(Backend::Partitioned),
+            Strategy::SingleThreaded => self.backend(Backend::Stream).inline_threshold(0).threads(1),
+        }
+    }
+}
+
+/// How long one strategy took, and whether its output matched the first strategy's
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    pub strategy: Strategy,
+    pub elapsed: Duration,
+    pub identical: bool,
+}
+
+/// Timings of several strategies on the same file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    /// In the order the strategies were given, the first being the reference
+    pub runs: Vec<Run>,
+}
+impl Comparison {
+    /// Whether every strategy printed the same results
+    pub fn is_consistent(&self) -> bool {
+        self.runs.iter().all(|run| run.identical)
+    }
+}
+impl Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fastest = self.runs.iter().map(|run| run.elapsed).min().unwrap_or_default();
+        writeln!(f, "{:<18}{:>10}{:>10}  Output", "Strategy", "Time", "Relative")?;
+        for (index, run) in self.runs.iter().enumerate() {
+            let output = match (index, run.identical) {
+                (0, _) => "reference",
+                (_, true) => "identical",
+                (_, false) => "DIFFERS",
+            };
+            let relative = run.elapsed.as_secs_f64() / fastest.as_secs_f64().max(f64::MIN_POSITIVE);
+            writeln!(f, "{:<18}{:>9.3}s{:>9.2}x  {output}", run.strategy.name(), run.elapsed.as_secs_f64(), relative)?;
+        }
+        Ok(())
+    }
+}
+
+/// Aggregates the file with each strategy in turn, on top of the settings `builder` returns, and
+/// checks that each prints the same results as the first
+pub fn compare(address: &str, builder: impl Fn() -> ProcessorBuilder, strategies: &[Strategy]) -> Result<Comparison, ProcessError> {
+    let mut reference = None;
+    let mut runs = Vec::with_capacity(strategies.len());
+    for &strategy in strategies {
+        let processor = builder().strategy(strategy).build()?;
+        let start = Instant::now();
+        let output = processor.process(address)?.to_string();
+        let elapsed = start.elapsed();
+        let identical = *reference.get_or_insert_with(|| output.clone()) == output;
+        runs.push(Run { strategy, elapsed, identical });
+    }
+    Ok(Comparison { runs })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Processor;
+
+    #[test]
+    fn test_compare_strategies() {
+        let path = std::env::temp_dir().join(format!("compare_strategies_{}.txt", std::process::id()));
+        let input = (0..20_000).map(|i| format!("Station {};{}.{}\n", i % 97, i % 100 - 50, i % 10)).collect::<String>();
+        std::fs::write(&path, input).unwrap();
+        let strategies = Strategy::available();
+        let comparison = compare(path.to_str().unwrap(), || Processor::builder().batch_lines(1000), &strategies).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(comparison.runs.iter().map(|run| run.strategy).collect::<Vec<_>>(), strategies);
+        assert!(comparison.is_consistent());
+        let table = comparison.to_string();
+        assert!(table.contains("reference") && table.contains("single-threaded"));
+        assert_eq!(Processor::builder().strategy(Strategy::SingleThreaded).build().unwrap().pool.num_threads(), 1);
+    }
+
+}
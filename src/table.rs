@@ -0,0 +1,122 @@
+use super::{Aggregator, Stations};
+
+// Bytes of each name kept in the slot itself, enough for most station names
+const INLINE_BYTES: usize = 16;
+const MIN_SLOTS: usize = 16;
+
+struct Slot<'a, A> {
+    hash: u64,
+    length: usize,
+    prefix: [u8; INLINE_BYTES],
+    name: &'a str,
+    data: A,
+}
+
+// Flat open-addressing table from the names borrowed from a batch to their data, probed
+// linearly. Each slot keeps the name's hash, length and first bytes, so a lookup settles most
+// comparisons without leaving the slot, and a whole comparison of names no longer than
+// `INLINE_BYTES` never touches the batch. Kept at most half full.
+pub(crate) struct StationTable<'a, A> {
+    slots: Vec<Option<Slot<'a, A>>>,
+    len: usize,
+    // Bits of the hash dropped to index the slots, whose count is a power of two
+    shift: u32,
+}
+impl<'a, A: Aggregator> StationTable<'a, A> {
+    pub fn with_capacity(stations: usize) -> Self {
+        let slots = (stations * 2).next_power_of_two().max(MIN_SLOTS);
+        StationTable { slots: (0..slots).map(|_| None).collect(), len: 0, shift: 64 - slots.trailing_zeros() }
+    }
+
+    pub fn update(&mut self, station: &'a str, value: i32) {
+        let (hash, prefix) = (hash(station.as_bytes()), prefix(station.as_bytes()));
+        let mask = self.slots.len() - 1;
+        let mut index = (hash >> self.shift) as usize;
+        loop {
+            match &mut self.slots[index] {
+                Some(slot) if slot.hash == hash && slot.length == station.len() && slot.prefix == prefix
+                    && (station.len() <= INLINE_BYTES || slot.name.as_bytes()[INLINE_BYTES..] == station.as_bytes()[INLINE_BYTES..]) => {
+                    slot.data.update(value);
+                    return;
+                }
+                Some(_) => index = (index + 1) & mask,
+                empty => {
+                    *empty = Some(Slot { hash, length: station.len(), prefix, name: station, data: A::new(value) });
+                    self.len += 1;
+                    if self.len * 2 > self.slots.len() {
+                        self.grow();
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    fn grow(&mut self) {
+        let slots = std::mem::take(&mut self.slots);
+        self.slots = (0..slots.len() * 2).map(|_| None).collect();
+        self.shift -= 1;
+        let mask = self.slots.len() - 1;
+        for slot in slots.into_iter().flatten() {
+            let mut index = (slot.hash >> self.shift) as usize;
+            while self.slots[index].is_some() {
+                index = (index + 1) & mask;
+            }
+            self.slots[index] = Some(slot);
+        }
+    }
+
+    // Folds every station into `map`
+    pub fn flush(self, map: &mut impl Stations<'a, A>) {
+        for slot in self.slots.into_iter().flatten() {
+            map.merge_station(slot.name, slot.data);
+        }
+    }
+}
+
+// Multiplicative hash over the name eight bytes at a time, mixed with its length. The top bits
+// pick the slot, being the best mixed.
+fn hash(name: &[u8]) -> u64 {
+    let mut hash = name.len() as u64;
+    for chunk in name.chunks(8) {
+        let mut word = [0; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        hash = (hash.rotate_left(5) ^ u64::from_le_bytes(word)).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+    hash
+}
+
+fn prefix(name: &[u8]) -> [u8; INLINE_BYTES] {
+    let mut prefix = [0; INLINE_BYTES];
+    let length = name.len().min(INLINE_BYTES);
+    prefix[..length].copy_from_slice(&name[..length]);
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use hashbrown::HashMap;
+    use crate::Data;
+
+    #[test]
+    fn test_station_table() {
+        // Names sharing their inline prefix, differing only past it, and growing the table
+        let names = (0..3000).map(|i| format!("A station with a long name {i}")).chain((0..300).map(|i| format!("S{i}"))).collect::<Vec<_>>();
+        let mut table = StationTable::with_capacity(1);
+        let mut expected = HashMap::<String, Data>::new();
+        for (i, name) in names.iter().enumerate().cycle().take(10_000) {
+            let value = (i % 199) as i32 - 99;
+            table.update(name, value);
+            expected.merge_station(name, Data::new(value));
+        }
+        let mut map = HashMap::<String, Data>::new();
+        table.flush(&mut map);
+        assert_eq!(map.len(), names.len());
+        for (name, data) in &map {
+            assert_eq!(format!("{data:?}"), format!("{:?}", expected[name]));
+        }
+    }
+
+}
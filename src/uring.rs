@@ -0,0 +1,209 @@
+use std::fs::File;
+use std::io::{self, Read};
+
+// Bytes asked for by each read, and so the size of each registered buffer
+#[cfg(all(target_os = "linux", feature = "io-uring", not(feature = "no-unsafe")))]
+const URING_READ_SIZE: usize = 1024 * 1024;
+// Most reads in flight, keeping the registered buffers within the kernel's limit
+pub(crate) const MAX_QUEUE_DEPTH: usize = 4096;
+
+// Reads the whole file in order, with `queue_depth` reads always in flight ahead of the caller
+#[cfg(all(target_os = "linux", feature = "io-uring", not(feature = "no-unsafe")))]
+pub(crate) fn open(file: File, queue_depth: usize) -> io::Result<Box<dyn Read + Send>> {
+    Ok(Box::new(UringReader::new(file, queue_depth, URING_READ_SIZE)?))
+}
+
+#[cfg(not(all(target_os = "linux", feature = "io-uring", not(feature = "no-unsafe"))))]
+pub(crate) fn open(_file: File, _queue_depth: usize) -> io::Result<Box<dyn Read + Send>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "io_uring reads need Linux and the io-uring feature, without no-unsafe"))
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring", not(feature = "no-unsafe")))]
+pub(crate) use reader::UringReader;
+
+#[cfg(all(target_os = "linux", feature = "io-uring", not(feature = "no-unsafe")))]
+mod reader {
+    use std::collections::VecDeque;
+    use std::fs::File;
+    use std::io::{self, Read};
+    use std::os::fd::AsRawFd;
+    use io_uring::{opcode, types, IoUring};
+
+    // Reads a file into a ring of registered buffers, one read in flight per buffer, handing the
+    // bytes out in file order as the reads complete
+    pub(crate) struct UringReader {
+        // Declared first so the ring is torn down before the buffers it reads into are freed
+        ring: IoUring,
+        file: File,
+        buffers: Vec<Box<[u8]>>,
+        // The file offset and length last asked for with each buffer
+        requests: Vec<(u64, usize)>,
+        // What each buffer's last read returned, once it completed
+        completions: Vec<Option<i32>>,
+        // Buffers with a read in flight or waiting to be handed out, in file order
+        queue: VecDeque<usize>,
+        in_flight: usize,
+        length: u64,
+        // Start of the next read
+        next_offset: u64,
+        // The buffer being handed out, how much of it was filled and how much was handed out
+        current: Option<usize>,
+        filled: usize,
+        position: usize,
+    }
+    impl UringReader {
+        pub fn new(file: File, queue_depth: usize, read_size: usize) -> io::Result<Self> {
+            let length = file.metadata()?.len();
+            let ring = IoUring::new(queue_depth.next_power_of_two() as u32)?;
+            let mut buffers = (0..queue_depth).map(|_| vec![0; read_size].into_boxed_slice()).collect::<Vec<_>>();
+            let iovecs = buffers.iter_mut()
+                .map(|buffer| libc::iovec { iov_base: buffer.as_mut_ptr().cast(), iov_len: buffer.len() })
+                .collect::<Vec<_>>();
+            // SAFETY: the buffers stay allocated, and are not moved, for as long as the ring
+            // lives, and are only read from once the kernel is done writing to them
+            unsafe { ring.submitter().register_buffers(&iovecs)? };
+            let mut reader = UringReader {
+                ring,
+                file,
+                buffers,
+                requests: vec![(0, 0); queue_depth],
+                completions: vec![None; queue_depth],
+                queue: VecDeque::with_capacity(queue_depth),
+                in_flight: 0,
+                length,
+                next_offset: 0,
+                current: None,
+                filled: 0,
+                position: 0,
+            };
+            for slot in 0..queue_depth {
+                if !reader.read_next(slot)? {
+                    break;
+                }
+            }
+            Ok(reader)
+        }
+
+        // Starts reading the next part of the file into the buffer, unless the file is all asked for
+        fn read_next(&mut self, slot: usize) -> io::Result<bool> {
+            if self.next_offset >= self.length {
+                return Ok(false);
+            }
+            let length = (self.length - self.next_offset).min(self.buffers[slot].len() as u64) as usize;
+            self.submit(slot, self.next_offset, length)?;
+            self.next_offset += length as u64;
+            self.queue.push_back(slot);
+            Ok(true)
+        }
+
+        fn submit(&mut self, slot: usize, offset: u64, length: usize) -> io::Result<()> {
+            self.requests[slot] = (offset, length);
+            self.completions[slot] = None;
+            let entry = opcode::ReadFixed::new(types::Fd(self.file.as_raw_fd()), self.buffers[slot].as_mut_ptr(), length as u32, slot as u16)
+                .offset(offset)
+                .build()
+                .user_data(slot as u64);
+            // SAFETY: the buffer is registered at index `slot`, holds `length` bytes and is left
+            // alone until the completion for `slot` arrives
+            unsafe { self.ring.submission().push(&entry) }.map_err(io::Error::other)?;
+            self.ring.submit()?;
+            self.in_flight += 1;
+            Ok(())
+        }
+
+        // Waits for the buffer's read, asking again for whatever an interrupted read did not get
+        fn wait_for(&mut self, slot: usize) -> io::Result<usize> {
+            loop {
+                while self.completions[slot].is_none() {
+                    self.ring.submit_and_wait(1)?;
+                    self.collect();
+                }
+                match self.completions[slot].unwrap_or_default() {
+                    result if result >= 0 => return Ok(result as usize),
+                    result if -result == libc::EINTR || -result == libc::EAGAIN => {
+                        let (offset, length) = self.requests[slot];
+                        self.submit(slot, offset, length)?;
+                    }
+                    result => return Err(io::Error::from_raw_os_error(-result)),
+                }
+            }
+        }
+
+        fn collect(&mut self) {
+            for entry in self.ring.completion() {
+                self.completions[entry.user_data() as usize] = Some(entry.result());
+                self.in_flight -= 1;
+            }
+        }
+    }
+    impl Read for UringReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            loop {
+                if let Some(slot) = self.current {
+                    if self.position < self.filled {
+                        let length = buf.len().min(self.filled - self.position);
+                        buf[..length].copy_from_slice(&self.buffers[slot][self.position..self.position + length]);
+                        self.position += length;
+                        return Ok(length);
+                    }
+                    self.current = None;
+                    let (offset, length) = self.requests[slot];
+                    if self.filled < length {
+                        // A short read is finished into the same buffer before moving on
+                        self.submit(slot, offset + self.filled as u64, length - self.filled)?;
+                        self.queue.push_front(slot);
+                    } else {
+                        self.read_next(slot)?;
+                    }
+                }
+                let Some(slot) = self.queue.pop_front() else {
+                    return Ok(0);
+                };
+                let filled = self.wait_for(slot)?;
+                // The file shrank since it was opened
+                if filled == 0 {
+                    self.queue.clear();
+                    return Ok(0);
+                }
+                (self.current, self.filled, self.position) = (Some(slot), filled, 0);
+            }
+        }
+    }
+    impl Drop for UringReader {
+        fn drop(&mut self) {
+            // The kernel may still be writing into the buffers until every read has completed
+            while self.in_flight > 0 && self.ring.submit_and_wait(1).is_ok() {
+                self.collect();
+            }
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux", feature = "io-uring", not(feature = "no-unsafe")))]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_uring_reader() {
+        let path = std::env::temp_dir().join(format!("uring_reader_{}.txt", std::process::id()));
+        let input = (0..5000).map(|i| format!("Station {};{}.{}\n", i % 37, i % 100 - 50, i % 10)).collect::<String>();
+        std::fs::write(&path, &input).unwrap();
+        let mut read = Vec::new();
+        // Many more reads than fit in the queue, the last one short
+        let reader = UringReader::new(File::open(&path).unwrap(), 4, 1000);
+        let Ok(mut reader) = reader else {
+            // Kernels and sandboxes without io_uring
+            std::fs::remove_file(&path).unwrap();
+            return;
+        };
+        reader.read_to_end(&mut read).unwrap();
+        let address = path.to_str().unwrap();
+        let results = crate::Processor::builder().backend(crate::Backend::IoUring).queue_depth(3).build().unwrap().process(address).unwrap();
+        let expected = crate::Processor::builder().build().unwrap().process(address).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read, input.as_bytes());
+        assert_eq!(results.to_string(), expected.to_string());
+    }
+
+}
@@ -0,0 +1,80 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+use std::io;
+use super::{parse_results, Summary};
+
+/// A station whose values differ between two results, or that only one of them has
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discrepancy {
+    pub station: String,
+    pub expected: Option<Summary>,
+    pub actual: Option<Summary>,
+}
+impl Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let values = |summary: Option<Summary>| match summary {
+            Some(Summary { min, mean, max }) => format!("{min:.1}/{mean:.1}/{max:.1}"),
+            None => "missing".to_string(),
+        };
+        write!(f, "{}: expected {}, actual {}", self.station, values(self.expected), values(self.actual))
+    }
+}
+
+/// Outcome of comparing two results station by station
+#[derive(Debug, Clone, PartialEq)]
+pub struct Validation {
+    /// Stations in either result
+    pub stations: usize,
+    /// In order of station name
+    pub discrepancies: Vec<Discrepancy>,
+}
+impl Validation {
+    pub fn is_ok(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+impl Display for Validation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for discrepancy in &self.discrepancies {
+            writeln!(f, "{discrepancy}")?;
+        }
+        writeln!(f, "{} of {} stations differ", self.discrepancies.len(), self.stations)
+    }
+}
+
+/// Compares printed results, such as a known-good baseline and the output of a changed build.
+/// Values match when they differ by at most `tolerance` degrees, so a tolerance of 0 still
+/// treats `-0.0` and `0.0` or `12` and `12.0` as equal.
+pub fn validate(expected: &str, actual: &str, tolerance: f64) -> io::Result<Validation> {
+    let (expected, actual) = (parse_results(expected)?, parse_results(actual)?);
+    // Rounding to tenths leaves errors far below this in values printed to one decimal
+    let tolerance = tolerance + 1e-9;
+    let close = |a: &Summary, b: &Summary| [(a.min, b.min), (a.mean, b.mean), (a.max, b.max)].iter()
+        .all(|(a, b)| (a - b).abs() <= tolerance);
+
+    let stations = expected.keys().chain(actual.keys()).collect::<BTreeSet<_>>();
+    let discrepancies = stations.iter().filter_map(|&station| match (expected.get(station), actual.get(station)) {
+        (Some(a), Some(b)) if close(a, b) => None,
+        (a, b) => Some(Discrepancy { station: station.clone(), expected: a.copied(), actual: b.copied() }),
+    }).collect();
+    Ok(Validation { stations: stations.len(), discrepancies })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let expected = "{Abha=-1.2/18.0/40.3, Hamburg=-0.1/9.7/30.2, Zürich=-5.0/0.0/5.0}";
+        assert!(validate(expected, "{Abha=-1.2/18/40.3, Hamburg=-0.1/9.7/30.2, Zürich=-5.0/-0.0/5.0}\n", 0.0).unwrap().is_ok());
+
+        let validation = validate(expected, "Abha=-1.2/18.1/40.3\nHamburg=-0.1/9.7/30.4\nOslo=1.0/2.0/3.0\n", 0.1).unwrap();
+        assert_eq!(validation.stations, 4);
+        assert_eq!(validation.to_string(), "Hamburg: expected -0.1/9.7/30.2, actual -0.1/9.7/30.4\n\
+            Oslo: expected missing, actual 1.0/2.0/3.0\nZürich: expected -5.0/0.0/5.0, actual missing\n3 of 4 stations differ\n");
+        assert!(validate(expected, "{Abha=1.0}", 0.0).is_err());
+    }
+
+}
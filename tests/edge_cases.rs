@@ -0,0 +1,100 @@
+use std::io::Write;
+
+use rust_billion_row_challenge::{process_file_reference, process_file_to_results, process_file_to_writer, Backend, Processor, ProcessorBuilder, Results};
+use tempfile::NamedTempFile;
+
+fn backends() -> &'static [Backend] {
+    if cfg!(feature = "no-unsafe") { &[Backend::Stream, Backend::Partitioned] } else { &[Backend::Stream, Backend::Mmap, Backend::Partitioned] }
+}
+
+fn measurements(input: &str) -> NamedTempFile {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(input.as_bytes()).unwrap();
+    file.flush().unwrap();
+    file
+}
+
+// Every station's count and printed values
+fn outcome(results: Results) -> (Vec<(String, u32)>, String) {
+    (results.iter().map(|(station, data)| (station.to_string(), data.count())).collect(), results.to_string())
+}
+
+// Aggregates the file with every backend, configured by `builder`, and with the default
+// settings, and checks each against the reference implementation
+fn check(input: &str, builder: impl Fn() -> ProcessorBuilder) {
+    let file = measurements(input);
+    let address = file.path().to_str().unwrap();
+    let expected = outcome(process_file_reference(address).unwrap());
+    assert_eq!(outcome(process_file_to_results(address).unwrap()), expected);
+    for &backend in backends() {
+        let processor = builder().backend(backend).build().unwrap();
+        assert_eq!(outcome(processor.process(address).unwrap()), expected, "{backend:?}");
+    }
+}
+
+#[test]
+fn test_smaller_than_one_batch() {
+    check("Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n", Processor::builder);
+    check("Hamburg;12.0", Processor::builder);
+
+    let file = measurements("Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\n");
+    let mut output = Vec::new();
+    process_file_to_writer(file.path().to_str().unwrap(), &mut output).unwrap();
+    assert_eq!(output, b"{Bulawayo=8.9/8.9/8.9, Hamburg=-3.4/4.3/12.0}\n");
+}
+
+#[test]
+fn test_empty_file() {
+    let file = measurements("");
+    for &backend in backends() {
+        let results = Processor::builder().backend(backend).build().unwrap().process(file.path().to_str().unwrap()).unwrap();
+        assert!(results.is_empty(), "{backend:?}");
+    }
+}
+
+#[test]
+fn test_exactly_at_batch_boundaries() {
+    const BATCH: usize = 64;
+    for lines in [BATCH - 1, BATCH, BATCH + 1, 2 * BATCH, 10 * BATCH] {
+        let input = (0..lines).map(|i| format!("Station {};{}.{}\n", i % 7, i % 100, i % 10)).collect::<String>();
+        // Reads of exactly one batch, so the last line of a batch ends on the last byte read
+        let line_length = input.len() / lines;
+        let builder = || Processor::builder().batch_lines(BATCH).avg_line_len(line_length).max_line_len(line_length).detect_line_lengths(false).threads(3);
+        check(&input, builder);
+        check(input.trim_end(), builder);
+    }
+}
+
+#[test]
+fn test_batch_boundaries_splitting_characters() {
+    // Four-byte characters with one to three bytes of ASCII before them, so reads of any size end
+    // partway through some character
+    let names = ["🌍", "a🌍", "ab🌍🌍", "abc東京", "Zürich", "São Paulo"];
+    let input = (0..5_000).map(|i| format!("{};{}.{}\n", names[i % names.len()], i as i64 % 50 - 25, i % 10)).collect::<String>();
+    for buffer_capacity in [1, 7, 61, 4096] {
+        check(&input, || Processor::builder().batch_lines(33).avg_line_len(3).buffer_capacity(buffer_capacity).threads(4));
+    }
+}
+
+#[test]
+fn test_single_station() {
+    let input = (0..10_000).map(|i| format!("Hamburg;{}.{}\n", i as i64 % 199 - 99, i % 10)).collect::<String>();
+    check(&input, || Processor::builder().batch_lines(100).threads(4));
+}
+
+#[test]
+fn test_ten_thousand_stations() {
+    let input = (0..50_000).map(|i| format!("Station {};{}.{}\n", i * 7_919 % 10_000, i as i64 % 199 - 99, i % 10)).collect::<String>();
+    check(&input, || Processor::builder().batch_lines(1_000).threads(4));
+    let file = measurements(&input);
+    assert_eq!(process_file_to_results(file.path().to_str().unwrap()).unwrap().len(), 10_000);
+}
+
+#[test]
+fn test_maximum_length_names() {
+    // 100 bytes each, in ASCII and in two- and four-byte characters
+    let names = ["x".repeat(100), "ü".repeat(50), "🌍".repeat(25), format!("{}{}", "y".repeat(96), "🌍")];
+    assert!(names.iter().all(|name| name.len() == 100));
+    let input = (0..2_000).map(|i| format!("{};-{}.{}\n", names[i % names.len()], i % 100, i % 10)).collect::<String>();
+    check(&input, || Processor::builder().batch_lines(10).threads(2));
+}